@@ -0,0 +1,461 @@
+use std::collections::HashMap;
+
+use thiserror::Error;
+use u256::U256;
+use zkevm_opcode_defs::{ImmMemHandlerFlags, OpcodeVariant, Operand, RegOrImmFlags};
+
+use crate::{
+    config::ProtocolVersion,
+    disassembler::{drops_trailing_r0, flag_names, mnemonic, predicate_suffix, slots, Slot},
+    opcode::{decoding_table, Predicate},
+    vm::EncodingMode,
+    Variant,
+};
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum AssemblerError {
+    #[error("line {0}: unknown instruction `{1}`")]
+    UnknownInstruction(usize, String),
+    #[error("line {0}: invalid modifier `{1}`")]
+    InvalidModifier(usize, String),
+    #[error("line {0}: invalid operand `{1}`")]
+    InvalidOperand(usize, String),
+    #[error("line {0}: expected {1} operands, found {2}")]
+    OperandCount(usize, usize, usize),
+    #[error("line {0}: no encoding for these operands and modifiers")]
+    UnsupportedOperands(usize),
+    #[error("line {0}: undefined label `{1}`")]
+    UndefinedLabel(usize, String),
+    #[error("line {0}: label `{1}` is already defined")]
+    DuplicateLabel(usize, String),
+    #[error("line {0}: immediate {1} does not fit in the encoding")]
+    ImmediateTooLarge(usize, u64),
+    #[error("line {0}: invalid constant `{1}`")]
+    InvalidConstant(usize, String),
+    #[error("line {0}: instructions must be in the .text section")]
+    InstructionOutsideText(usize),
+    #[error("line {0}: constants must be in the .rodata section")]
+    ConstantOutsideRodata(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Section {
+    Text,
+    Rodata,
+}
+
+// Labels resolve to a pc in the text section or to a word index in the constants section
+enum Label {
+    Code(u64),
+    Constant(usize),
+}
+
+struct Line<'a> {
+    number: usize,
+    head: &'a str,
+    operands: Vec<&'a str>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Form {
+    Register,
+    Immediate,
+    StackPop,
+    StackPush,
+    StackOffset,
+    StackAbsolute,
+    CodePage,
+}
+
+struct ParsedOperand {
+    form: Form,
+    register: u8,
+    imm: u64,
+}
+
+impl Form {
+    // Operands the instruction doesn't take fit any entry
+    fn fits_if_given(form: Option<Self>, operand: Operand, is_dst: bool) -> bool {
+        match form {
+            Some(form) => form.fits(operand, is_dst),
+            None => true,
+        }
+    }
+
+    fn fits(self, operand: Operand, is_dst: bool) -> bool {
+        match (self, operand) {
+            (
+                Form::Register,
+                Operand::RegOnly
+                | Operand::RegOrImm(RegOrImmFlags::UseRegOnly)
+                | Operand::Full(ImmMemHandlerFlags::UseRegOnly),
+            ) => true,
+            (
+                Form::Immediate,
+                Operand::RegOrImm(RegOrImmFlags::UseImm16Only)
+                | Operand::Full(ImmMemHandlerFlags::UseImm16Only),
+            ) => true,
+            (Form::StackPop, Operand::Full(ImmMemHandlerFlags::UseStackWithPushPop)) => !is_dst,
+            (Form::StackPush, Operand::Full(ImmMemHandlerFlags::UseStackWithPushPop)) => is_dst,
+            (Form::StackOffset, Operand::Full(ImmMemHandlerFlags::UseStackWithOffset)) => true,
+            (Form::StackAbsolute, Operand::Full(ImmMemHandlerFlags::UseAbsoluteOnStack)) => true,
+            (Form::CodePage, Operand::Full(ImmMemHandlerFlags::UseCodePage)) => true,
+            _ => false,
+        }
+    }
+}
+
+fn predicates() -> [Predicate; 8] {
+    [
+        Predicate::Always,
+        Predicate::Gt,
+        Predicate::Lt,
+        Predicate::Eq,
+        Predicate::Ge,
+        Predicate::Le,
+        Predicate::Ne,
+        Predicate::GtOrLt,
+    ]
+}
+
+fn find_variant(table: &[OpcodeVariant], name: &str) -> Option<Variant> {
+    table
+        .iter()
+        .map(|entry| entry.opcode)
+        .find(|variant| mnemonic(variant) == name)
+}
+
+fn is_label_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '$'))
+}
+
+fn parse_number(text: &str) -> Option<u64> {
+    match text.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+struct Assembler<'a> {
+    encoding_mode: EncodingMode,
+    table: &'static [OpcodeVariant],
+    labels: HashMap<&'a str, Label>,
+    constants_start: usize,
+}
+
+impl Assembler<'_> {
+    fn resolve(&self, line: usize, name: &str) -> Result<u64, AssemblerError> {
+        match self.labels.get(name) {
+            Some(Label::Code(pc)) => Ok(*pc),
+            Some(Label::Constant(index)) => Ok((self.constants_start + index) as u64),
+            None => Err(AssemblerError::UndefinedLabel(line, name.to_owned())),
+        }
+    }
+
+    fn imm_limit(&self) -> u64 {
+        match self.encoding_mode {
+            EncodingMode::Testing => u32::MAX as u64,
+            EncodingMode::Production => u16::MAX as u64,
+        }
+    }
+
+    fn parse_imm(&self, line: usize, text: &str) -> Result<Option<u64>, AssemblerError> {
+        let value = match text.strip_prefix('@') {
+            Some(label) => self.resolve(line, label)?,
+            None => match parse_number(text) {
+                Some(value) => value,
+                None => return Ok(None),
+            },
+        };
+        if value > self.imm_limit() {
+            return Err(AssemblerError::ImmediateTooLarge(line, value));
+        }
+        Ok(Some(value))
+    }
+
+    fn parse_operand(&self, line: usize, text: &str) -> Result<ParsedOperand, AssemblerError> {
+        let invalid = || AssemblerError::InvalidOperand(line, text.to_owned());
+        if let Some(register) = parse_register(text) {
+            return Ok(ParsedOperand {
+                form: Form::Register,
+                register,
+                imm: 0,
+            });
+        }
+        if let Some(imm) = self.parse_imm(line, text)? {
+            return Ok(ParsedOperand {
+                form: Form::Immediate,
+                register: 0,
+                imm,
+            });
+        }
+
+        let (form, address) = [
+            ("stack-=[", Form::StackPop),
+            ("stack+=[", Form::StackPush),
+            ("stack-[", Form::StackOffset),
+            ("stack[", Form::StackAbsolute),
+            ("code[", Form::CodePage),
+        ]
+        .into_iter()
+        .find_map(|(prefix, form)| {
+            text.strip_prefix(prefix)
+                .and_then(|rest| rest.strip_suffix(']'))
+                .map(|address| (form, address))
+        })
+        .ok_or_else(invalid)?;
+
+        // `rN`, `imm` or `rN+imm`
+        let (mut register, mut imm) = (None, None);
+        for part in address.split('+').map(str::trim) {
+            if let Some(index) = parse_register(part) {
+                if register.replace(index).is_some() {
+                    return Err(invalid());
+                }
+            } else if let Some(value) = self.parse_imm(line, part)? {
+                if imm.replace(value).is_some() {
+                    return Err(invalid());
+                }
+            } else {
+                return Err(invalid());
+            }
+        }
+        Ok(ParsedOperand {
+            form,
+            register: register.unwrap_or(0),
+            imm: imm.unwrap_or(0),
+        })
+    }
+
+    fn encode(&self, line: &Line) -> Result<u128, AssemblerError> {
+        let number = line.number;
+        let (head, set_flags) = match line.head.strip_suffix('!') {
+            Some(head) => (head, true),
+            None => (line.head, false),
+        };
+
+        // The longest dot separated prefix naming an instruction, the rest are modifiers
+        let parts: Vec<&str> = head.split('.').collect();
+        let (variant, modifiers) = (1..=parts.len())
+            .rev()
+            .find_map(|len| {
+                find_variant(self.table, &parts[..len].join("."))
+                    .map(|variant| (variant, &parts[len..]))
+            })
+            .ok_or_else(|| AssemblerError::UnknownInstruction(number, line.head.to_owned()))?;
+
+        let names = flag_names(&variant);
+        let mut flags = [false, false];
+        if set_flags {
+            let position = names
+                .iter()
+                .position(|name| *name == Some("!"))
+                .ok_or_else(|| AssemblerError::InvalidModifier(number, "!".to_owned()))?;
+            flags[position] = true;
+        }
+        let mut predicate = Predicate::Always;
+        for modifier in modifiers {
+            let dotted = format!(".{modifier}");
+            if let Some(position) = names.iter().position(|name| *name == Some(dotted.as_str())) {
+                flags[position] = true;
+            } else if let Some(found) = predicates()
+                .into_iter()
+                .find(|predicate| predicate_suffix(predicate) == dotted)
+            {
+                predicate = found;
+            } else {
+                return Err(AssemblerError::InvalidModifier(
+                    number,
+                    modifier.to_string(),
+                ));
+            }
+        }
+
+        let slots = slots(&variant, flags[0]);
+        let given = line.operands.len();
+        if given > slots.len() || (given < slots.len() && !drops_trailing_r0(&variant)) {
+            return Err(AssemblerError::OperandCount(number, slots.len(), given));
+        }
+
+        let (mut src0, mut src1, mut dst0, mut dst1) = (0u8, 0u8, 0u8, 0u8);
+        let (mut imm0, mut imm1) = (0u64, 0u64);
+        let (mut src0_form, mut dst0_form) = (None, None);
+        for (i, slot) in slots.iter().enumerate() {
+            let text = line.operands.get(i).copied().unwrap_or("r0");
+            let operand = self.parse_operand(number, text)?;
+            let expect = |form: Form| {
+                if operand.form == form {
+                    Ok(())
+                } else {
+                    Err(AssemblerError::InvalidOperand(number, text.to_owned()))
+                }
+            };
+            match slot {
+                Slot::Src0 => {
+                    src0 = operand.register;
+                    imm0 = operand.imm;
+                    src0_form = Some(operand.form);
+                }
+                Slot::Dst0 => {
+                    dst0 = operand.register;
+                    imm1 = operand.imm;
+                    dst0_form = Some(operand.form);
+                }
+                Slot::Src1 => {
+                    expect(Form::Register)?;
+                    src1 = operand.register;
+                }
+                Slot::Dst1 => {
+                    expect(Form::Register)?;
+                    dst1 = operand.register;
+                }
+                Slot::Label0 => {
+                    expect(Form::Immediate)?;
+                    imm0 = operand.imm;
+                }
+                Slot::Label1 => {
+                    expect(Form::Immediate)?;
+                    imm1 = operand.imm;
+                }
+            }
+        }
+
+        // The decoder moves the only flag of pointer opcodes to `flag1`
+        let table_flags = match variant {
+            Variant::Ptr(_) => [flags[1], false],
+            _ => flags,
+        };
+        let index = self
+            .table
+            .iter()
+            .position(|entry| {
+                entry.opcode == variant
+                    && entry.flags == table_flags
+                    && Form::fits_if_given(src0_form, entry.src0_operand_type, false)
+                    && Form::fits_if_given(dst0_form, entry.dst0_operand_type, true)
+            })
+            .ok_or(AssemblerError::UnsupportedOperands(number))?;
+
+        let low = index as u128
+            | (predicate as u128) << 13
+            | ((src0 | src1 << 4) as u128) << 16
+            | ((dst0 | dst1 << 4) as u128) << 24;
+        Ok(match self.encoding_mode {
+            EncodingMode::Testing => low | (imm0 as u128) << 32 | (imm1 as u128) << 64,
+            EncodingMode::Production => low | (imm0 as u128) << 32 | (imm1 as u128) << 48,
+        })
+    }
+}
+
+fn parse_register(text: &str) -> Option<u8> {
+    text.strip_prefix('r')
+        .and_then(|index| index.parse::<u8>().ok())
+        .filter(|index| *index < 16)
+}
+
+fn parse_constant(text: &str) -> Option<U256> {
+    match text.strip_prefix("0x") {
+        Some(hex) => U256::from_str_radix(hex, 16).ok(),
+        None => U256::from_dec_str(text).ok(),
+    }
+}
+
+/// Assembles a program written in the zkasm syntax printed by the disassembler.
+///
+/// Instructions go in the `.text` section, constants in the `.rodata` one as `.cell` values and
+/// are laid out right after the last instruction word. Labels can be used in place of any code
+/// address or constant index.
+///
+/// Instructions are encoded with the decoding table of `version`, those it doesn't have, e.g.
+/// transient storage before VM 1.5, are unknown.
+pub fn assemble(
+    source: &str,
+    encoding_mode: EncodingMode,
+    version: ProtocolVersion,
+) -> Result<Vec<U256>, AssemblerError> {
+    let mut labels = HashMap::new();
+    let mut lines = vec![];
+    let mut constants = vec![];
+    let mut section = Section::Text;
+
+    for (index, raw_line) in source.lines().enumerate() {
+        let number = index + 1;
+        let mut text = raw_line.split(';').next().unwrap_or_default().trim();
+
+        while let Some((name, rest)) = text.split_once(':') {
+            let name = name.trim();
+            if !is_label_name(name) {
+                break;
+            }
+            let label = match section {
+                Section::Text => Label::Code(lines.len() as u64),
+                Section::Rodata => Label::Constant(constants.len()),
+            };
+            if labels.insert(name, label).is_some() {
+                return Err(AssemblerError::DuplicateLabel(number, name.to_owned()));
+            }
+            text = rest.trim();
+        }
+        if text.is_empty() {
+            continue;
+        }
+
+        let (head, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+        let rest = rest.trim();
+        match head {
+            ".text" => section = Section::Text,
+            ".rodata" => section = Section::Rodata,
+            ".cell" => {
+                if section != Section::Rodata {
+                    return Err(AssemblerError::ConstantOutsideRodata(number));
+                }
+                let constant = parse_constant(rest)
+                    .ok_or_else(|| AssemblerError::InvalidConstant(number, rest.to_owned()))?;
+                constants.push(constant);
+            }
+            // Other directives, e.g. `.globl` or `.p2align`, don't affect the code page
+            _ if head.starts_with('.') => {}
+            _ => {
+                if section != Section::Text {
+                    return Err(AssemblerError::InstructionOutsideText(number));
+                }
+                let operands = if rest.is_empty() {
+                    vec![]
+                } else {
+                    rest.split(',').map(str::trim).collect()
+                };
+                lines.push(Line {
+                    number,
+                    head,
+                    operands,
+                });
+            }
+        }
+    }
+
+    let per_word = match encoding_mode {
+        EncodingMode::Testing => 2,
+        EncodingMode::Production => 4,
+    };
+    let assembler = Assembler {
+        encoding_mode,
+        table: decoding_table(version),
+        labels,
+        constants_start: lines.len().div_ceil(per_word),
+    };
+
+    let mut code = Vec::with_capacity(assembler.constants_start + constants.len());
+    for chunk in lines.chunks(per_word) {
+        let mut word = U256::zero();
+        for (i, line) in chunk.iter().enumerate() {
+            let raw = U256::from(assembler.encode(line)?);
+            word |= raw << ((per_word - 1 - i) * (256 / per_word));
+        }
+        code.push(word);
+    }
+    code.extend(constants);
+    Ok(code)
+}
@@ -1,8 +1,11 @@
-use std::num::Saturating;
+use std::{num::Saturating, sync::Arc};
 use u256::U256;
 use zkevm_opcode_defs::ethereum_types::Address;
 
-use crate::{execution::Stack, state::StateSnapshot, utils::is_kernel};
+use crate::{
+    config::ProtocolVersion, dispatch::Instruction, execution::Stack, state::StateSnapshot,
+    utils::is_kernel, vm::EncodingMode, Opcode,
+};
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct CallFrame {
@@ -14,15 +17,101 @@ pub struct CallFrame {
     pub stipend: Saturating<u32>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
-pub struct CodePage(Vec<U256>);
+// Cloning a code page shares the code instead of copying it, frames running the same code and
+// snapshots of them all point to the same words and decoded instructions.
+#[derive(Debug, Clone)]
+pub struct CodePage {
+    words: Arc<[U256]>,
+    /// Instructions decoded ahead of time along with the encoding of the code,
+    /// `None` where decoding fails
+    instructions: Option<(EncodingMode, Arc<[Option<Instruction>]>)>,
+}
+
+impl PartialEq for CodePage {
+    fn eq(&self, other: &Self) -> bool {
+        self.words == other.words
+    }
+}
 
 impl CodePage {
+    pub fn new(words: impl Into<Arc<[U256]>>) -> Self {
+        Self {
+            words: words.into(),
+            instructions: None,
+        }
+    }
+
+    /// Decodes every instruction of production encoded code up front, see `instruction`
+    pub fn decode(self, version: ProtocolVersion) -> Self {
+        self.decode_with(EncodingMode::Production, version)
+    }
+
+    /// Decodes every instruction of the code up front, see `instruction`
+    pub fn decode_with(self, encoding_mode: EncodingMode, version: ProtocolVersion) -> Self {
+        let words = self.words;
+        let instructions = match encoding_mode {
+            EncodingMode::Production => words
+                .iter()
+                .flat_map(|word| {
+                    (0..4)
+                        .rev()
+                        .map(move |i| ((*word >> (64 * i)) & u64::MAX.into()).as_u64())
+                })
+                .map(|raw| {
+                    Opcode::try_from_raw_opcode(raw, version)
+                        .ok()
+                        .map(Instruction::new)
+                })
+                .collect(),
+            EncodingMode::Testing => words
+                .iter()
+                .flat_map(|word| [(*word >> 128).low_u128(), word.low_u128()])
+                .map(|raw| {
+                    Opcode::try_from_raw_opcode_test_encode(raw, version)
+                        .ok()
+                        .map(Instruction::new)
+                })
+                .collect(),
+        };
+        Self {
+            words,
+            instructions: Some((encoding_mode, instructions)),
+        }
+    }
+
+    pub fn is_decoded_with(&self, encoding_mode: EncodingMode) -> bool {
+        matches!(&self.instructions, Some((mode, _)) if *mode == encoding_mode)
+    }
+
     pub fn get(&self, idx: usize) -> U256 {
         // NOTE: the spec mandates reads past the end of the program return any value that decodes
         // as an `invalid` instruction. 0u256 fits the bill because its decoded variant is 0 which
         // in turn is **the** invalid opcode.
-        self.0.get(idx).cloned().unwrap_or_else(U256::zero)
+        self.words.get(idx).cloned().unwrap_or_else(U256::zero)
+    }
+
+    /// The pre-decoded instruction at `pc`. `None` if the page wasn't decoded up front with
+    /// `encoding_mode`, or `pc` is past the end or doesn't decode.
+    pub fn instruction(&self, encoding_mode: EncodingMode, pc: u64) -> Option<&Instruction> {
+        match &self.instructions {
+            Some((mode, instructions)) if *mode == encoding_mode => {
+                instructions.get(pc as usize)?.as_ref()
+            }
+            _ => None,
+        }
+    }
+
+    pub fn words(&self) -> &[U256] {
+        &self.words
+    }
+
+    /// Length in words
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
     }
 }
 
@@ -38,11 +127,14 @@ pub struct Context {
     pub code_address: Address,
     /// Stands for the amount of wei sent in a transaction
     pub context_u128: u128,
-    // Max length for this is 1 << 16. Might want to enforce that at some point
+    // Max length for this is `MAX_STACK_SIZE`
     pub stack: Stack,
     pub heap_id: u32,
     pub aux_heap_id: u32,
     pub calldata_heap_id: u32,
+    /// Heaps of returned callees that the data they returned lives in.
+    /// Freed once nothing in this frame points into them anymore.
+    pub returned_heaps: Vec<u32>,
     // Code memory is word addressable even though instructions are 64 bit wide.
     pub code_page: CodePage,
     pub is_static: bool,
@@ -54,7 +146,7 @@ pub struct Context {
 impl Context {
     #[allow(clippy::too_many_arguments)]
     pub fn new(
-        program_code: Vec<U256>,
+        code_page: CodePage,
         gas: u32,
         contract_address: Address,
         code_address: Address,
@@ -79,7 +171,8 @@ impl Context {
             heap_id,
             aux_heap_id,
             calldata_heap_id,
-            code_page: CodePage(program_code),
+            returned_heaps: vec![],
+            code_page,
             is_static,
         }
     }
@@ -0,0 +1,127 @@
+use zkevm_opcode_defs::{
+    system_params::{
+        EVM_SIMULATOR_STIPEND, NEW_FRAME_MEMORY_STIPEND, STORAGE_ACCESS_COLD_READ_COST,
+        STORAGE_ACCESS_COLD_WRITE_COST, STORAGE_ACCESS_WARM_READ_COST,
+        STORAGE_ACCESS_WARM_WRITE_COST,
+    },
+    ISAVersion, MEMORY_GROWTH_ERGS_PER_BYTE,
+};
+
+use crate::eravm_error::ConfigError;
+
+/// VM releases whose instruction set or semantics differ.
+/// Historical batches must be re-executed with the version they were sealed with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum ProtocolVersion {
+    /// VM 1.4, without transient storage, static memory or the decommit opcode
+    Vm1_4,
+    /// VM 1.5, calls into EVM bytecode are not supported
+    Vm1_5,
+    /// VM 1.5 with EVM bytecode executed by the EVM interpreter
+    #[default]
+    Vm1_5EvmInterpreter,
+}
+
+impl ProtocolVersion {
+    pub fn isa_version(&self) -> ISAVersion {
+        match self {
+            ProtocolVersion::Vm1_4 => ISAVersion(1),
+            ProtocolVersion::Vm1_5 | ProtocolVersion::Vm1_5EvmInterpreter => ISAVersion(2),
+        }
+    }
+
+    /// Whether code hashes with the blob version flag are run through the EVM interpreter
+    pub fn supports_evm_bytecode(&self) -> bool {
+        *self >= ProtocolVersion::Vm1_5EvmInterpreter
+    }
+
+    pub fn supports_transient_storage(&self) -> bool {
+        *self >= ProtocolVersion::Vm1_5
+    }
+}
+
+/// Pricing and limit parameters of the VM.
+/// The default matches mainnet, other values can be used to model protocol upgrades.
+/// The vm only runs configs that pass `VmConfig::validate`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VmConfig {
+    /// Picks the decoding table and the version specific semantics
+    pub protocol_version: ProtocolVersion,
+    /// Ergs paid for each byte a heap grows by
+    pub memory_growth_ergs_per_byte: u32,
+    /// Bytes of heap and aux heap a new frame can use without paying for growth
+    pub new_frame_memory_stipend: u32,
+    /// A far call passes at most `far_call_gas_dividend / far_call_gas_divisor` of the ergs left
+    pub far_call_gas_dividend: u32,
+    pub far_call_gas_divisor: u32,
+    /// Extra ergs given to calls into the EVM interpreter
+    pub evm_simulator_stipend: u32,
+    pub storage_cold_read_cost: u32,
+    pub storage_cold_write_cost: u32,
+    pub storage_warm_read_cost: u32,
+    pub storage_warm_write_cost: u32,
+    /// Far call frames that can be running at once, the initial frame included
+    pub max_far_call_depth: u32,
+    /// Near call frames each far call frame can have
+    pub max_near_call_depth: u32,
+}
+
+impl Default for VmConfig {
+    fn default() -> Self {
+        Self {
+            protocol_version: ProtocolVersion::default(),
+            memory_growth_ergs_per_byte: MEMORY_GROWTH_ERGS_PER_BYTE,
+            new_frame_memory_stipend: NEW_FRAME_MEMORY_STIPEND,
+            far_call_gas_dividend: 63,
+            far_call_gas_divisor: 64,
+            evm_simulator_stipend: EVM_SIMULATOR_STIPEND,
+            storage_cold_read_cost: STORAGE_ACCESS_COLD_READ_COST,
+            storage_cold_write_cost: STORAGE_ACCESS_COLD_WRITE_COST,
+            storage_warm_read_cost: STORAGE_ACCESS_WARM_READ_COST,
+            storage_warm_write_cost: STORAGE_ACCESS_WARM_WRITE_COST,
+            max_far_call_depth: 1024,
+            max_near_call_depth: 1 << 16,
+        }
+    }
+}
+
+impl VmConfig {
+    /// Checks that refunds can't exceed what was charged and that far calls can't pass on more
+    /// ergs than are left.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.storage_warm_read_cost > self.storage_cold_read_cost {
+            return Err(ConfigError::WarmReadAboveCold);
+        }
+        if self.storage_warm_write_cost > self.storage_cold_write_cost {
+            return Err(ConfigError::WarmWriteAboveCold);
+        }
+        if self.storage_cold_read_cost > self.storage_cold_write_cost {
+            return Err(ConfigError::ColdReadAboveColdWrite);
+        }
+        if self.far_call_gas_divisor == 0 {
+            return Err(ConfigError::ZeroFarCallGasDivisor);
+        }
+        if self.far_call_gas_dividend > self.far_call_gas_divisor {
+            return Err(ConfigError::FarCallGasDividendAboveDivisor);
+        }
+        Ok(())
+    }
+
+    // Storage accesses are charged as cold and refunded when they turn out to be warm
+    pub(crate) fn warm_read_refund(&self) -> u32 {
+        self.storage_cold_read_cost - self.storage_warm_read_cost
+    }
+
+    pub(crate) fn warm_write_refund(&self) -> u32 {
+        self.storage_cold_write_cost - self.storage_warm_write_cost
+    }
+
+    pub(crate) fn cold_write_after_warm_read_refund(&self) -> u32 {
+        self.storage_cold_read_cost
+    }
+
+    /// The most ergs a far call can pass on when `gas_left` ergs are left
+    pub(crate) fn max_far_call_gas(&self, gas_left: u32) -> u32 {
+        gas_left / self.far_call_gas_divisor * self.far_call_gas_dividend
+    }
+}
@@ -0,0 +1,229 @@
+use u256::H160;
+use zkevm_opcode_defs::LogOpcode;
+
+use crate::{
+    eravm_error::EraVmError,
+    heaps::Heaps,
+    state::VMState,
+    store::{Storage, StorageKey},
+    tracers::no_tracer::NoTracer,
+    value::TaggedValue,
+    vm::{EncodingMode, ExecutionOutput},
+    EraVM, Opcode, Variant,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Breakpoint {
+    /// Stops before executing the instruction at `pc` of the code loaded from `code_address`
+    Location { code_address: H160, pc: u64 },
+    /// Stops before executing any instruction with the given variant
+    Opcode(Variant),
+    /// Stops before reading or writing the given key, either in storage or transient storage
+    StorageAccess(StorageKey),
+    /// Stops before emitting an event
+    Event,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DebugStop {
+    /// A single instruction was executed
+    Step,
+    /// The breakpoint at the given index is about to be hit
+    Breakpoint(usize),
+    /// The execution finished or got suspended on a hook
+    Output(ExecutionOutput),
+}
+
+/// Drives an `EraVM` one instruction at a time, giving read access to its state in between.
+pub struct Debugger {
+    vm: EraVM,
+    encoding_mode: EncodingMode,
+    pub breakpoints: Vec<Breakpoint>,
+    // Set once the program returned from its initial frame, stepping further does nothing
+    output: Option<ExecutionOutput>,
+}
+
+impl Debugger {
+    pub fn new(mut vm: EraVM, encoding_mode: EncodingMode) -> Self {
+        vm.decode_code(encoding_mode);
+        Self {
+            vm,
+            encoding_mode,
+            breakpoints: vec![],
+            output: None,
+        }
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> usize {
+        self.breakpoints.push(breakpoint);
+        self.breakpoints.len() - 1
+    }
+
+    pub fn vm(&self) -> &EraVM {
+        &self.vm
+    }
+
+    /// Mutable access to the vm, e.g. to set the pc to resume from after a hook.
+    pub fn vm_mut(&mut self) -> &mut EraVM {
+        &mut self.vm
+    }
+
+    pub fn into_vm(self) -> EraVM {
+        self.vm
+    }
+
+    pub fn output(&self) -> Option<&ExecutionOutput> {
+        self.output.as_ref()
+    }
+
+    pub fn register(&self, index: u8) -> TaggedValue {
+        self.vm.execution.get_register(index)
+    }
+
+    /// The stack of the current context up to the stack pointer of the current frame.
+    /// Slots past the end were never written and read as zero.
+    pub fn stack(&self) -> &[TaggedValue] {
+        let execution = &self.vm.execution;
+        let (Ok(context), Ok(frame)) = (execution.current_context(), execution.current_frame())
+        else {
+            return &[];
+        };
+        let len = context.stack.stack.len().min(frame.sp as usize);
+        &context.stack.stack[..len]
+    }
+
+    pub fn heaps(&self) -> &Heaps {
+        &self.vm.execution.heaps
+    }
+
+    pub fn state(&self) -> &VMState {
+        &self.vm.state
+    }
+
+    /// The instruction that will be executed by the next step, if any.
+    pub fn next_opcode(&self) -> Option<Opcode> {
+        if self.output.is_some() {
+            return None;
+        }
+        let opcode = match self.encoding_mode {
+            EncodingMode::Testing => self.vm.execution.get_opcode_with_test_encode(),
+            EncodingMode::Production => self.vm.execution.get_opcode(),
+        };
+        opcode.ok()
+    }
+
+    /// Executes a single instruction.
+    pub fn step(&mut self, storage: &mut dyn Storage) -> Result<DebugStop, EraVmError> {
+        if let Some(output) = &self.output {
+            return Ok(DebugStop::Output(output.clone()));
+        }
+        match self
+            .vm
+            .run_step(&mut NoTracer::default(), self.encoding_mode, storage)?
+        {
+            Some(output @ ExecutionOutput::SuspendedOnHook { .. }) => Ok(DebugStop::Output(output)),
+            Some(output) => {
+                self.output = Some(output.clone());
+                Ok(DebugStop::Output(output))
+            }
+            None => Ok(DebugStop::Step),
+        }
+    }
+
+    /// Executes a single instruction, running near and far calls to completion.
+    /// Stops early if a breakpoint is hit inside the call.
+    pub fn step_over(&mut self, storage: &mut dyn Storage) -> Result<DebugStop, EraVmError> {
+        let is_call = self.next_opcode().is_some_and(|opcode| {
+            matches!(opcode.variant, Variant::NearCall(_) | Variant::FarCall(_))
+                && matches!(self.vm.execution.can_execute(&opcode), Ok(true))
+        });
+        let depth = self.vm.execution.frame_depth();
+        let stop = self.step(storage)?;
+        if !is_call {
+            return Ok(stop);
+        }
+        self.run_while(storage, |vm| vm.execution.frame_depth() > depth)
+    }
+
+    /// Executes until a breakpoint is about to be hit or the execution stops.
+    /// The instruction the debugger is currently stopped at is always executed.
+    pub fn continue_until(&mut self, storage: &mut dyn Storage) -> Result<DebugStop, EraVmError> {
+        match self.step(storage)? {
+            DebugStop::Step => self.run_while(storage, |_| true),
+            stop => Ok(stop),
+        }
+    }
+
+    fn run_while(
+        &mut self,
+        storage: &mut dyn Storage,
+        should_continue: impl Fn(&EraVM) -> bool,
+    ) -> Result<DebugStop, EraVmError> {
+        while should_continue(&self.vm) {
+            if let Some(index) = self.hit_breakpoint() {
+                return Ok(DebugStop::Breakpoint(index));
+            }
+            match self.step(storage)? {
+                DebugStop::Step => {}
+                stop => return Ok(stop),
+            }
+        }
+        Ok(DebugStop::Step)
+    }
+
+    fn hit_breakpoint(&self) -> Option<usize> {
+        let opcode = self.next_opcode()?;
+        let execution = &self.vm.execution;
+        let context = execution.current_context().ok()?;
+        let pc = execution.current_frame().ok()?.pc;
+        let will_execute = matches!(execution.can_execute(&opcode), Ok(true));
+
+        self.breakpoints
+            .iter()
+            .position(|breakpoint| match breakpoint {
+                Breakpoint::Location {
+                    code_address,
+                    pc: at,
+                } => *code_address == context.code_address && *at == pc,
+                Breakpoint::Opcode(variant) => will_execute && *variant == opcode.variant,
+                Breakpoint::StorageAccess(key) => {
+                    will_execute
+                        && matches!(
+                            opcode.variant,
+                            Variant::Log(
+                                LogOpcode::StorageRead
+                                    | LogOpcode::StorageWrite
+                                    | LogOpcode::TransientStorageRead
+                                    | LogOpcode::TransientStorageWrite
+                            )
+                        )
+                        && *key
+                            == StorageKey::new(
+                                context.contract_address,
+                                execution.get_register(opcode.src0_index).value,
+                            )
+                }
+                Breakpoint::Event => {
+                    will_execute && matches!(opcode.variant, Variant::Log(LogOpcode::Event))
+                }
+            })
+    }
+}
+
+impl From<(H160, u64)> for Breakpoint {
+    fn from((code_address, pc): (H160, u64)) -> Self {
+        Breakpoint::Location { code_address, pc }
+    }
+}
+
+impl From<Variant> for Breakpoint {
+    fn from(variant: Variant) -> Self {
+        Breakpoint::Opcode(variant)
+    }
+}
+
+impl From<StorageKey> for Breakpoint {
+    fn from(key: StorageKey) -> Self {
+        Breakpoint::StorageAccess(key)
+    }
+}
@@ -0,0 +1,517 @@
+use std::collections::BTreeSet;
+use std::fmt;
+
+use u256::U256;
+use zkevm_opcode_defs::{
+    BinopOpcode, ContextOpcode, FarCallOpcode, ImmMemHandlerFlags, LogOpcode, Operand, PtrOpcode,
+    RegOrImmFlags, RetOpcode, ShiftOpcode, UMAOpcode,
+};
+
+use crate::{config::ProtocolVersion, opcode::Predicate, vm::EncodingMode, Opcode, Variant};
+
+/// An operand position in the assembly syntax of an instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Slot {
+    Src0,
+    Src1,
+    Dst0,
+    Dst1,
+    /// A code address taken from `imm0`
+    Label0,
+    /// A code address taken from `imm1`
+    Label1,
+}
+
+pub(crate) fn mnemonic(variant: &Variant) -> &'static str {
+    match variant {
+        Variant::Invalid(_) => "invalid",
+        Variant::Nop(_) => "nop",
+        Variant::Add(_) => "add",
+        Variant::Sub(_) => "sub",
+        Variant::Mul(_) => "mul",
+        Variant::Div(_) => "div",
+        Variant::Jump(_) => "jump",
+        Variant::Context(context) => match context {
+            ContextOpcode::This => "context.this",
+            ContextOpcode::Caller => "context.caller",
+            ContextOpcode::CodeAddress => "context.code_source",
+            ContextOpcode::Meta => "context.meta",
+            ContextOpcode::ErgsLeft => "context.ergs_left",
+            ContextOpcode::Sp => "context.sp",
+            ContextOpcode::GetContextU128 => "context.get_context_u128",
+            ContextOpcode::SetContextU128 => "context.set_context_u128",
+            ContextOpcode::AuxMutating0 => "context.aux_mutating0",
+            ContextOpcode::IncrementTxNumber => "context.inc_tx_num",
+        },
+        Variant::Shift(shift) => match shift {
+            ShiftOpcode::Shl => "shl",
+            ShiftOpcode::Shr => "shr",
+            ShiftOpcode::Rol => "rol",
+            ShiftOpcode::Ror => "ror",
+        },
+        Variant::Binop(binop) => match binop {
+            BinopOpcode::Xor => "xor",
+            BinopOpcode::And => "and",
+            BinopOpcode::Or => "or",
+        },
+        Variant::Ptr(ptr) => match ptr {
+            PtrOpcode::Add => "ptr.add",
+            PtrOpcode::Sub => "ptr.sub",
+            PtrOpcode::Pack => "ptr.pack",
+            PtrOpcode::Shrink => "ptr.shrink",
+        },
+        Variant::NearCall(_) => "near_call",
+        Variant::Log(log) => match log {
+            LogOpcode::StorageRead => "sload",
+            LogOpcode::StorageWrite => "sstore",
+            LogOpcode::ToL1Message => "to_l1",
+            LogOpcode::Event => "event",
+            LogOpcode::PrecompileCall => "precompile",
+            LogOpcode::Decommit => "decommit",
+            LogOpcode::TransientStorageRead => "tload",
+            LogOpcode::TransientStorageWrite => "tstore",
+        },
+        Variant::FarCall(far_call) => match far_call {
+            FarCallOpcode::Normal => "far_call",
+            FarCallOpcode::Delegate => "far_call.delegate",
+            FarCallOpcode::Mimic => "far_call.mimic",
+        },
+        Variant::Ret(ret) => match ret {
+            RetOpcode::Ok => "ret.ok",
+            RetOpcode::Revert => "ret.revert",
+            RetOpcode::Panic => "ret.panic",
+        },
+        Variant::UMA(uma) => match uma {
+            UMAOpcode::HeapRead => "ld.1",
+            UMAOpcode::HeapWrite => "st.1",
+            UMAOpcode::AuxHeapRead => "ld.2",
+            UMAOpcode::AuxHeapWrite => "st.2",
+            UMAOpcode::FatPointerRead => "ld",
+            UMAOpcode::StaticMemoryRead => "ld.static",
+            UMAOpcode::StaticMemoryWrite => "st.static",
+        },
+    }
+}
+
+/// The names of the `flag0` and `flag1` modifiers of a variant, `!` stands for "set flags".
+pub(crate) fn flag_names(variant: &Variant) -> [Option<&'static str>; 2] {
+    match variant {
+        Variant::Add(_)
+        | Variant::Sub(_)
+        | Variant::Mul(_)
+        | Variant::Div(_)
+        | Variant::Shift(_)
+        | Variant::Binop(_) => [Some("!"), Some(".s")],
+        Variant::Ptr(_) => [None, Some(".s")],
+        Variant::Log(_) => [Some(".first"), None],
+        Variant::FarCall(_) => [Some(".static"), None],
+        Variant::Ret(_) => [Some(".to_label"), None],
+        Variant::UMA(_) => [Some(".inc"), None],
+        _ => [None, None],
+    }
+}
+
+pub(crate) fn predicate_suffix(predicate: &Predicate) -> &'static str {
+    match predicate {
+        Predicate::Always => "",
+        Predicate::Gt => ".gt",
+        Predicate::Lt => ".lt",
+        Predicate::Eq => ".eq",
+        Predicate::Ge => ".ge",
+        Predicate::Le => ".le",
+        Predicate::Ne => ".ne",
+        Predicate::GtOrLt => ".gtlt",
+    }
+}
+
+/// The operands an instruction is written with, depending on its variant and flags.
+pub(crate) fn slots(variant: &Variant, flag0_set: bool) -> Vec<Slot> {
+    use Slot::*;
+    match variant {
+        Variant::Invalid(_) => vec![],
+        Variant::Nop(_) | Variant::Jump(_) => vec![Src0, Dst0],
+        Variant::Add(_)
+        | Variant::Sub(_)
+        | Variant::Shift(_)
+        | Variant::Binop(_)
+        | Variant::Ptr(_) => vec![Src0, Src1, Dst0],
+        Variant::Mul(_) | Variant::Div(_) => vec![Src0, Src1, Dst0, Dst1],
+        Variant::Context(context) => match context {
+            ContextOpcode::SetContextU128 => vec![Src0],
+            ContextOpcode::AuxMutating0 | ContextOpcode::IncrementTxNumber => vec![],
+            _ => vec![Dst0],
+        },
+        Variant::NearCall(_) => vec![Src0, Label0, Label1],
+        Variant::Log(log) => match log {
+            LogOpcode::StorageRead | LogOpcode::TransientStorageRead => vec![Src0, Dst0],
+            LogOpcode::PrecompileCall | LogOpcode::Decommit => vec![Src0, Src1, Dst0],
+            _ => vec![Src0, Src1],
+        },
+        Variant::FarCall(_) => vec![Src0, Src1, Label0],
+        Variant::Ret(ret) => {
+            let mut slots = match ret {
+                RetOpcode::Panic => vec![],
+                _ => vec![Src0],
+            };
+            if flag0_set {
+                slots.push(Label0);
+            }
+            slots
+        }
+        Variant::UMA(uma) => {
+            let is_write = matches!(
+                uma,
+                UMAOpcode::HeapWrite | UMAOpcode::AuxHeapWrite | UMAOpcode::StaticMemoryWrite
+            );
+            match (is_write, flag0_set) {
+                (false, false) => vec![Src0, Dst0],
+                (false, true) => vec![Src0, Dst0, Dst1],
+                (true, false) => vec![Src0, Src1],
+                (true, true) => vec![Src0, Src1, Dst0],
+            }
+        }
+    }
+}
+
+/// Whether the instruction can be written without its operands, i.e. `nop` or `jump @label`.
+pub(crate) fn drops_trailing_r0(variant: &Variant) -> bool {
+    matches!(variant, Variant::Nop(_) | Variant::Jump(_))
+}
+
+/// How code addresses and constants are named while rendering an instruction.
+trait Symbols {
+    fn code_label(&self, pc: u32) -> String {
+        pc.to_string()
+    }
+    fn constant_label(&self, _word: u32) -> Option<String> {
+        None
+    }
+}
+
+struct NoSymbols;
+
+impl Symbols for NoSymbols {}
+
+fn address(register: u8, offset: u32) -> String {
+    match (register, offset) {
+        (0, offset) => offset.to_string(),
+        (register, 0) => format!("r{register}"),
+        (register, offset) => format!("r{register}+{offset}"),
+    }
+}
+
+fn render_src0(opcode: &Opcode, symbols: &dyn Symbols) -> String {
+    let register = opcode.src0_index;
+    let imm = opcode.imm0;
+    match opcode.src0_operand_type {
+        Operand::RegOnly
+        | Operand::RegOrImm(RegOrImmFlags::UseRegOnly)
+        | Operand::Full(ImmMemHandlerFlags::UseRegOnly) => format!("r{register}"),
+        Operand::RegOrImm(RegOrImmFlags::UseImm16Only)
+        | Operand::Full(ImmMemHandlerFlags::UseImm16Only) => match opcode.variant {
+            Variant::Jump(_) => format!("@{}", symbols.code_label(imm)),
+            _ => imm.to_string(),
+        },
+        Operand::Full(ImmMemHandlerFlags::UseStackWithPushPop) => {
+            format!("stack-=[{}]", address(register, imm))
+        }
+        Operand::Full(ImmMemHandlerFlags::UseStackWithOffset) => {
+            format!("stack-[{}]", address(register, imm))
+        }
+        Operand::Full(ImmMemHandlerFlags::UseAbsoluteOnStack) => {
+            format!("stack[{}]", address(register, imm))
+        }
+        Operand::Full(ImmMemHandlerFlags::UseCodePage) => {
+            match (register, symbols.constant_label(imm)) {
+                (0, Some(label)) => format!("code[@{label}]"),
+                _ => format!("code[{}]", address(register, imm)),
+            }
+        }
+    }
+}
+
+fn render_dst0(opcode: &Opcode) -> String {
+    let register = opcode.dst0_index;
+    let imm = opcode.imm1;
+    match opcode.dst0_operand_type {
+        Operand::Full(ImmMemHandlerFlags::UseStackWithPushPop) => {
+            format!("stack+=[{}]", address(register, imm))
+        }
+        Operand::Full(ImmMemHandlerFlags::UseStackWithOffset) => {
+            format!("stack-[{}]", address(register, imm))
+        }
+        Operand::Full(ImmMemHandlerFlags::UseAbsoluteOnStack) => {
+            format!("stack[{}]", address(register, imm))
+        }
+        // Immediates and the code page can't be written to, the decoding table never
+        // produces them as destinations
+        _ => format!("r{register}"),
+    }
+}
+
+fn render(opcode: &Opcode, symbols: &dyn Symbols) -> String {
+    let variant = &opcode.variant;
+    let [flag0, flag1] = flag_names(variant);
+    let mut text = mnemonic(variant).to_owned();
+    if let (Some(name), true) = (flag1, opcode.flag1_set) {
+        text.push_str(name);
+    }
+    if let (Some(name), true) = (flag0, opcode.flag0_set) {
+        if name != "!" {
+            text.push_str(name);
+        }
+    }
+    text.push_str(predicate_suffix(&opcode.predicate));
+    if let (Some("!"), true) = (flag0, opcode.flag0_set) {
+        text.push('!');
+    }
+
+    let mut operands: Vec<String> = slots(variant, opcode.flag0_set)
+        .into_iter()
+        .map(|slot| match slot {
+            Slot::Src0 => render_src0(opcode, symbols),
+            Slot::Src1 => format!("r{}", opcode.src1_index),
+            Slot::Dst0 => render_dst0(opcode),
+            Slot::Dst1 => format!("r{}", opcode.dst1_index),
+            Slot::Label0 => format!("@{}", symbols.code_label(opcode.imm0)),
+            Slot::Label1 => format!("@{}", symbols.code_label(opcode.imm1)),
+        })
+        .collect();
+    if drops_trailing_r0(variant) {
+        while operands.last().is_some_and(|operand| *operand == "r0") {
+            operands.pop();
+        }
+    }
+    if !operands.is_empty() {
+        text.push(' ');
+        text.push_str(&operands.join(", "));
+    }
+    text
+}
+
+/// Renders the instruction on its own, code addresses are written as plain numbers.
+impl fmt::Display for Opcode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&render(self, &NoSymbols))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DisassembledInstruction {
+    pub pc: u64,
+    /// The encoded instruction, only the low 64 bits are used by the production encoding
+    pub raw: u128,
+    /// `None` if the instruction couldn't be decoded
+    pub opcode: Option<Opcode>,
+}
+
+/// A code page split into its instructions and the constants that follow them.
+#[derive(Debug, Clone)]
+pub struct Disassembly {
+    pub instructions: Vec<DisassembledInstruction>,
+    /// Index of the first word of the constants section
+    pub constants_start: usize,
+    pub constants: Vec<U256>,
+    /// Code addresses targeted by jumps, calls and exception handlers
+    pub labels: BTreeSet<u64>,
+}
+
+impl Symbols for Disassembly {
+    fn code_label(&self, pc: u32) -> String {
+        if self.labels.contains(&(pc as u64)) {
+            format!(".L{pc}")
+        } else {
+            pc.to_string()
+        }
+    }
+
+    fn constant_label(&self, word: u32) -> Option<String> {
+        let word = word as usize;
+        (word >= self.constants_start && word < self.constants_start + self.constants.len())
+            .then(|| format!("CPI{word}"))
+    }
+}
+
+fn instructions_per_word(encoding_mode: EncodingMode) -> usize {
+    match encoding_mode {
+        EncodingMode::Testing => 2,
+        EncodingMode::Production => 4,
+    }
+}
+
+fn decode_word(
+    word: U256,
+    encoding_mode: EncodingMode,
+    version: ProtocolVersion,
+) -> Vec<(u128, Option<Opcode>)> {
+    match encoding_mode {
+        EncodingMode::Testing => [(word >> 128).low_u128(), word.low_u128()]
+            .into_iter()
+            .map(|raw| {
+                (
+                    raw,
+                    Opcode::try_from_raw_opcode_test_encode(raw, version).ok(),
+                )
+            })
+            .collect(),
+        EncodingMode::Production => (0..4)
+            .rev()
+            .map(|i| {
+                let raw = ((word >> (64 * i)) & u64::MAX.into()).as_u64();
+                (raw as u128, Opcode::try_from_raw_opcode(raw, version).ok())
+            })
+            .collect(),
+    }
+}
+
+// Code addresses an instruction may transfer control to
+fn jump_targets(opcode: &Opcode) -> Vec<u64> {
+    let mut targets: Vec<u64> = slots(&opcode.variant, opcode.flag0_set)
+        .into_iter()
+        .filter_map(|slot| match slot {
+            Slot::Label0 => Some(opcode.imm0 as u64),
+            Slot::Label1 => Some(opcode.imm1 as u64),
+            _ => None,
+        })
+        .collect();
+    if let (
+        Variant::Jump(_),
+        Operand::Full(ImmMemHandlerFlags::UseImm16Only)
+        | Operand::RegOrImm(RegOrImmFlags::UseImm16Only),
+    ) = (opcode.variant, opcode.src0_operand_type)
+    {
+        targets.push(opcode.imm0 as u64);
+    }
+    targets
+}
+
+// Where the instructions reachable from the entry point end, `None` if a jump takes its target
+// from a register or memory, so that what it reaches can't be known
+fn reachable_end(instructions: &[DisassembledInstruction]) -> Option<usize> {
+    let mut reached = vec![false; instructions.len()];
+    let mut pending = vec![0];
+    let mut end = 0;
+    while let Some(pc) = pending.pop() {
+        if pc >= instructions.len() || reached[pc] {
+            continue;
+        }
+        reached[pc] = true;
+        end = end.max(pc + 1);
+        let Some(opcode) = &instructions[pc].opcode else {
+            continue;
+        };
+        if matches!(opcode.variant, Variant::Jump(_)) && jump_targets(opcode).is_empty() {
+            return None;
+        }
+        pending.extend(jump_targets(opcode).into_iter().map(|pc| pc as usize));
+        let ends_flow = matches!(opcode.predicate, Predicate::Always)
+            && matches!(
+                opcode.variant,
+                Variant::Jump(_) | Variant::Ret(_) | Variant::Invalid(_)
+            );
+        if !ends_flow {
+            pending.push(pc + 1);
+        }
+    }
+    Some(end)
+}
+
+/// Decodes a code page with the instruction set of `version`, like `CodePage::decode` does.
+/// The constants section starts at the first word that can't hold instructions: one read through
+/// `code[N]` by an instruction before it, one with an instruction that doesn't decode, or one past
+/// every instruction reachable from the entry point when no jump goes through a register.
+pub fn disassemble(
+    code: &[U256],
+    encoding_mode: EncodingMode,
+    version: ProtocolVersion,
+) -> Disassembly {
+    let per_word = instructions_per_word(encoding_mode);
+    let mut instructions = vec![];
+    for (word_index, word) in code.iter().enumerate() {
+        for (i, (raw, opcode)) in decode_word(*word, encoding_mode, version)
+            .into_iter()
+            .enumerate()
+        {
+            instructions.push(DisassembledInstruction {
+                pc: (word_index * per_word + i) as u64,
+                raw,
+                opcode,
+            });
+        }
+    }
+
+    let mut constants_start = code.len();
+    for instruction in &instructions {
+        let word_index = instruction.pc as usize / per_word;
+        if word_index >= constants_start {
+            break;
+        }
+        if let Some(Opcode {
+            src0_operand_type: Operand::Full(ImmMemHandlerFlags::UseCodePage),
+            src0_index: 0,
+            imm0,
+            ..
+        }) = instruction.opcode
+        {
+            let word = imm0 as usize;
+            if word > word_index && word < constants_start {
+                constants_start = word;
+            }
+        }
+    }
+    if let Some(pc) = instructions
+        .iter()
+        .position(|instruction| instruction.opcode.is_none())
+    {
+        constants_start = constants_start.min(pc / per_word);
+    }
+    if let Some(end) = reachable_end(&instructions[..constants_start * per_word]) {
+        constants_start = constants_start.min(end.div_ceil(per_word));
+    }
+    instructions.truncate(constants_start * per_word);
+
+    let instruction_count = instructions.len() as u64;
+    let labels = instructions
+        .iter()
+        .filter_map(|instruction| instruction.opcode.as_ref())
+        .flat_map(jump_targets)
+        .filter(|pc| *pc < instruction_count)
+        .collect();
+
+    Disassembly {
+        instructions,
+        constants_start,
+        constants: code[constants_start..].to_vec(),
+        labels,
+    }
+}
+
+impl Disassembly {
+    /// The instruction in assembly syntax, with code addresses replaced by labels.
+    pub fn render_instruction(&self, instruction: &DisassembledInstruction) -> String {
+        match &instruction.opcode {
+            Some(opcode) => render(opcode, self),
+            None => format!(".invalid {:#x}", instruction.raw),
+        }
+    }
+}
+
+impl fmt::Display for Disassembly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "\t.text")?;
+        for instruction in &self.instructions {
+            if self.labels.contains(&instruction.pc) {
+                writeln!(f, ".L{}:", instruction.pc)?;
+            }
+            writeln!(f, "\t{}", self.render_instruction(instruction))?;
+        }
+        if !self.constants.is_empty() {
+            writeln!(f, "\t.rodata")?;
+            for (i, constant) in self.constants.iter().enumerate() {
+                writeln!(f, "CPI{}:", self.constants_start + i)?;
+                writeln!(f, "\t.cell {constant:#x}")?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,290 @@
+use zkevm_opcode_defs::{
+    BinopOpcode, ContextOpcode, FarCallOpcode, LogOpcode, PtrOpcode, RetOpcode, ShiftOpcode,
+    UMAOpcode,
+};
+
+use crate::address_operands::{address_operands_read, address_operands_store};
+use crate::eravm_error::{EraVmError, OpcodeError, PrecompileError};
+use crate::op_handlers::add::add;
+use crate::op_handlers::and::and;
+use crate::op_handlers::aux_heap_read::aux_heap_read;
+use crate::op_handlers::aux_heap_write::aux_heap_write;
+use crate::op_handlers::context::{
+    aux_mutating0, caller, code_address, ergs_left, get_context_u128, increment_tx_number, meta,
+    set_context_u128, sp, this,
+};
+use crate::op_handlers::div::div;
+use crate::op_handlers::event::event;
+use crate::op_handlers::far_call::far_call;
+use crate::op_handlers::fat_pointer_read::fat_pointer_read;
+use crate::op_handlers::heap_read::heap_read;
+use crate::op_handlers::heap_write::heap_write;
+use crate::op_handlers::jump::jump;
+use crate::op_handlers::log::{
+    add_l2_to_l1_message, storage_read, storage_write, transient_storage_read,
+    transient_storage_write,
+};
+use crate::op_handlers::mul::mul;
+use crate::op_handlers::near_call::near_call;
+use crate::op_handlers::opcode_decommit::opcode_decommit;
+use crate::op_handlers::or::or;
+use crate::op_handlers::precompile_call::precompile_call;
+use crate::op_handlers::ptr_add::ptr_add;
+use crate::op_handlers::ptr_pack::ptr_pack;
+use crate::op_handlers::ptr_shrink::ptr_shrink;
+use crate::op_handlers::ptr_sub::ptr_sub;
+use crate::op_handlers::ret::{panic_from_far_call, ret};
+use crate::op_handlers::shift::{rol, ror, shl, shr};
+use crate::op_handlers::static_memory_read::static_memory_read;
+use crate::op_handlers::static_memory_write::static_memory_write;
+use crate::op_handlers::sub::sub;
+use crate::op_handlers::xor::xor;
+use crate::store::Storage;
+use crate::value::TaggedValue;
+use crate::vm::{retrieve_result, ExecutionOutput};
+use crate::{EraVM, Opcode, Variant};
+
+/// What the interpreter loop does once an instruction's handler is done
+pub(crate) enum Flow {
+    /// Move on to the next instruction
+    Continue,
+    /// Move on to the next instruction, the precompile call wrote a failure instead of its output
+    PrecompileFailed(PrecompileError),
+    /// The handler already moved the pc somewhere else, e.g. to the handler of a failed far call
+    Redirected,
+    /// The execution finished or got suspended on a hook
+    Exit(ExecutionOutput),
+}
+
+/// Executes an already decoded instruction.
+/// An error makes the current frame panic.
+pub(crate) type Handler = fn(&mut EraVM, &Opcode, &mut dyn Storage) -> Result<Flow, EraVmError>;
+
+/// A decoded instruction together with the handler that executes it, so that running it is a
+/// single indirect call instead of a match over the nested opcode variants.
+#[derive(Debug, Clone)]
+pub struct Instruction {
+    pub opcode: Opcode,
+    pub(crate) handler: Handler,
+}
+
+impl Instruction {
+    pub fn new(opcode: Opcode) -> Self {
+        Self {
+            handler: handler_for(&opcode.variant),
+            opcode,
+        }
+    }
+}
+
+fn handler_for(variant: &Variant) -> Handler {
+    match variant {
+        Variant::Invalid(_) => |_, _, _| Err(OpcodeError::InvalidOpCode.into()),
+        Variant::Nop(_) => |vm, opcode, _| {
+            address_operands_read(&mut vm.execution, opcode)?;
+            address_operands_store(
+                &mut vm.execution,
+                opcode,
+                TaggedValue::new_raw_integer(0.into()),
+            )?;
+            Ok(Flow::Continue)
+        },
+        Variant::Add(_) => |vm, opcode, _| next(add(&mut vm.execution, opcode)),
+        Variant::Sub(_) => |vm, opcode, _| next(sub(&mut vm.execution, opcode)),
+        Variant::Jump(_) => |vm, opcode, _| next(jump(&mut vm.execution, opcode)),
+        Variant::Mul(_) => |vm, opcode, _| next(mul(&mut vm.execution, opcode)),
+        Variant::Div(_) => |vm, opcode, _| next(div(&mut vm.execution, opcode)),
+        Variant::Context(context_variant) => match context_variant {
+            ContextOpcode::AuxMutating0 => {
+                |vm, opcode, _| next(aux_mutating0(&mut vm.execution, opcode))
+            }
+            ContextOpcode::Caller => |vm, opcode, _| next(caller(&mut vm.execution, opcode)),
+            ContextOpcode::CodeAddress => {
+                |vm, opcode, _| next(code_address(&mut vm.execution, opcode))
+            }
+            ContextOpcode::ErgsLeft => |vm, opcode, _| next(ergs_left(&mut vm.execution, opcode)),
+            ContextOpcode::GetContextU128 => {
+                |vm, opcode, _| next(get_context_u128(&mut vm.execution, opcode))
+            }
+            ContextOpcode::IncrementTxNumber => |vm, opcode, _| {
+                next(increment_tx_number(
+                    &mut vm.execution,
+                    opcode,
+                    &mut vm.state,
+                ))
+            },
+            ContextOpcode::Meta => |vm, opcode, _| next(meta(&mut vm.execution, opcode, &vm.state)),
+            ContextOpcode::SetContextU128 => {
+                |vm, opcode, _| next(set_context_u128(&mut vm.execution, opcode))
+            }
+            ContextOpcode::Sp => |vm, opcode, _| next(sp(&mut vm.execution, opcode)),
+            ContextOpcode::This => |vm, opcode, _| next(this(&mut vm.execution, opcode)),
+        },
+        Variant::Shift(shift_variant) => match shift_variant {
+            ShiftOpcode::Shl => |vm, opcode, _| next(shl(&mut vm.execution, opcode)),
+            ShiftOpcode::Shr => |vm, opcode, _| next(shr(&mut vm.execution, opcode)),
+            ShiftOpcode::Rol => |vm, opcode, _| next(rol(&mut vm.execution, opcode)),
+            ShiftOpcode::Ror => |vm, opcode, _| next(ror(&mut vm.execution, opcode)),
+        },
+        Variant::Binop(binop) => match binop {
+            BinopOpcode::Xor => |vm, opcode, _| next(xor(&mut vm.execution, opcode)),
+            BinopOpcode::And => |vm, opcode, _| next(and(&mut vm.execution, opcode)),
+            BinopOpcode::Or => |vm, opcode, _| next(or(&mut vm.execution, opcode)),
+        },
+        Variant::Ptr(ptr_variant) => match ptr_variant {
+            PtrOpcode::Add => |vm, opcode, _| next(ptr_add(&mut vm.execution, opcode)),
+            PtrOpcode::Sub => |vm, opcode, _| next(ptr_sub(&mut vm.execution, opcode)),
+            PtrOpcode::Pack => |vm, opcode, _| next(ptr_pack(&mut vm.execution, opcode)),
+            PtrOpcode::Shrink => |vm, opcode, _| next(ptr_shrink(&mut vm.execution, opcode)),
+        },
+        Variant::NearCall(_) => {
+            |vm, opcode, _| next(near_call(&mut vm.execution, opcode, &vm.state))
+        }
+        Variant::Log(log_variant) => match log_variant {
+            LogOpcode::StorageRead => |vm, opcode, storage| {
+                next(storage_read(
+                    &mut vm.execution,
+                    opcode,
+                    &mut vm.state,
+                    &mut vm.statistics,
+                    storage,
+                ))
+            },
+            LogOpcode::StorageWrite => |vm, opcode, storage| {
+                next(storage_write(
+                    &mut vm.execution,
+                    opcode,
+                    &mut vm.state,
+                    &mut vm.statistics,
+                    storage,
+                ))
+            },
+            LogOpcode::ToL1Message => |vm, opcode, _| {
+                next(add_l2_to_l1_message(
+                    &mut vm.execution,
+                    opcode,
+                    &mut vm.state,
+                ))
+            },
+            LogOpcode::PrecompileCall => |vm, opcode, _| {
+                precompile_call(
+                    &mut vm.execution,
+                    opcode,
+                    &mut vm.state,
+                    &mut vm.statistics,
+                    &vm.precompiles,
+                )
+                .map(|failure| failure.map_or(Flow::Continue, Flow::PrecompileFailed))
+            },
+            LogOpcode::Event => {
+                |vm, opcode, _| next(event(&mut vm.execution, opcode, &mut vm.state))
+            }
+            LogOpcode::Decommit => |vm, opcode, storage| {
+                next(opcode_decommit(
+                    &mut vm.execution,
+                    opcode,
+                    &mut vm.state,
+                    &mut vm.statistics,
+                    storage,
+                ))
+            },
+            LogOpcode::TransientStorageRead => |vm, opcode, _| {
+                next(transient_storage_read(
+                    &mut vm.execution,
+                    opcode,
+                    &mut vm.state,
+                ))
+            },
+            LogOpcode::TransientStorageWrite => |vm, opcode, _| {
+                next(transient_storage_write(
+                    &mut vm.execution,
+                    opcode,
+                    &mut vm.state,
+                ))
+            },
+        },
+        Variant::FarCall(far_call_variant) => match far_call_variant {
+            FarCallOpcode::Normal => {
+                |vm, opcode, storage| far_call_or_panic(vm, opcode, &FarCallOpcode::Normal, storage)
+            }
+            FarCallOpcode::Delegate => |vm, opcode, storage| {
+                far_call_or_panic(vm, opcode, &FarCallOpcode::Delegate, storage)
+            },
+            FarCallOpcode::Mimic => {
+                |vm, opcode, storage| far_call_or_panic(vm, opcode, &FarCallOpcode::Mimic, storage)
+            }
+        },
+        Variant::Ret(ret_variant) => match ret_variant {
+            RetOpcode::Ok => |vm, opcode, _| ret_or_exit(vm, opcode, RetOpcode::Ok),
+            RetOpcode::Revert => |vm, opcode, _| ret_or_exit(vm, opcode, RetOpcode::Revert),
+            RetOpcode::Panic => |vm, opcode, _| ret_or_exit(vm, opcode, RetOpcode::Panic),
+        },
+        Variant::UMA(uma_variant) => match uma_variant {
+            UMAOpcode::HeapRead => |vm, opcode, _| next(heap_read(&mut vm.execution, opcode)),
+            UMAOpcode::HeapWrite => |vm, opcode, _| match heap_write(&mut vm.execution, opcode)? {
+                hook @ ExecutionOutput::SuspendedOnHook { .. } => Ok(Flow::Exit(hook)),
+                _ => Ok(Flow::Continue),
+            },
+            UMAOpcode::AuxHeapRead => {
+                |vm, opcode, _| next(aux_heap_read(&mut vm.execution, opcode))
+            }
+            UMAOpcode::AuxHeapWrite => {
+                |vm, opcode, _| next(aux_heap_write(&mut vm.execution, opcode))
+            }
+            UMAOpcode::FatPointerRead => {
+                |vm, opcode, _| next(fat_pointer_read(&mut vm.execution, opcode))
+            }
+            UMAOpcode::StaticMemoryRead => {
+                |vm, opcode, _| next(static_memory_read(&mut vm.execution, opcode))
+            }
+            UMAOpcode::StaticMemoryWrite => {
+                |vm, opcode, _| next(static_memory_write(&mut vm.execution, opcode))
+            }
+        },
+    }
+}
+
+fn next(result: Result<(), EraVmError>) -> Result<Flow, EraVmError> {
+    result.map(|_| Flow::Continue)
+}
+
+// A failing far call doesn't panic the current frame, it jumps to the caller's exception handler
+fn far_call_or_panic(
+    vm: &mut EraVM,
+    opcode: &Opcode,
+    far_call_variant: &FarCallOpcode,
+    storage: &mut dyn Storage,
+) -> Result<Flow, EraVmError> {
+    let result = far_call(
+        &mut vm.execution,
+        opcode,
+        far_call_variant,
+        &mut vm.state,
+        &mut vm.statistics,
+        storage,
+    );
+    match result {
+        Ok(()) => Ok(Flow::Continue),
+        Err(EraVmError::StorageError(err)) if err.is_backend_failure() => Err(err.into()),
+        Err(_) => {
+            panic_from_far_call(&mut vm.execution, opcode)?;
+            Ok(Flow::Redirected)
+        }
+    }
+}
+
+fn ret_or_exit(
+    vm: &mut EraVM,
+    opcode: &Opcode,
+    ret_variant: RetOpcode,
+) -> Result<Flow, EraVmError> {
+    if !ret(&mut vm.execution, opcode, &mut vm.state, ret_variant)? {
+        return Ok(Flow::Continue);
+    }
+    let output = match ret_variant {
+        RetOpcode::Ok => ExecutionOutput::Ok(retrieve_result(&mut vm.execution)?),
+        RetOpcode::Revert => ExecutionOutput::Revert(retrieve_result(&mut vm.execution)?),
+        RetOpcode::Panic => ExecutionOutput::Panic,
+    };
+    Ok(Flow::Exit(output))
+}
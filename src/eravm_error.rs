@@ -39,6 +39,20 @@ pub enum EraVmError {
     DecommitFailed,
 }
 
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigError {
+    #[error("Warm storage reads cost more than cold ones")]
+    WarmReadAboveCold,
+    #[error("Warm storage writes cost more than cold ones")]
+    WarmWriteAboveCold,
+    #[error("Cold storage reads cost more than cold writes")]
+    ColdReadAboveColdWrite,
+    #[error("Far call gas divisor is zero")]
+    ZeroFarCallGasDivisor,
+    #[error("Far calls would pass more gas than is left")]
+    FarCallGasDividendAboveDivisor,
+}
+
 #[derive(Error, Debug)]
 pub enum OperandError {
     #[error("{0:?}: Dest cannot be imm16 only")]
@@ -63,6 +77,8 @@ pub enum OperandError {
 pub enum ContextError {
     #[error("VM has no running contract")]
     NoContract,
+    #[error("Call depth limit exceeded")]
+    CallDepthExceeded,
 }
 
 #[derive(Error, Debug)]
@@ -73,6 +89,8 @@ pub enum StackError {
     StoreOutOfBounds,
     #[error("Trying to read outside of stack bounds")]
     ReadOutOfBounds,
+    #[error("Stack size limit exceeded")]
+    Overflow,
 }
 
 #[derive(Error, Debug)]
@@ -89,16 +107,30 @@ pub enum HeapError {
 pub enum OpcodeError {
     #[error("Invalid OpCode")]
     InvalidOpCode,
-    #[error("Unimplemented")]
-    UnimplementedOpcode,
     #[error("Invalid Opcode predicate")]
     InvalidPredicate,
 }
 
-#[derive(Error, Debug)]
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PrecompileError {
     #[error("Invalid byte in ecrecover")]
     EcRecoverInvalidByte,
+    #[error("Invalid ecrecover signature")]
+    EcRecoverInvalidSignature,
     #[error("Non recoverable k*g point")]
     NonRecoverablePoint,
+    #[error("Invalid secp256r1 signature")]
+    Secp256r1InvalidSignature,
+    #[error("Invalid secp256r1 public key")]
+    Secp256r1InvalidPublicKey,
+    #[error("BN254 coordinate is not a field element")]
+    Bn254InvalidCoordinate,
+    #[error("BN254 point is not on the curve or not in the subgroup")]
+    Bn254InvalidPoint,
+    #[error("Modexp operand past the length limit")]
+    ModexpInputTooLong,
+    #[error("Pairing check with more pairs than the limit")]
+    EcPairingTooManyPairs,
+    #[error("Precompile input or output past the end of the heap")]
+    MemoryOutOfBounds,
 }
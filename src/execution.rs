@@ -1,32 +1,48 @@
 use std::num::Saturating;
+use std::sync::Arc;
 
-use crate::call_frame::{CallFrame, Context};
+use crate::call_frame::{CallFrame, CodePage, Context};
+use crate::config::VmConfig;
+use crate::dispatch::Instruction;
 use crate::heaps::Heaps;
+use crate::rollbacks::Rollbackable;
 
 use crate::eravm_error::{ContextError, EraVmError, HeapError, StackError};
 use crate::state::StateSnapshot;
 use crate::{
     opcode::Predicate,
     value::{FatPointer, TaggedValue},
+    vm::EncodingMode,
     Opcode,
 };
 use u256::{H160, U256};
 use zkevm_opcode_defs::ethereum_types::Address;
-use zkevm_opcode_defs::MEMORY_GROWTH_ERGS_PER_BYTE;
 
 pub const CALLDATA_HEAP: u32 = 1;
 pub const FIRST_HEAP: u32 = 2;
 pub const FIRST_AUX_HEAP: u32 = 3;
+/// Shared by all the frames of a transaction, see `Heaps::clear_static_memory`
+pub const STATIC_MEMORY_HEAP: u32 = 4;
+/// Stack slots a single far call frame can use
+pub const MAX_STACK_SIZE: usize = 1 << 16;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Stack {
     pub stack: Vec<TaggedValue>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+/// Heaps are split in pages of this many bytes, which are only allocated when written to
+pub const HEAP_PAGE_SIZE: usize = 4096;
+
+type HeapPage = [u8; HEAP_PAGE_SIZE];
+
+#[derive(Debug, Clone)]
 pub struct Heap {
-    heap: Vec<u8>,
+    // Cloning a heap shares its pages, they are copied when written to
+    pages: Vec<Option<Arc<HeapPage>>>,
     size: u32,
+    /// End of the highest byte written, reads through pointers stop there
+    written: u32,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -50,6 +66,8 @@ pub struct Execution {
     pub evm_interpreter_code_hash: [u8; 32],
     pub hook_address: u32,
     pub use_hooks: bool,
+    /// Set by `EraVM::with_config`, never changes during a run
+    pub config: VmConfig,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -61,7 +79,7 @@ pub struct ExecutionSnapshot {
     pub flag_eq: bool,
     pub running_contexts: Vec<Context>,
     pub tx_number: u64,
-    pub heaps: Heaps,
+    pub heaps: <Heaps as Rollbackable>::Snapshot,
     pub register_context_u128: u128,
     pub default_aa_code_hash: [u8; 32],
     pub evm_interpreter_code_hash: [u8; 32],
@@ -94,7 +112,7 @@ impl Execution {
         registers[0] = TaggedValue::new_pointer(calldata_ptr.encode());
 
         let context = Context::new(
-            program_code.clone(),
+            CodePage::new(program_code.clone()),
             initial_gas,
             contract_address,
             contract_address,
@@ -126,6 +144,7 @@ impl Execution {
             evm_interpreter_code_hash,
             hook_address,
             use_hooks,
+            config: VmConfig::default(),
         }
     }
 
@@ -150,7 +169,7 @@ impl Execution {
     #[allow(clippy::too_many_arguments)]
     pub fn push_far_call_frame(
         &mut self,
-        program_code: Vec<U256>,
+        code_page: CodePage,
         gas_stipend: u32,
         code_address: Address,
         contract_address: Address,
@@ -164,8 +183,11 @@ impl Execution {
         is_static: bool,
         stipend: u32,
     ) -> Result<(), EraVmError> {
+        if self.running_contexts.len() >= self.config.max_far_call_depth as usize {
+            return Err(ContextError::CallDepthExceeded.into());
+        }
         let new_context = Context::new(
-            program_code,
+            code_page,
             gas_stipend,
             contract_address,
             code_address,
@@ -200,9 +222,12 @@ impl Execution {
     }
 
     pub fn push_near_call_frame(&mut self, near_call_frame: CallFrame) -> Result<(), EraVmError> {
-        self.current_context_mut()?
-            .near_call_frames
-            .push(near_call_frame);
+        let max_near_call_depth = self.config.max_near_call_depth as usize;
+        let near_call_frames = &mut self.current_context_mut()?.near_call_frames;
+        if near_call_frames.len() >= max_near_call_depth {
+            return Err(ContextError::CallDepthExceeded.into());
+        }
+        near_call_frames.push(near_call_frame);
         Ok(())
     }
 
@@ -240,6 +265,14 @@ impl Execution {
         }
     }
 
+    /// Number of frames on the call stack, counting both far and near call frames
+    pub fn frame_depth(&self) -> usize {
+        self.running_contexts
+            .iter()
+            .map(|context| context.near_call_frames.len() + 1)
+            .sum()
+    }
+
     pub fn can_execute(&self, opcode: &Opcode) -> Result<bool, EraVmError> {
         let predicate_holds = match opcode.predicate {
             Predicate::Always => true,
@@ -294,11 +327,35 @@ impl Execution {
             1 => raw_op.low_u128(),
             _ => (raw_op >> 128).low_u128(),
         };
-        Opcode::try_from_raw_opcode_test_encode(opcode)
+        Opcode::try_from_raw_opcode_test_encode(opcode, self.config.protocol_version)
     }
+    /// The testing encoding instruction at the current pc, pre-decoded if the code page is
+    pub fn get_instruction_with_test_encode(&self) -> Result<Instruction, EraVmError> {
+        let current_context = self.current_context()?;
+        let pc = self.current_frame()?.pc;
+        match current_context
+            .code_page
+            .instruction(EncodingMode::Testing, pc)
+        {
+            Some(instruction) => Ok(instruction.clone()),
+            None => self.get_opcode_with_test_encode().map(Instruction::new),
+        }
+    }
+
     pub fn get_opcode(&self) -> Result<Opcode, EraVmError> {
+        self.get_instruction().map(|instruction| instruction.opcode)
+    }
+
+    /// The production encoding instruction at the current pc, pre-decoded if the code page is
+    pub fn get_instruction(&self) -> Result<Instruction, EraVmError> {
         let current_context = self.current_context()?;
         let pc = self.current_frame()?.pc;
+        if let Some(instruction) = current_context
+            .code_page
+            .instruction(EncodingMode::Production, pc)
+        {
+            return Ok(instruction.clone());
+        }
         let raw_opcode = current_context.code_page.get(pc as usize / 4);
 
         let raw_op = match pc % 4 {
@@ -308,8 +365,9 @@ impl Execution {
             _ => ((raw_opcode >> 192) & u64::MAX.into()).as_u64(), // 0
         };
 
-        Opcode::try_from_raw_opcode(raw_op)
+        Opcode::try_from_raw_opcode(raw_op, self.config.protocol_version).map(Instruction::new)
     }
+
     pub fn decrease_gas(&mut self, cost: u32) -> Result<(), EraVmError> {
         let underflows = cost > self.current_frame()?.gas_left.0;
         if underflows {
@@ -349,7 +407,7 @@ impl Execution {
             flag_eq: self.flag_eq,
             flag_gt: self.flag_gt,
             flag_lt_of: self.flag_lt_of,
-            heaps: self.heaps.clone(),
+            heaps: self.heaps.snapshot(),
             hook_address: self.hook_address,
             register_context_u128: self.register_context_u128,
             registers: self.registers,
@@ -364,7 +422,7 @@ impl Execution {
         self.flag_eq = snapshot.flag_eq;
         self.flag_gt = snapshot.flag_gt;
         self.flag_lt_of = snapshot.flag_lt_of;
-        self.heaps = snapshot.heaps;
+        self.heaps.rollback(snapshot.heaps);
         self.hook_address = snapshot.hook_address;
         self.register_context_u128 = snapshot.register_context_u128;
         self.registers = snapshot.registers;
@@ -389,13 +447,17 @@ impl Stack {
         self.stack.push(value);
     }
 
-    pub fn fill_with_zeros(&mut self, value: usize) {
+    pub fn fill_with_zeros(&mut self, value: usize) -> Result<(), StackError> {
+        if self.stack.len() + value > MAX_STACK_SIZE {
+            return Err(StackError::Overflow);
+        }
         for _ in 0..value {
             self.stack.push(TaggedValue {
                 value: U256::zero(),
                 is_pointer: false,
             });
         }
+        Ok(())
     }
 
     pub fn get_with_offset(&self, offset: u16, sp: u32) -> Result<TaggedValue, StackError> {
@@ -431,7 +493,7 @@ impl Stack {
         }
         let index = (sp - offset as u32) as usize;
         if index >= self.stack.len() {
-            self.fill_with_zeros(index - self.stack.len() + 1);
+            self.fill_with_zeros(index - self.stack.len() + 1)?;
         }
         self.stack[index] = value;
         Ok(())
@@ -440,7 +502,7 @@ impl Stack {
     pub fn store_absolute(&mut self, index: u16, value: TaggedValue) -> Result<(), StackError> {
         let index = index as usize;
         if index >= self.stack.len() {
-            self.fill_with_zeros(index - self.stack.len() + 1);
+            self.fill_with_zeros(index - self.stack.len() + 1)?;
         }
         self.stack[index] = value;
         Ok(())
@@ -453,72 +515,131 @@ impl Default for Heap {
     }
 }
 
+impl PartialEq for Heap {
+    // Pages that were never written to are equal to zeroed ones
+    fn eq(&self, other: &Self) -> bool {
+        let page_count = self.pages.len().max(other.pages.len());
+        self.size == other.size
+            && self.written == other.written
+            && (0..page_count).all(|index| match (self.page(index), other.page(index)) {
+                (None, None) => true,
+                (Some(page), None) | (None, Some(page)) => page.iter().all(|byte| *byte == 0),
+                (Some(a), Some(b)) => a == b,
+            })
+    }
+}
+
 impl Heap {
     pub fn new(values: Vec<u8>, size: u32) -> Self {
-        Self { heap: values, size }
+        let mut heap = Self {
+            pages: vec![],
+            size,
+            written: 0,
+        };
+        heap.write_bytes(0, &values);
+        heap
+    }
+
+    fn page(&self, index: usize) -> Option<&HeapPage> {
+        self.pages.get(index)?.as_deref()
     }
-    fn expand_memory_inner(&mut self, address: u32) {
-        if address >= self.heap.len() as u32 {
-            self.heap.resize(address as usize, 0);
+
+    fn page_mut(&mut self, index: usize) -> &mut HeapPage {
+        if index >= self.pages.len() {
+            self.pages.resize(index + 1, None);
+        }
+        let page = self.pages[index].get_or_insert_with(|| Arc::new([0; HEAP_PAGE_SIZE]));
+        Arc::make_mut(page)
+    }
+
+    fn write_bytes(&mut self, address: usize, bytes: &[u8]) {
+        let mut written = 0;
+        while written < bytes.len() {
+            let at = address + written;
+            let offset = at % HEAP_PAGE_SIZE;
+            let count = (HEAP_PAGE_SIZE - offset).min(bytes.len() - written);
+            self.page_mut(at / HEAP_PAGE_SIZE)[offset..offset + count]
+                .copy_from_slice(&bytes[written..written + count]);
+            written += count;
         }
+        self.written = self.written.max((address + bytes.len()) as u32);
+    }
+
+    // Bytes that were never written to read as zero
+    fn read_bytes(&self, address: usize, buffer: &mut [u8]) {
+        let mut read = 0;
+        while read < buffer.len() {
+            let at = address + read;
+            let offset = at % HEAP_PAGE_SIZE;
+            let count = (HEAP_PAGE_SIZE - offset).min(buffer.len() - read);
+            let target = &mut buffer[read..read + count];
+            match self.page(at / HEAP_PAGE_SIZE) {
+                Some(page) => target.copy_from_slice(&page[offset..offset + count]),
+                None => target.fill(0),
+            }
+            read += count;
+        }
+    }
+
+    pub(crate) fn resize(&mut self, size: u32) {
+        self.size = size;
+    }
+
+    pub(crate) fn written_len(&self) -> u32 {
+        self.written
+    }
+
+    // Only for undoing writes, the bytes past `written` must have been zero before
+    pub(crate) fn set_written_len(&mut self, written: u32) {
+        self.written = written;
     }
 
     // Returns how many ergs the expand costs
-    pub fn expand_memory(&mut self, address: u32) -> u32 {
+    pub fn expand_memory(&mut self, address: u32, ergs_per_byte: u32) -> u32 {
         if address >= self.size {
             let old_size = self.size;
             self.size = address;
-            return MEMORY_GROWTH_ERGS_PER_BYTE * (self.size - old_size);
+            return ergs_per_byte * (self.size - old_size);
         }
         0
     }
 
     pub fn store(&mut self, address: u32, value: U256) {
-        let start = address as usize;
-        let end = start + 32;
-        self.expand_memory_inner(end as u32);
-        value.to_big_endian(&mut self.heap[start..end]);
+        let mut bytes = [0u8; 32];
+        value.to_big_endian(&mut bytes);
+        self.write_bytes(address as usize, &bytes);
     }
 
     pub fn read(&self, address: u32) -> U256 {
-        let start = address as usize;
-        let mut end = start + 32;
-        if start >= self.heap.len() {
-            return U256::zero();
-        }
-        end = end.min(self.heap.len());
-        let mut result = self.heap[start..end].to_vec();
-        result.resize(32, 0);
-        U256::from_big_endian(&result)
+        let mut bytes = [0u8; 32];
+        self.read_bytes(address as usize, &mut bytes);
+        U256::from_big_endian(&bytes)
     }
 
-    pub fn expanded_read(&mut self, address: u32) -> (U256, u32) {
-        let gas_cost = self.expand_memory(address + 32);
+    // Returns the word and how many ergs the expand costs
+    pub fn expanded_read(&mut self, address: u32, ergs_per_byte: u32) -> (U256, u32) {
+        let gas_cost = self.expand_memory(address + 32, ergs_per_byte);
         let result = self.read(address);
         (result, gas_cost)
     }
 
     pub fn read_byte(&self, address: u32) -> u8 {
-        if address >= self.heap.len() as u32 {
-            return 0;
-        }
-        self.heap[address as usize]
+        let address = address as usize;
+        self.page(address / HEAP_PAGE_SIZE)
+            .map_or(0, |page| page[address % HEAP_PAGE_SIZE])
     }
 
     pub fn read_from_pointer(&self, pointer: &FatPointer) -> U256 {
-        let mut result = U256::zero();
-        let start = pointer.start + pointer.offset;
-        let end = self.heap.len().min((start + 32) as usize) as u32;
-        for (i, addr) in (start..end).enumerate() {
-            result |= U256::from(self.heap[addr as usize]) << ((31 - i) * 8);
-        }
-        result
+        self.read(pointer.start + pointer.offset)
     }
 
+    /// Reads the pointed bytes, up to the last one written
     pub fn read_unaligned_from_pointer(&self, pointer: &FatPointer) -> Result<Vec<u8>, HeapError> {
         let start = (pointer.start + pointer.offset) as usize;
-        let finish = (start + pointer.len as usize).min(self.heap.len());
-        Ok(self.heap[start..finish].into())
+        let finish = (start + pointer.len as usize).min(self.written as usize);
+        let mut result = vec![0; finish.saturating_sub(start)];
+        self.read_bytes(start, &mut result);
+        Ok(result)
     }
 
     pub fn len(&self) -> usize {
@@ -1,10 +1,59 @@
-use zkevm_opcode_defs::system_params::NEW_FRAME_MEMORY_STIPEND;
+use u256::U256;
 
-use crate::{eravm_error::HeapError, execution::Heap};
+use crate::{
+    eravm_error::HeapError,
+    execution::{Heap, STATIC_MEMORY_HEAP},
+    rollbacks::{Journal, JournalSnapshot, Rollbackable},
+};
 
-#[derive(Debug, Clone, Default, PartialEq)]
+#[derive(Debug, Clone, Default)]
 pub struct Heaps {
     heaps: Vec<Heap>,
+    /// Ids of deallocated heaps, handed out again by the next allocations
+    free_ids: Vec<u32>,
+    /// Undo log of the changes live snapshots can roll back, a snapshot is a position in it
+    journal: Journal<HeapChange>,
+}
+
+#[derive(Debug, Clone)]
+enum HeapChange {
+    /// `previous` is `None` when the heap was pushed, otherwise its id was taken from `free_ids`
+    Allocated {
+        id: u32,
+        previous: Option<Heap>,
+    },
+    Deallocated {
+        id: u32,
+        previous: Heap,
+    },
+    Replaced {
+        id: u32,
+        previous: Heap,
+    },
+    Stored {
+        id: u32,
+        address: u32,
+        previous: U256,
+        written: u32,
+    },
+    Resized {
+        id: u32,
+        previous: u32,
+    },
+}
+
+/// Gives write access to a heap, recording the changes in the journal of its `Heaps`
+pub struct HeapMut<'a> {
+    id: u32,
+    heap: &'a mut Heap,
+    journal: &'a mut Journal<HeapChange>,
+}
+
+impl PartialEq for Heaps {
+    // The journal is how the heaps got here, not part of their contents
+    fn eq(&self, other: &Self) -> bool {
+        self.heaps == other.heaps && self.free_ids == other.free_ids
+    }
 }
 
 impl Heaps {
@@ -17,25 +66,71 @@ impl Heaps {
             Heap::new(calldata, size),
             Heap::default(),
             Heap::default(),
+            // static memory
+            Heap::default(),
         ];
 
-        Self { heaps }
+        Self {
+            heaps,
+            free_ids: vec![],
+            journal: Journal::default(),
+        }
+    }
+
+    /// Allocates a new heap where the first `stipend` bytes are already paid for
+    pub fn allocate(&mut self, stipend: u32) -> u32 {
+        let heap = Heap::new(vec![], stipend);
+        match self.free_ids.pop() {
+            Some(id) => {
+                let previous = std::mem::replace(&mut self.heaps[id as usize], heap);
+                self.journal.push(HeapChange::Allocated {
+                    id,
+                    previous: Some(previous),
+                });
+                id
+            }
+            None => {
+                self.heaps.push(heap);
+                let id = self.heaps.len() as u32 - 1;
+                self.journal
+                    .push(HeapChange::Allocated { id, previous: None });
+                id
+            }
+        }
     }
 
-    pub fn allocate(&mut self) -> u32 {
-        let id = self.heaps.len() as u32;
-        self.heaps.push(Heap::new(vec![], NEW_FRAME_MEMORY_STIPEND));
-        id
+    /// Frees the heap's memory and lets its id be reused.
+    /// No pointer into the heap may be reachable anymore.
+    pub fn deallocate(&mut self, heap: u32) {
+        if heap <= STATIC_MEMORY_HEAP
+            || heap as usize >= self.heaps.len()
+            || self.free_ids.contains(&heap)
+        {
+            return;
+        }
+        let previous = std::mem::take(&mut self.heaps[heap as usize]);
+        self.journal
+            .push(HeapChange::Deallocated { id: heap, previous });
+        self.free_ids.push(heap);
     }
 
-    pub fn allocate_copy(&mut self) -> u32 {
-        let id: u32 = self.heaps.len() as u32;
-        self.heaps.push(Heap::new(vec![], NEW_FRAME_MEMORY_STIPEND));
-        id
+    /// Static memory lives for a single transaction, it's emptied when the next one starts.
+    pub fn clear_static_memory(&mut self) {
+        let previous = std::mem::take(&mut self.heaps[STATIC_MEMORY_HEAP as usize]);
+        self.journal.push(HeapChange::Replaced {
+            id: STATIC_MEMORY_HEAP,
+            previous,
+        });
     }
 
-    pub fn deallocate(&mut self, heap: u32) {
-        self.heaps[heap as usize] = Heap::default();
+    /// How many heaps are in use, the ones every execution starts with included
+    pub fn allocated(&self) -> usize {
+        self.heaps.len() - self.free_ids.len()
+    }
+
+    /// Changes the snapshots alive can still roll back, for tests and diagnostics
+    pub fn journal_len(&self) -> usize {
+        self.journal.len()
     }
 
     pub fn get(&self, index: u32) -> Option<&Heap> {
@@ -46,11 +141,114 @@ impl Heaps {
         self.get(index).ok_or(HeapError::ReadOutOfBounds)
     }
 
-    pub fn get_mut(&mut self, index: u32) -> Option<&mut Heap> {
-        self.heaps.get_mut(index as usize)
+    pub fn get_mut(&mut self, index: u32) -> Option<HeapMut<'_>> {
+        Some(HeapMut {
+            id: index,
+            heap: self.heaps.get_mut(index as usize)?,
+            journal: &mut self.journal,
+        })
     }
 
-    pub fn try_get_mut(&mut self, index: u32) -> Result<&mut Heap, HeapError> {
+    pub fn try_get_mut(&mut self, index: u32) -> Result<HeapMut<'_>, HeapError> {
         self.get_mut(index).ok_or(HeapError::ReadOutOfBounds)
     }
 }
+
+impl Rollbackable for Heaps {
+    type Snapshot = JournalSnapshot;
+
+    fn rollback(&mut self, snapshot: Self::Snapshot) {
+        let undone = self.journal.split_off(&snapshot);
+        for change in undone.into_iter().rev() {
+            match change {
+                HeapChange::Allocated { previous: None, .. } => {
+                    self.heaps.pop();
+                }
+                HeapChange::Allocated {
+                    id,
+                    previous: Some(previous),
+                } => {
+                    self.heaps[id as usize] = previous;
+                    self.free_ids.push(id);
+                }
+                HeapChange::Deallocated { id, previous } => {
+                    self.heaps[id as usize] = previous;
+                    // take back this heap's id, whichever position it has
+                    if let Some(index) = self.free_ids.iter().rposition(|free| *free == id) {
+                        self.free_ids.remove(index);
+                    }
+                }
+                HeapChange::Replaced { id, previous } => self.heaps[id as usize] = previous,
+                HeapChange::Stored {
+                    id,
+                    address,
+                    previous,
+                    written,
+                } => {
+                    let heap = &mut self.heaps[id as usize];
+                    heap.store(address, previous);
+                    heap.set_written_len(written);
+                }
+                HeapChange::Resized { id, previous } => self.heaps[id as usize].resize(previous),
+            }
+        }
+    }
+
+    fn snapshot(&self) -> Self::Snapshot {
+        self.journal.snapshot()
+    }
+}
+
+impl HeapMut<'_> {
+    // Returns how many ergs the expand costs
+    pub fn expand_memory(&mut self, address: u32, ergs_per_byte: u32) -> u32 {
+        let previous = self.heap.len() as u32;
+        let cost = self.heap.expand_memory(address, ergs_per_byte);
+        self.record_growth(previous);
+        cost
+    }
+
+    pub fn store(&mut self, address: u32, value: U256) {
+        self.journal.push(HeapChange::Stored {
+            id: self.id,
+            address,
+            previous: self.heap.read(address),
+            written: self.heap.written_len(),
+        });
+        self.heap.store(address, value);
+    }
+
+    // Returns the word and how many ergs the expand costs
+    pub fn expanded_read(&mut self, address: u32, ergs_per_byte: u32) -> (U256, u32) {
+        let previous = self.heap.len() as u32;
+        let result = self.heap.expanded_read(address, ergs_per_byte);
+        self.record_growth(previous);
+        result
+    }
+
+    /// Replaces the whole heap, e.g. to set up its contents before running
+    pub fn replace(&mut self, heap: Heap) {
+        let previous = std::mem::replace(self.heap, heap);
+        self.journal.push(HeapChange::Replaced {
+            id: self.id,
+            previous,
+        });
+    }
+
+    fn record_growth(&mut self, previous: u32) {
+        if self.heap.len() as u32 != previous {
+            self.journal.push(HeapChange::Resized {
+                id: self.id,
+                previous,
+            });
+        }
+    }
+}
+
+impl std::ops::Deref for HeapMut<'_> {
+    type Target = Heap;
+
+    fn deref(&self) -> &Heap {
+        self.heap
+    }
+}
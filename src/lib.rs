@@ -1,12 +1,17 @@
 mod address_operands;
+pub mod assembler;
 pub mod call_frame;
-mod eravm_error;
+pub mod config;
+pub mod debugger;
+pub mod disassembler;
+mod dispatch;
+pub mod eravm_error;
 pub mod execution;
 pub mod heaps;
 mod op_handlers;
 pub mod opcode;
 pub mod output;
-mod precompiles;
+pub mod precompiles;
 mod ptr_operator;
 pub mod statistics;
 pub mod store;
@@ -14,6 +19,7 @@ pub mod tracers;
 pub mod utils;
 pub mod value;
 pub mod vm;
+pub use dispatch::Instruction;
 pub use execution::Execution;
 pub use opcode::Opcode;
 pub use vm::EraVM;
@@ -81,12 +81,20 @@ pub fn set_context_u128(vm: &mut Execution, opcode: &Opcode) -> Result<(), EraVm
     Ok(())
 }
 
+// Kernel only. zk_evm's context opcode execution has an empty arm for AuxMutating0: past the
+// kernel mode check and the base cost, it leaves registers, flags, memory and storage untouched.
+// `aux_mutating0_leaves_the_state_untouched` pins that.
+pub fn aux_mutating0(_vm: &mut Execution, _opcode: &Opcode) -> Result<(), EraVmError> {
+    Ok(())
+}
+
 pub fn increment_tx_number(
     vm: &mut Execution,
     _opcode: &Opcode,
     state: &mut VMState,
 ) -> Result<(), EraVmError> {
     vm.tx_number += 1;
+    vm.heaps.clear_static_memory();
     state.clear_transient_storage();
     Ok(())
 }
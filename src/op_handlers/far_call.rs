@@ -1,10 +1,7 @@
 use u256::{H160, U256};
 use zkevm_opcode_defs::{
     ethereum_types::Address,
-    system_params::{
-        DEPLOYER_SYSTEM_CONTRACT_ADDRESS_LOW, EVM_SIMULATOR_STIPEND,
-        MSG_VALUE_SIMULATOR_ADDITIVE_COST,
-    },
+    system_params::{DEPLOYER_SYSTEM_CONTRACT_ADDRESS_LOW, MSG_VALUE_SIMULATOR_ADDITIVE_COST},
     FarCallOpcode, ADDRESS_MSG_VALUE,
 };
 
@@ -33,8 +30,6 @@ struct FarCallParams {
     /// If the far call is in kernel mode.
     to_system: bool,
 }
-const FAR_CALL_GAS_SCALAR_MODIFIER_DIVIDEND: u32 = 63;
-const FAR_CALL_GAS_SCALAR_MODIFIER_DIVISOR: u32 = 64;
 
 #[repr(u8)]
 #[derive(Debug, Clone, Copy)]
@@ -87,7 +82,7 @@ pub fn get_forward_memory_pointer(
                     .heaps
                     .get_mut(pointer.page)
                     .ok_or(HeapError::StoreOutOfBounds)?
-                    .expand_memory(bound);
+                    .expand_memory(bound, vm.config.memory_growth_ergs_per_byte);
 
                 vm.decrease_gas(ergs_cost)?;
             }
@@ -104,10 +99,7 @@ fn far_call_params_from_register(
     let source = source.value;
     let mut args = [0u8; 32];
     let mut ergs_passed = source.0[3] as u32;
-    let gas_left = vm.gas_left()?;
-
-    let maximum_gas =
-        gas_left / FAR_CALL_GAS_SCALAR_MODIFIER_DIVISOR * FAR_CALL_GAS_SCALAR_MODIFIER_DIVIDEND;
+    let maximum_gas = vm.config.max_far_call_gas(vm.gas_left()?);
 
     ergs_passed = ergs_passed.min(maximum_gas);
 
@@ -124,7 +116,7 @@ fn far_call_params_from_register(
         shard_id,
     })
 }
-fn address_from_u256(register_value: &U256) -> H160 {
+pub(crate) fn address_from_u256(register_value: &U256) -> H160 {
     let mut buffer: [u8; 32] = [0; 32];
     register_value.to_big_endian(&mut buffer[..]);
     H160::from_slice(&buffer[12..])
@@ -134,7 +126,7 @@ fn decommit_code_hash(
     state: &mut VMState,
     address: Address,
     default_aa_code_hash: [u8; 32],
-    evm_interpreter_code_hash: [u8; 32],
+    evm_interpreter_code_hash: Option<[u8; 32]>,
     is_constructor_call: bool,
     storage: &mut dyn Storage,
 ) -> Result<(U256, bool, u32), EraVmError> {
@@ -144,7 +136,7 @@ fn decommit_code_hash(
     let storage_key = StorageKey::new(deployer_system_contract_address, address_into_u256(address));
 
     // reading when decommiting doesn't refund
-    let code_info = state.storage_read_with_no_refund(storage_key, storage);
+    let code_info = state.storage_read_with_no_refund(storage_key, storage)?;
     let mut code_info_bytes = [0; 32];
     code_info.to_big_endian(&mut code_info_bytes);
 
@@ -185,7 +177,10 @@ fn decommit_code_hash(
             }
         }
         // There is an EVM contract (blob) stored in this address (we need the interpreter)
+        // Versions without the interpreter treat it as invalid
         BLOB_VERSION_FLAG => {
+            let evm_interpreter_code_hash =
+                evm_interpreter_code_hash.ok_or(EraVmError::IncorrectBytecodeFormat)?;
             if is_constructed == is_constructor_call {
                 try_default_aa.ok_or(StorageError::KeyNotPresent)?
             } else {
@@ -247,7 +242,10 @@ pub fn far_call(
         state,
         contract_address,
         vm.default_aa_code_hash,
-        vm.evm_interpreter_code_hash,
+        vm.config
+            .protocol_version
+            .supports_evm_bytecode()
+            .then_some(vm.evm_interpreter_code_hash),
         abi.is_constructor_call,
         storage,
     )?;
@@ -266,51 +264,52 @@ pub fn far_call(
         }
     }
 
-    let gas_left = vm.gas_left()?;
-    let maximum_gas =
-        gas_left / FAR_CALL_GAS_SCALAR_MODIFIER_DIVISOR * FAR_CALL_GAS_SCALAR_MODIFIER_DIVIDEND;
+    let maximum_gas = vm.config.max_far_call_gas(vm.gas_left()?);
     let ergs_passed = ergs_passed.min(maximum_gas);
     vm.decrease_gas(ergs_passed)?;
 
     // mandated gas can surprass the 63/64 limit
     let ergs_passed = ergs_passed + mandated_gas;
 
-    let stipend = if is_evm { EVM_SIMULATOR_STIPEND } else { 0 };
+    let stipend = if is_evm {
+        vm.config.evm_simulator_stipend
+    } else {
+        0
+    };
 
     let ergs_passed = (ergs_passed)
         .checked_add(stipend)
         .expect("stipend must not cause overflow");
 
-    let (program_code, was_decommited) = state.decommit(code_key, storage);
+    let (program_code, was_decommited) =
+        state.decommit(code_key, storage, vm.config.protocol_version)?;
 
     let program_code = program_code.ok_or(StorageError::KeyNotPresent)?;
     if !was_decommited {
         statistics.storage_application_cycles += STORAGE_READ_STORAGE_APPLICATION_CYCLES;
-        statistics.decommiter_cycle_from_decommit(&program_code);
+        statistics.decommiter_cycle_from_decommit(program_code.words());
     }
 
-    let new_heap = vm.heaps.allocate();
-    let new_aux_heap = vm.heaps.allocate();
+    let new_heap = vm.heaps.allocate(vm.config.new_frame_memory_stipend);
+    let new_aux_heap = vm.heaps.allocate(vm.config.new_frame_memory_stipend);
     let is_new_frame_static = opcode.flag0_set || vm.current_context()?.is_static;
 
-    match far_call {
-        FarCallOpcode::Normal => {
-            vm.push_far_call_frame(
-                program_code,
-                ergs_passed,
-                contract_address,
-                contract_address,
-                vm.current_context()?.contract_address,
-                new_heap,
-                new_aux_heap,
-                forward_memory.page,
-                exception_handler,
-                vm.register_context_u128,
-                snapshot,
-                is_new_frame_static && !is_evm,
-                stipend,
-            )?;
-        }
+    let pushed = match far_call {
+        FarCallOpcode::Normal => vm.push_far_call_frame(
+            program_code,
+            ergs_passed,
+            contract_address,
+            contract_address,
+            vm.current_context()?.contract_address,
+            new_heap,
+            new_aux_heap,
+            forward_memory.page,
+            exception_handler,
+            vm.register_context_u128,
+            snapshot,
+            is_new_frame_static && !is_evm,
+            stipend,
+        ),
         FarCallOpcode::Mimic => {
             let caller = address_from_u256(&vm.get_register(15).value);
 
@@ -328,7 +327,7 @@ pub fn far_call(
                 snapshot,
                 is_new_frame_static && !is_evm,
                 stipend,
-            )?;
+            )
         }
         FarCallOpcode::Delegate => {
             let this_context = vm.current_context()?;
@@ -348,9 +347,15 @@ pub fn far_call(
                 snapshot,
                 is_new_frame_static && !is_evm,
                 stipend,
-            )?;
+            )
         }
     };
+    if let Err(err) = pushed {
+        // the frame was never pushed, nothing can point into its heaps
+        vm.heaps.deallocate(new_heap);
+        vm.heaps.deallocate(new_aux_heap);
+        return Err(err);
+    }
 
     vm.register_context_u128 = 0_u128;
 
@@ -20,11 +20,12 @@ pub fn fat_pointer_read(vm: &mut Execution, opcode: &Opcode) -> Result<(), EraVm
     let pointer = FatPointer::decode(src0.value);
 
     let value = if pointer.offset < pointer.len {
-        let heap = vm
+        let ergs_per_byte = vm.config.memory_growth_ergs_per_byte;
+        let mut heap = vm
             .heaps
             .get_mut(pointer.page)
             .ok_or(HeapError::ReadOutOfBounds)?;
-        let gas_cost = heap.expand_memory(pointer.start + pointer.offset + 32);
+        let gas_cost = heap.expand_memory(pointer.start + pointer.offset + 32, ergs_per_byte);
         let value = heap.read_from_pointer(&pointer);
         vm.decrease_gas(gas_cost)?;
 
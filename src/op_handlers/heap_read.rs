@@ -7,6 +7,17 @@ use crate::value::TaggedValue;
 use crate::{execution::Execution, opcode::Opcode};
 
 pub fn heap_read(vm: &mut Execution, opcode: &Opcode) -> Result<(), EraVmError> {
+    let heap_id = vm.current_context()?.heap_id;
+    read_from_heap(vm, opcode, heap_id)
+}
+
+/// Reads the word at the address in `src0` from the given heap, paying for the heap growth.
+/// Shared by every instruction that reads a heap through an address.
+pub(crate) fn read_from_heap(
+    vm: &mut Execution,
+    opcode: &Opcode,
+    heap_id: u32,
+) -> Result<(), EraVmError> {
     let (src0, _) = address_operands_read(vm, opcode)?;
     if src0.is_pointer {
         return Err(OperandError::InvalidSrcPointer(opcode.variant).into());
@@ -19,15 +30,15 @@ pub fn heap_read(vm: &mut Execution, opcode: &Opcode) -> Result<(), EraVmError>
 
     let gas_cost = vm
         .heaps
-        .get_mut(vm.current_context()?.heap_id)
-        .ok_or(HeapError::StoreOutOfBounds)?
-        .expand_memory(addr + 32);
+        .get_mut(heap_id)
+        .ok_or(HeapError::ReadOutOfBounds)?
+        .expand_memory(addr + 32, vm.config.memory_growth_ergs_per_byte);
 
     vm.decrease_gas(gas_cost)?;
 
     let value = vm
         .heaps
-        .get(vm.current_context()?.heap_id)
+        .get(heap_id)
         .ok_or(HeapError::ReadOutOfBounds)?
         .read(addr);
 
@@ -8,6 +8,27 @@ use crate::vm::ExecutionOutput;
 use crate::{execution::Execution, opcode::Opcode};
 
 pub fn heap_write(vm: &mut Execution, opcode: &Opcode) -> Result<ExecutionOutput, EraVmError> {
+    let heap_id = vm.current_context()?.heap_id;
+    let (addr, value) = write_to_heap(vm, opcode, heap_id)?;
+
+    if vm.use_hooks && addr == vm.hook_address {
+        Ok(ExecutionOutput::SuspendedOnHook {
+            hook: value.as_u32(),
+            pc_to_resume_from: vm.current_frame()?.pc.wrapping_add(1) as u16,
+        })
+    } else {
+        Ok(ExecutionOutput::Ok(vec![]))
+    }
+}
+
+/// Stores `src1` at the address in `src0` of the given heap, paying for the heap growth.
+/// Shared by every instruction that writes a heap through an address.
+/// Returns the address and the value written.
+pub(crate) fn write_to_heap(
+    vm: &mut Execution,
+    opcode: &Opcode,
+    heap_id: u32,
+) -> Result<(u32, U256), EraVmError> {
     let (src0, src1) = address_operands_read(vm, opcode)?;
     if src0.is_pointer {
         return Err(OperandError::InvalidSrcPointer(opcode.variant).into());
@@ -20,14 +41,14 @@ pub fn heap_write(vm: &mut Execution, opcode: &Opcode) -> Result<ExecutionOutput
 
     let gas_cost = vm
         .heaps
-        .get_mut(vm.current_context()?.heap_id)
+        .get_mut(heap_id)
         .ok_or(HeapError::StoreOutOfBounds)?
-        .expand_memory(addr + 32);
+        .expand_memory(addr + 32, vm.config.memory_growth_ergs_per_byte);
 
     vm.decrease_gas(gas_cost)?;
 
     vm.heaps
-        .get_mut(vm.current_context()?.heap_id)
+        .get_mut(heap_id)
         .ok_or(HeapError::StoreOutOfBounds)?
         .store(addr, src1.value);
 
@@ -38,13 +59,5 @@ pub fn heap_write(vm: &mut Execution, opcode: &Opcode) -> Result<ExecutionOutput
             TaggedValue::new_raw_integer(U256::from(addr + 32)),
         );
     }
-
-    if vm.use_hooks && addr == vm.hook_address {
-        Ok(ExecutionOutput::SuspendedOnHook {
-            hook: src1.value.as_u32(),
-            pc_to_resume_from: vm.current_frame()?.pc.wrapping_add(1) as u16,
-        })
-    } else {
-        Ok(ExecutionOutput::Ok(vec![]))
-    }
+    Ok((addr, src1.value))
 }
@@ -1,5 +1,5 @@
 use crate::{
-    eravm_error::EraVmError,
+    eravm_error::{EraVmError, OpcodeError},
     execution::Execution,
     state::{L2ToL1Log, VMState},
     statistics::{
@@ -25,7 +25,7 @@ pub fn storage_write(
         statistics.storage_application_cycles += STORAGE_WRITE_STORAGE_APPLICATION_CYCLES;
     }
     let value = vm.get_register(opcode.src1_index).value;
-    let refund = state.storage_write(key, value, storage);
+    let refund = state.storage_write(key, value, storage, &vm.config)?;
     vm.increase_gas(refund)?;
     Ok(())
 }
@@ -45,7 +45,7 @@ pub fn storage_read(
     if !state.read_storage_slots().contains(&key) && !state.written_storage_slots().contains(&key) {
         statistics.storage_application_cycles += STORAGE_READ_STORAGE_APPLICATION_CYCLES;
     }
-    let (value, refund) = state.storage_read(key, storage);
+    let (value, refund) = state.storage_read(key, storage, &vm.config)?;
     vm.increase_gas(refund)?;
     vm.set_register(opcode.dst0_index, TaggedValue::new_raw_integer(value));
     Ok(())
@@ -56,6 +56,9 @@ pub fn transient_storage_write(
     opcode: &Opcode,
     state: &mut VMState,
 ) -> Result<(), EraVmError> {
+    if !vm.config.protocol_version.supports_transient_storage() {
+        return Err(OpcodeError::InvalidOpCode.into());
+    }
     let key_for_contract_storage = vm.get_register(opcode.src0_index).value;
     let address = vm.current_context()?.contract_address;
     let key = StorageKey::new(address, key_for_contract_storage);
@@ -69,6 +72,9 @@ pub fn transient_storage_read(
     opcode: &Opcode,
     state: &mut VMState,
 ) -> Result<(), EraVmError> {
+    if !vm.config.protocol_version.supports_transient_storage() {
+        return Err(OpcodeError::InvalidOpCode.into());
+    }
     let key_for_contract_storage = vm.get_register(opcode.src0_index).value;
     let address = vm.current_context()?.contract_address;
     let key = StorageKey::new(address, key_for_contract_storage);
@@ -22,6 +22,7 @@ pub mod ptr_shrink;
 pub mod ptr_sub;
 pub mod ret;
 pub mod shift;
+pub mod static_memory_read;
+pub mod static_memory_write;
 pub mod sub;
-pub mod unimplemented;
 pub mod xor;
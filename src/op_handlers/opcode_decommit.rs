@@ -36,7 +36,7 @@ pub fn opcode_decommit(
         return Ok(());
     }
 
-    let (code, was_decommited) = state.decommit(code_hash, storage);
+    let (code, was_decommited) = state.decommit(code_hash, storage, vm.config.protocol_version)?;
     if was_decommited {
         // refund it
         vm.increase_gas(extra_cost)?;
@@ -47,19 +47,22 @@ pub fn opcode_decommit(
     let code = code.ok_or(EraVmError::DecommitFailed)?;
 
     let code_len_in_bytes = code.len() * 32;
-    let id = vm.heaps.allocate();
+    let id = vm.heaps.allocate(vm.config.new_frame_memory_stipend);
     let mem_expansion_gas_cost = vm
         .heaps
         .get_mut(id)
         .ok_or(HeapError::StoreOutOfBounds)?
-        .expand_memory(code_len_in_bytes as u32);
+        .expand_memory(
+            code_len_in_bytes as u32,
+            vm.config.memory_growth_ergs_per_byte,
+        );
 
     vm.decrease_gas(mem_expansion_gas_cost)?;
 
-    let heap = vm.heaps.get_mut(id).ok_or(HeapError::StoreOutOfBounds)?;
+    let mut heap = vm.heaps.get_mut(id).ok_or(HeapError::StoreOutOfBounds)?;
 
     let mut address = 0;
-    for value in code.iter() {
+    for value in code.words() {
         heap.store(address, *value);
         address += 32;
     }
@@ -1,19 +1,10 @@
-use zkevm_opcode_defs::{
-    system_params::{
-        ECRECOVER_INNER_FUNCTION_PRECOMPILE_ADDRESS, KECCAK256_ROUND_FUNCTION_PRECOMPILE_ADDRESS,
-        SECP256R1_VERIFY_PRECOMPILE_ADDRESS, SHA256_ROUND_FUNCTION_PRECOMPILE_ADDRESS,
-    },
-    PrecompileAuxData, PrecompileCallABI,
-};
+use zkevm_opcode_defs::{PrecompileAuxData, PrecompileCallABI};
 
 use crate::{
     address_operands::{address_operands_read, address_operands_store},
-    eravm_error::EraVmError,
+    eravm_error::{EraVmError, PrecompileError},
     execution::Execution,
-    precompiles::{
-        ecrecover::ecrecover_function, keccak256::keccak256_rounds_function,
-        secp256r1_verify::secp256r1_verify_function, sha256::sha256_rounds_function,
-    },
+    precompiles::registry::PrecompileRegistry,
     state::VMState,
     statistics::VmStatistics,
     value::TaggedValue,
@@ -25,7 +16,8 @@ pub fn precompile_call(
     opcode: &Opcode,
     state: &mut VMState,
     statistics: &mut VmStatistics,
-) -> Result<(), EraVmError> {
+    precompiles: &PrecompileRegistry,
+) -> Result<Option<PrecompileError>, EraVmError> {
     let (src0, src1) = address_operands_read(vm, opcode)?;
     let aux_data = PrecompileAuxData::from_u256(src1.value);
     vm.decrease_gas(aux_data.extra_ergs_cost)?;
@@ -43,26 +35,16 @@ pub fn precompile_call(
 
     let address_bytes = vm.current_context()?.contract_address.0;
     let address_low = u16::from_le_bytes([address_bytes[19], address_bytes[18]]);
-    let heaps = &mut vm.heaps;
-
-    match address_low {
-        KECCAK256_ROUND_FUNCTION_PRECOMPILE_ADDRESS => {
-            statistics.keccak256_cycles += keccak256_rounds_function(abi_key, heaps)?;
-        }
-        SHA256_ROUND_FUNCTION_PRECOMPILE_ADDRESS => {
-            statistics.sha256_cycles += sha256_rounds_function(abi_key, heaps)?;
-        }
-        ECRECOVER_INNER_FUNCTION_PRECOMPILE_ADDRESS => {
-            statistics.ecrecover_cycles += ecrecover_function(abi_key, heaps)?;
+    // A precompile call to an address without a precompile may be used just to burn gas
+    let failure = match precompiles.get(address_low) {
+        Some(precompile) => {
+            let output = precompile.execute_precompile(abi_key, &mut vm.heaps, &vm.config)?;
+            statistics.add_precompile_cycles(address_low, output.cycles);
+            output.failure
         }
-        SECP256R1_VERIFY_PRECOMPILE_ADDRESS => {
-            statistics.secp255r1_verify_cycles += secp256r1_verify_function(abi_key, heaps)?;
-        }
-        _ => {
-            // A precompile call may be used just to burn gas
-        }
-    }
+        None => None,
+    };
     address_operands_store(vm, opcode, TaggedValue::new_raw_integer(1.into()))?;
 
-    Ok(())
+    Ok(failure)
 }
@@ -32,6 +32,44 @@ fn get_result(
     Ok(TaggedValue::new_pointer(FatPointer::encode(&result)))
 }
 
+// The heaps a far call frame can point into: its own and the ones its callees returned data in
+fn frame_heap_ids(vm: &mut Execution) -> Result<Vec<u32>, EraVmError> {
+    let context = vm.current_context_mut()?;
+    let mut heap_ids = std::mem::take(&mut context.returned_heaps);
+    heap_ids.extend([context.heap_id, context.aux_heap_id]);
+    Ok(heap_ids)
+}
+
+fn points_into(value: &TaggedValue, page: u32) -> bool {
+    value.is_pointer && FatPointer::decode(value.value).page == page
+}
+
+// Once a far call frame returns its heaps can be reused, except the one the returned pointer points into.
+// The caller keeps that one until neither its registers nor its stack point into it anymore.
+fn deallocate_heaps(
+    vm: &mut Execution,
+    heap_ids: Vec<u32>,
+    result: &TaggedValue,
+) -> Result<(), EraVmError> {
+    let returned_page = FatPointer::decode(result.value).page;
+    let context = vm.current_context_mut()?;
+    let (mut kept, mut unreachable): (Vec<u32>, Vec<u32>) =
+        heap_ids.into_iter().partition(|id| *id == returned_page);
+    // The registers were just cleared, so only the result and the stack can point into older ones
+    for id in std::mem::take(&mut context.returned_heaps) {
+        if points_into(result, id) || context.stack.stack.iter().any(|v| points_into(v, id)) {
+            kept.push(id);
+        } else {
+            unreachable.push(id);
+        }
+    }
+    context.returned_heaps = kept;
+    for id in unreachable {
+        vm.heaps.deallocate(id);
+    }
+    Ok(())
+}
+
 pub fn ret(
     vm: &mut Execution,
     opcode: &Opcode,
@@ -62,7 +100,9 @@ pub fn ret(
         vm.register_context_u128 = 0_u128;
         vm.clear_registers();
         vm.set_register(1, result);
+        let heap_ids = frame_heap_ids(vm)?;
         let previous_frame = vm.pop_frame()?;
+        deallocate_heaps(vm, heap_ids, &result)?;
         vm.increase_gas((previous_frame.gas_left - previous_frame.stipend).0)?;
         if is_failure {
             state.rollback(previous_frame.snapshot);
@@ -104,7 +144,9 @@ pub fn inexplicit_panic(vm: &mut Execution, state: &mut VMState) -> Result<bool,
         vm.register_context_u128 = 0_u128;
         vm.clear_registers();
         vm.set_register(1, result);
+        let heap_ids = frame_heap_ids(vm)?;
         let previous_frame = vm.pop_frame()?;
+        deallocate_heaps(vm, heap_ids, &result)?;
         vm.increase_gas((previous_frame.gas_left - previous_frame.stipend).0)?;
         vm.current_frame_mut()?.pc = previous_frame.exception_handler;
         state.rollback(previous_frame.snapshot);
@@ -0,0 +1,11 @@
+use crate::eravm_error::EraVmError;
+use crate::{
+    execution::{Execution, STATIC_MEMORY_HEAP},
+    opcode::Opcode,
+};
+
+use super::heap_read::read_from_heap;
+
+pub fn static_memory_read(vm: &mut Execution, opcode: &Opcode) -> Result<(), EraVmError> {
+    read_from_heap(vm, opcode, STATIC_MEMORY_HEAP)
+}
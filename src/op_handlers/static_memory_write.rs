@@ -0,0 +1,12 @@
+use crate::eravm_error::EraVmError;
+use crate::{
+    execution::{Execution, STATIC_MEMORY_HEAP},
+    opcode::Opcode,
+};
+
+use super::heap_write::write_to_heap;
+
+pub fn static_memory_write(vm: &mut Execution, opcode: &Opcode) -> Result<(), EraVmError> {
+    write_to_heap(vm, opcode, STATIC_MEMORY_HEAP)?;
+    Ok(())
+}
@@ -1,3 +1,4 @@
+use crate::config::ProtocolVersion;
 use crate::eravm_error::EraVmError;
 use crate::eravm_error::OpcodeError;
 use lazy_static::lazy_static;
@@ -58,17 +59,33 @@ pub struct Opcode {
     pub gas_cost: u32,
 }
 lazy_static! {
-    static ref OPCODE_TABLE: Vec<OpcodeVariant> =
-        zkevm_opcode_defs::synthesize_opcode_decoding_tables(11, zkevm_opcode_defs::ISAVersion(2));
+    pub(crate) static ref OPCODE_TABLE: Vec<OpcodeVariant> =
+        opcode_table(ProtocolVersion::default());
+    static ref OPCODE_TABLE_1_4: Vec<OpcodeVariant> = opcode_table(ProtocolVersion::Vm1_4);
+}
+
+fn opcode_table(version: ProtocolVersion) -> Vec<OpcodeVariant> {
+    zkevm_opcode_defs::synthesize_opcode_decoding_tables(OPCODES_TABLE_WIDTH, version.isa_version())
+}
+
+/// The decoding table for the instruction set of the given version
+pub(crate) fn decoding_table(version: ProtocolVersion) -> &'static [OpcodeVariant] {
+    match version {
+        ProtocolVersion::Vm1_4 => &OPCODE_TABLE_1_4,
+        ProtocolVersion::Vm1_5 | ProtocolVersion::Vm1_5EvmInterpreter => &OPCODE_TABLE,
+    }
 }
 
 impl Opcode {
     const VARIANT_MASK: u64 = (1u64 << OPCODES_TABLE_WIDTH) - 1;
 
-    pub fn try_from_raw_opcode_test_encode(raw_op: u128) -> Result<Self, EraVmError> {
+    pub fn try_from_raw_opcode_test_encode(
+        raw_op: u128,
+        version: ProtocolVersion,
+    ) -> Result<Self, EraVmError> {
         // First 11 bits
         let variant_bits = (raw_op as u64) & Self::VARIANT_MASK;
-        let opcode_zksync = OPCODE_TABLE[variant_bits as usize];
+        let opcode_zksync = decoding_table(version)[variant_bits as usize];
         let [flag0_set, flag1_set] = match opcode_zksync.opcode {
             Variant::Ptr(_) => [false, opcode_zksync.flags[0]],
             _ => opcode_zksync.flags,
@@ -104,10 +121,10 @@ impl Opcode {
             gas_cost,
         })
     }
-    pub fn try_from_raw_opcode(raw_op: u64) -> Result<Self, EraVmError> {
+    pub fn try_from_raw_opcode(raw_op: u64, version: ProtocolVersion) -> Result<Self, EraVmError> {
         // First 11 bits
         let variant_bits = raw_op & 2047;
-        let opcode_zksync = OPCODE_TABLE[variant_bits as usize];
+        let opcode_zksync = decoding_table(version)[variant_bits as usize];
         let [flag0_set, flag1_set] = match opcode_zksync.opcode {
             Variant::Ptr(_) => [false, opcode_zksync.flags[0]],
             _ => opcode_zksync.flags,
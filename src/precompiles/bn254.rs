@@ -0,0 +1,219 @@
+use bn::{pairing_batch, AffineG1, AffineG2, Fq, Fq2, Fr, Group, Gt, G1, G2};
+use u256::U256;
+
+use super::{
+    precompile_abi_in_log, word_address, Precompile, PrecompileCallABI, PrecompileOutput,
+    DEFAULT_NUM_ROUNDS,
+};
+use crate::{
+    config::VmConfig,
+    eravm_error::{EraVmError, PrecompileError},
+    heaps::{HeapMut, Heaps},
+};
+
+// Same addresses as the Ethereum precompiles, the system contracts deployed there forward to these
+pub const ECADD_PRECOMPILE_ADDRESS: u16 = 0x06;
+pub const ECMUL_PRECOMPILE_ADDRESS: u16 = 0x07;
+pub const ECPAIRING_PRECOMPILE_ADDRESS: u16 = 0x08;
+
+/// Words of a (G1, G2) pair in the pairing check input
+const PAIRING_PAIR_WORDS: u32 = 6;
+
+/// Upper bound on the number of pairs of a pairing check
+pub const ECPAIRING_MAX_PAIRS: u64 = 256;
+
+/// Adds the two G1 points `[x1, y1, x2, y2]`.
+/// Writes the success marker and `[x, y]` of the sum.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct EcAddPrecompile;
+
+impl Precompile for EcAddPrecompile {
+    fn execute_precompile(
+        &self,
+        abi_key: U256,
+        heaps: &mut Heaps,
+        config: &VmConfig,
+    ) -> Result<PrecompileOutput, EraVmError> {
+        let params = precompile_abi_in_log(abi_key);
+        let mut read_heap = heaps.try_get_mut(params.memory_page_to_read)?;
+        let sum = read_input(&mut read_heap, config, &params, 0)
+            .and_then(|[x1, y1, x2, y2]| ecadd_inner((x1, y1), (x2, y2)));
+        let failure = write_output(heaps, &params, sum)?;
+
+        Ok(PrecompileOutput {
+            cycles: DEFAULT_NUM_ROUNDS,
+            failure,
+        })
+    }
+}
+
+/// Multiplies the G1 point `[x, y]` by the scalar `[s]`.
+/// Writes the success marker and `[x, y]` of the product.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct EcMulPrecompile;
+
+impl Precompile for EcMulPrecompile {
+    fn execute_precompile(
+        &self,
+        abi_key: U256,
+        heaps: &mut Heaps,
+        config: &VmConfig,
+    ) -> Result<PrecompileOutput, EraVmError> {
+        let params = precompile_abi_in_log(abi_key);
+        let mut read_heap = heaps.try_get_mut(params.memory_page_to_read)?;
+        let product = read_input(&mut read_heap, config, &params, 0)
+            .and_then(|[x, y, scalar]| ecmul_inner((x, y), scalar));
+        let failure = write_output(heaps, &params, product)?;
+
+        Ok(PrecompileOutput {
+            cycles: DEFAULT_NUM_ROUNDS,
+            failure,
+        })
+    }
+}
+
+/// Checks that the product of the pairings of `precompile_interpreted_data` (G1, G2) pairs is one.
+/// Each pair is `[x1, y1, x2_im, x2_re, y2_im, y2_re]`, G2 coordinates in the Ethereum order.
+/// Writes the success marker and `[1]` if the check passes, `[0]` otherwise.
+/// More than `ECPAIRING_MAX_PAIRS` pairs fail without reading the input.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct EcPairingPrecompile;
+
+impl Precompile for EcPairingPrecompile {
+    fn execute_precompile(
+        &self,
+        abi_key: U256,
+        heaps: &mut Heaps,
+        config: &VmConfig,
+    ) -> Result<PrecompileOutput, EraVmError> {
+        let params = precompile_abi_in_log(abi_key);
+        let num_pairs = params.precompile_interpreted_data;
+        if num_pairs > ECPAIRING_MAX_PAIRS {
+            let failure =
+                write_output::<1>(heaps, &params, Err(PrecompileError::EcPairingTooManyPairs))?;
+            return Ok(PrecompileOutput { cycles: 1, failure });
+        }
+
+        let mut read_heap = heaps.try_get_mut(params.memory_page_to_read)?;
+        let result = (0..num_pairs as u32)
+            .map(|i| read_input(&mut read_heap, config, &params, i * PAIRING_PAIR_WORDS))
+            .collect::<Result<Vec<[U256; PAIRING_PAIR_WORDS as usize]>, _>>()
+            .and_then(|pairs| ecpairing_inner(&pairs))
+            .map(|holds| [U256::from(holds as u64)]);
+        let failure = write_output(heaps, &params, result)?;
+
+        Ok(PrecompileOutput {
+            cycles: num_pairs as usize,
+            failure,
+        })
+    }
+}
+
+pub fn ecadd_inner(p1: (U256, U256), p2: (U256, U256)) -> Result<[U256; 2], PrecompileError> {
+    let sum = g1_point(p1)? + g1_point(p2)?;
+    Ok(g1_words(sum))
+}
+
+pub fn ecmul_inner(p: (U256, U256), scalar: U256) -> Result<[U256; 2], PrecompileError> {
+    let mut bytes = [0u8; 32];
+    scalar.to_big_endian(&mut bytes);
+    // scalars are reduced modulo the group order, which leaves the product unchanged
+    let scalar = Fr::from_slice(&bytes).map_err(|_| PrecompileError::Bn254InvalidCoordinate)?;
+    Ok(g1_words(g1_point(p)? * scalar))
+}
+
+pub fn ecpairing_inner(pairs: &[[U256; 6]]) -> Result<bool, PrecompileError> {
+    let mut points = Vec::with_capacity(pairs.len());
+    for [x1, y1, x2_im, x2_re, y2_im, y2_re] in pairs {
+        let g1 = g1_point((*x1, *y1))?;
+        let g2 = g2_point((*x2_re, *x2_im), (*y2_re, *y2_im))?;
+        points.push((g1, g2));
+    }
+    Ok(pairing_batch(&points) == Gt::one())
+}
+
+// Reads `N` words starting `first_word` words past the input offset
+fn read_input<const N: usize>(
+    read_heap: &mut HeapMut,
+    config: &VmConfig,
+    params: &PrecompileCallABI,
+    first_word: u32,
+) -> Result<[U256; N], PrecompileError> {
+    // if the last word fits in the heap, so do the ones before it
+    word_address(params.input_memory_offset, first_word + N as u32 - 1)
+        .ok_or(PrecompileError::MemoryOutOfBounds)?;
+    let addr = |offset: u32| (params.input_memory_offset + first_word + offset) * 32;
+    Ok(std::array::from_fn(|i| {
+        read_heap
+            .expanded_read(addr(i as u32), config.memory_growth_ergs_per_byte)
+            .0
+    }))
+}
+
+// Writes the success marker followed by the output, or a zero marker and zeroed output on failure.
+// Returns the failure, if any. Nothing is written if the output doesn't fit in the heap.
+fn write_output<const N: usize>(
+    heaps: &mut Heaps,
+    params: &PrecompileCallABI,
+    output: Result<[U256; N], PrecompileError>,
+) -> Result<Option<PrecompileError>, EraVmError> {
+    if word_address(params.output_memory_offset, N as u32).is_none() {
+        return Ok(Some(PrecompileError::MemoryOutOfBounds));
+    }
+    let (marker, words) = match output {
+        Ok(words) => (U256::one(), words),
+        Err(_) => (U256::zero(), [U256::zero(); N]),
+    };
+    let mut write_heap = heaps.try_get_mut(params.memory_page_to_write)?;
+    let addr = |offset: u32| (params.output_memory_offset + offset) * 32;
+    write_heap.store(addr(0), marker);
+    for (i, word) in words.into_iter().enumerate() {
+        write_heap.store(addr(i as u32 + 1), word);
+    }
+    Ok(output.err())
+}
+
+fn fq(value: U256) -> Result<Fq, PrecompileError> {
+    let mut bytes = [0u8; 32];
+    value.to_big_endian(&mut bytes);
+    Fq::from_slice(&bytes).map_err(|_| PrecompileError::Bn254InvalidCoordinate)
+}
+
+// (0, 0) encodes the point at infinity
+fn g1_point((x, y): (U256, U256)) -> Result<G1, PrecompileError> {
+    let (x, y) = (fq(x)?, fq(y)?);
+    if x == Fq::zero() && y == Fq::zero() {
+        return Ok(G1::zero());
+    }
+    AffineG1::new(x, y)
+        .map(G1::from)
+        .map_err(|_| PrecompileError::Bn254InvalidPoint)
+}
+
+// Coordinates are given as (real, imaginary)
+fn g2_point((x_re, x_im): (U256, U256), (y_re, y_im): (U256, U256)) -> Result<G2, PrecompileError> {
+    let x = Fq2::new(fq(x_re)?, fq(x_im)?);
+    let y = Fq2::new(fq(y_re)?, fq(y_im)?);
+    if x == Fq2::zero() && y == Fq2::zero() {
+        return Ok(G2::zero());
+    }
+    AffineG2::new(x, y)
+        .map(G2::from)
+        .map_err(|_| PrecompileError::Bn254InvalidPoint)
+}
+
+fn g1_words(point: G1) -> [U256; 2] {
+    // the point at infinity has no affine form and is encoded as (0, 0)
+    let Some(affine) = AffineG1::from_jacobian(point) else {
+        return [U256::zero(); 2];
+    };
+    [fq_word(affine.x()), fq_word(affine.y())]
+}
+
+fn fq_word(value: Fq) -> U256 {
+    let mut bytes = [0u8; 32];
+    value
+        .to_big_endian(&mut bytes)
+        .expect("field elements fit in 32 bytes");
+    U256::from_big_endian(&bytes)
+}
@@ -22,16 +22,22 @@ use super::*;
 pub struct ECRecoverPrecompile;
 
 impl Precompile for ECRecoverPrecompile {
-    fn execute_precompile(&mut self, query: U256, heaps: &mut Heaps) -> Result<usize, EraVmError> {
+    fn execute_precompile(
+        &self,
+        query: U256,
+        heaps: &mut Heaps,
+        config: &VmConfig,
+    ) -> Result<PrecompileOutput, EraVmError> {
         let precompile_call_params = query;
         let params = precompile_abi_in_log(precompile_call_params);
         let addr = |offset: u32| (params.output_memory_offset + offset) * 32;
 
-        let read_heap = heaps.try_get_mut(params.memory_page_to_read)?;
-        let (hash_value, _) = read_heap.expanded_read(addr(0));
-        let (v_value, _) = read_heap.expanded_read(addr(1));
-        let (r_value, _) = read_heap.expanded_read(addr(2));
-        let (s_value, _) = read_heap.expanded_read(addr(3));
+        let mut read_heap = heaps.try_get_mut(params.memory_page_to_read)?;
+        let ergs_per_byte = config.memory_growth_ergs_per_byte;
+        let (hash_value, _) = read_heap.expanded_read(addr(0), ergs_per_byte);
+        let (v_value, _) = read_heap.expanded_read(addr(1), ergs_per_byte);
+        let (r_value, _) = read_heap.expanded_read(addr(2), ergs_per_byte);
+        let (s_value, _) = read_heap.expanded_read(addr(3), ergs_per_byte);
 
         // read everything as bytes for ecrecover purposes
         let mut buffer = [0u8; 32];
@@ -47,25 +53,25 @@ impl Precompile for ECRecoverPrecompile {
         v_value.to_big_endian(&mut buffer[..]);
         let v = buffer[31];
 
-        if v != 0 && v != 1 {
-            return Err(PrecompileError::EcRecoverInvalidByte.into());
-        }
-
-        let pk = ecrecover_inner(&hash, &r_bytes, &s_bytes, v);
+        let address = if v != 0 && v != 1 {
+            Err(PrecompileError::EcRecoverInvalidByte)
+        } else {
+            // here it may be possible to have non-recoverable k*G point, so can fail
+            ecrecover_inner(&hash, &r_bytes, &s_bytes, v).and_then(get_address_from_pk)
+        };
 
-        // here it may be possible to have non-recoverable k*G point, so can fail
-        let (marker, result) = match pk {
-            Ok(recovered_pubkey) => (
-                U256::one(),
-                U256::from_big_endian(&get_address_from_pk(recovered_pubkey)?),
-            ),
-            _ => (U256::zero(), U256::zero()),
+        let (marker, result) = match address {
+            Ok(address) => (U256::one(), U256::from_big_endian(&address)),
+            Err(_) => (U256::zero(), U256::zero()),
         };
-        let write_heap = heaps.try_get_mut(params.memory_page_to_write)?;
+        let mut write_heap = heaps.try_get_mut(params.memory_page_to_write)?;
         write_heap.store(addr(0), marker);
         write_heap.store(addr(1), result);
 
-        Ok(DEFAULT_NUM_ROUNDS)
+        Ok(PrecompileOutput {
+            cycles: DEFAULT_NUM_ROUNDS,
+            failure: address.err(),
+        })
     }
 }
 
@@ -75,13 +81,14 @@ pub fn ecrecover_inner(
     r: &[u8; 32],
     s: &[u8; 32],
     rec_id: u8,
-) -> Result<VerifyingKey, ()> {
+) -> Result<VerifyingKey, PrecompileError> {
     // r, s
     let mut signature = [0u8; 64];
     signature[..32].copy_from_slice(r);
     signature[32..].copy_from_slice(s);
     // we expect pre-validation, so this check always works
-    let signature = Signature::try_from(&signature[..]).map_err(|_| ())?;
+    let signature = Signature::try_from(&signature[..])
+        .map_err(|_| PrecompileError::EcRecoverInvalidSignature)?;
     let recid = RecoveryId::try_from(rec_id).unwrap();
 
     recover_no_malleability_check(digest, signature, recid)
@@ -91,11 +98,11 @@ fn recover_no_malleability_check(
     digest: &[u8; 32],
     signature: k256::ecdsa::Signature,
     recovery_id: k256::ecdsa::RecoveryId,
-) -> Result<VerifyingKey, ()> {
+) -> Result<VerifyingKey, PrecompileError> {
     let (r, s) = signature.split_scalars();
-    let z = <Scalar as Reduce<k256::U256>>::reduce_bytes(
-        &bits2field::<k256::Secp256k1>(digest).map_err(|_| ())?,
-    );
+    let field = bits2field::<k256::Secp256k1>(digest)
+        .map_err(|_| PrecompileError::EcRecoverInvalidSignature)?;
+    let z = <Scalar as Reduce<k256::U256>>::reduce_bytes(&field);
 
     let mut r_bytes: GenericArray<u8, <k256::Secp256k1 as Curve>::FieldBytesSize> = r.to_repr();
     if recovery_id.is_x_reduced() {
@@ -109,14 +116,14 @@ fn recover_no_malleability_check(
                 )
             }
             // No reduction should happen here if r was reduced
-            None => return Err(()),
+            None => return Err(PrecompileError::NonRecoverablePoint),
         };
     }
 
     let y = AffinePoint::decompress(&r_bytes, u8::from(recovery_id.is_y_odd()).into());
 
     if y.is_none().into() {
-        return Err(());
+        return Err(PrecompileError::NonRecoverablePoint);
     }
 
     let y = ProjectivePoint::from(y.unwrap());
@@ -124,24 +131,24 @@ fn recover_no_malleability_check(
     let u1 = -(r_inv * z);
     let u2 = r_inv * *s;
     let pk = ProjectivePoint::lincomb(&ProjectivePoint::GENERATOR, &u1, &y, &u2);
-    let vk = VerifyingKey::from_affine(pk.into()).map_err(|_| ())?;
+    let vk =
+        VerifyingKey::from_affine(pk.into()).map_err(|_| PrecompileError::NonRecoverablePoint)?;
 
     // Ensure signature verifies with the recovered key
-    let field = bits2field::<k256::Secp256k1>(digest).map_err(|_| ())?;
     // here we actually skip a high-s check (that should never be there at the first place and should be checked by caller)
     k256::ecdsa::hazmat::verify_prehashed(&vk.as_affine().into(), &field, &signature)
-        .map_err(|_| ())?;
+        .map_err(|_| PrecompileError::EcRecoverInvalidSignature)?;
 
     Ok(vk)
 }
 
-fn get_address_from_pk(pk: VerifyingKey) -> Result<[u8; 32], EraVmError> {
+fn get_address_from_pk(pk: VerifyingKey) -> Result<[u8; 32], PrecompileError> {
     let pk = k256::PublicKey::from(pk);
     let affine_point = *pk.as_affine();
     let pk_bytes = affine_point.to_encoded_point(false);
     let pk_bytes_ref: &[u8] = pk_bytes.as_ref();
     if pk_bytes_ref.len() != 65 && pk_bytes_ref[0] != 0x04 {
-        return Err(PrecompileError::NonRecoverablePoint.into());
+        return Err(PrecompileError::NonRecoverablePoint);
     }
     let address_hash = Keccak256::digest(&pk_bytes_ref[1..]);
 
@@ -152,6 +159,7 @@ fn get_address_from_pk(pk: VerifyingKey) -> Result<[u8; 32], EraVmError> {
     Ok(address)
 }
 
-pub fn ecrecover_function(abi: U256, heaps: &mut Heaps) -> Result<usize, EraVmError> {
-    ECRecoverPrecompile.execute_precompile(abi, heaps)
+/// Runs the precompile with the default pricing
+pub fn ecrecover_function(abi: U256, heaps: &mut Heaps) -> Result<PrecompileOutput, EraVmError> {
+    ECRecoverPrecompile.execute_precompile(abi, heaps, &VmConfig::default())
 }
@@ -1,9 +1,6 @@
-use super::get_hasher_state;
-use super::{precompile_abi_in_log, Precompile};
-use crate::{eravm_error::EraVmError, heaps::Heaps};
+use super::{precompile_abi_in_log, Precompile, PrecompileOutput};
+use crate::{config::VmConfig, eravm_error::EraVmError, heaps::Heaps};
 use u256::U256;
-pub use zkevm_opcode_defs::sha2::Digest;
-pub use zkevm_opcode_defs::sha3::Keccak256;
 
 pub const KECCAK_RATE_BYTES: usize = 136;
 pub const KECCAK_ROUND_COUNT: usize = 24;
@@ -58,23 +55,107 @@ impl ByteBuffer {
     }
 }
 
-fn hash_as_bytes32(hash: [u64; 25]) -> [u8; 32] {
-    let mut result = [0; 32];
-    for i in 0..4 {
-        result[i * 8..(i + 1) * 8].copy_from_slice(&hash[i].to_le_bytes());
+const ROUND_CONSTANTS: [u64; KECCAK_ROUND_COUNT] = [
+    0x0000000000000001,
+    0x0000000000008082,
+    0x800000000000808a,
+    0x8000000080008000,
+    0x000000000000808b,
+    0x0000000080000001,
+    0x8000000080008081,
+    0x8000000000008009,
+    0x000000000000008a,
+    0x0000000000000088,
+    0x0000000080008009,
+    0x000000008000000a,
+    0x000000008000808b,
+    0x800000000000008b,
+    0x8000000000008089,
+    0x8000000000008003,
+    0x8000000000008002,
+    0x8000000000000080,
+    0x000000000000800a,
+    0x800000008000000a,
+    0x8000000080008081,
+    0x8000000000008080,
+    0x0000000080000001,
+    0x8000000080008008,
+];
+
+// The rho and pi steps combined: the lanes in the order pi moves them, and their rotations
+const LANE_ORDER: [usize; 24] = [
+    10, 7, 11, 17, 18, 3, 5, 16, 8, 21, 24, 4, 15, 23, 19, 13, 12, 2, 20, 14, 22, 9, 6, 1,
+];
+const ROTATIONS: [u32; 24] = [
+    1, 3, 6, 10, 15, 21, 28, 36, 45, 55, 2, 14, 27, 41, 56, 8, 25, 43, 62, 18, 39, 61, 20, 44,
+];
+
+/// The Keccak-f[1600] state, lane `x + 5 * y` at index `x + 5 * y`.
+/// The precompile outputs the state after absorbing its input, the contract calling it finishes
+/// the hash over as many calls as it needs.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct KeccakState(pub [u64; 25]);
+
+impl KeccakState {
+    /// XORs `block` into the rate part of the state and applies the permutation
+    pub fn absorb(&mut self, block: &[u8; KECCAK_RATE_BYTES]) {
+        for (lane, bytes) in self.0.iter_mut().zip(block.chunks_exact(8)) {
+            *lane ^= u64::from_le_bytes(bytes.try_into().unwrap());
+        }
+        self.permute();
+    }
+
+    fn permute(&mut self) {
+        let a = &mut self.0;
+        for round_constant in ROUND_CONSTANTS {
+            // theta
+            let c: [u64; 5] =
+                std::array::from_fn(|x| a[x] ^ a[x + 5] ^ a[x + 10] ^ a[x + 15] ^ a[x + 20]);
+            for x in 0..5 {
+                let d = c[(x + 4) % 5] ^ c[(x + 1) % 5].rotate_left(1);
+                for y in (0..25).step_by(5) {
+                    a[y + x] ^= d;
+                }
+            }
+            // rho and pi
+            let mut last = a[1];
+            for (&lane, &rotation) in LANE_ORDER.iter().zip(&ROTATIONS) {
+                let current = a[lane];
+                a[lane] = last.rotate_left(rotation);
+                last = current;
+            }
+            // chi
+            for y in (0..25).step_by(5) {
+                let row: [u64; 5] = a[y..y + 5].try_into().unwrap();
+                for (x, lane) in a[y..y + 5].iter_mut().enumerate() {
+                    *lane = row[x] ^ (!row[(x + 1) % 5] & row[(x + 2) % 5]);
+                }
+            }
+            // iota
+            a[0] ^= round_constant;
+        }
+    }
+
+    /// The first 256 bits of the state, which are the hash once the whole input was absorbed
+    pub fn hash(&self) -> [u8; 32] {
+        let mut result = [0; 32];
+        for (bytes, lane) in result.chunks_mut(8).zip(self.0) {
+            bytes.copy_from_slice(&lane.to_le_bytes());
+        }
+        result
     }
-    result
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
-struct Keccak256Precompile;
+pub struct Keccak256Precompile;
 
 impl Precompile for Keccak256Precompile {
     fn execute_precompile(
-        &mut self,
+        &self,
         abi_key: U256,
         heaps: &mut Heaps,
-    ) -> Result<usize, EraVmError> {
+        config: &VmConfig,
+    ) -> Result<PrecompileOutput, EraVmError> {
         let mut full_round_padding = [0u8; KECCAK_RATE_BYTES];
         full_round_padding[0] = 0x01;
         full_round_padding[KECCAK_RATE_BYTES - 1] = 0x80;
@@ -93,8 +174,8 @@ impl Precompile for Keccak256Precompile {
 
         let mut input_buffer = ByteBuffer::default();
 
-        let mut hasher = Keccak256::new();
-        let heap_to_read = heaps.try_get_mut(params.memory_page_to_read)?;
+        let mut state = KeccakState::default();
+        let mut heap_to_read = heaps.try_get_mut(params.memory_page_to_read)?;
 
         for round in 0..num_rounds {
             let is_last = round == num_rounds - 1;
@@ -122,7 +203,8 @@ impl Precompile for Keccak256Precompile {
                 };
 
                 if should_read {
-                    let (data, _) = heap_to_read.expanded_read(read_addr as u32 * 32);
+                    let (data, _) = heap_to_read
+                        .expanded_read(read_addr as u32 * 32, config.memory_growth_ergs_per_byte);
                     data.to_big_endian(&mut bytes32_buffer[..]);
                     input_byte_offset += meaningful_bytes_in_query;
                     bytes_left -= meaningful_bytes_in_query;
@@ -144,18 +226,21 @@ impl Precompile for Keccak256Precompile {
                 }
             }
 
-            hasher.update(block);
+            state.absorb(&block);
         }
-        let state: [u64; 25] = get_hasher_state(hasher.decompose().0, KECCAK_ROUND_COUNT)?;
-        let hash = U256::from_big_endian(&hash_as_bytes32(state));
+        let hash = U256::from_big_endian(&state.hash());
         heaps
             .try_get_mut(params.memory_page_to_write)?
             .store(params.output_memory_offset * 32, hash);
 
-        Ok(num_rounds)
+        Ok(PrecompileOutput::success(num_rounds))
     }
 }
 
-pub fn keccak256_rounds_function(abi_key: U256, heaps: &mut Heaps) -> Result<usize, EraVmError> {
-    Keccak256Precompile.execute_precompile(abi_key, heaps)
+/// Runs the precompile with the default pricing
+pub fn keccak256_rounds_function(
+    abi_key: U256,
+    heaps: &mut Heaps,
+) -> Result<PrecompileOutput, EraVmError> {
+    Keccak256Precompile.execute_precompile(abi_key, heaps, &VmConfig::default())
 }
@@ -1,18 +1,48 @@
-use crate::{eravm_error::EraVmError, heaps::Heaps};
-use std::{mem::size_of, ptr};
+use crate::{
+    config::VmConfig,
+    eravm_error::{EraVmError, PrecompileError},
+    heaps::Heaps,
+};
 use u256::U256;
-use zkevm_opcode_defs::{sha2::Sha256VarCore, sha3::Keccak256Core};
 
+pub mod bn254;
 pub mod ecrecover;
 pub mod keccak256;
+pub mod modexp;
+pub mod registry;
 pub mod secp256r1_verify;
 pub mod sha256;
 
 const DEFAULT_NUM_ROUNDS: usize = 1;
 
-pub trait Precompile: std::fmt::Debug {
-    fn execute_precompile(&mut self, abi_key: U256, heaps: &mut Heaps)
-        -> Result<usize, EraVmError>;
+/// A function run natively by the vm when a contract at its address issues a precompile call.
+/// It reads its input from and writes its output to the heaps described by `abi_key`, see
+/// `PrecompileCallABI`, growing them at the price in `config`. Errors are reserved for failures of
+/// the vm itself, invalid inputs are reported through `PrecompileOutput::failure`.
+pub trait Precompile: std::fmt::Debug + Send + Sync {
+    fn execute_precompile(
+        &self,
+        abi_key: U256,
+        heaps: &mut Heaps,
+        config: &VmConfig,
+    ) -> Result<PrecompileOutput, EraVmError>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PrecompileOutput {
+    pub cycles: usize,
+    /// Why the call failed, if it did. A failed call writes a zero success marker to its output
+    /// and the execution goes on, so this is only reported for tracing.
+    pub failure: Option<PrecompileError>,
+}
+
+impl PrecompileOutput {
+    pub fn success(cycles: usize) -> Self {
+        Self {
+            cycles,
+            failure: None,
+        }
+    }
 }
 
 pub struct PrecompileCallABI {
@@ -52,33 +82,10 @@ pub fn precompile_abi_in_log(abi_key: U256) -> PrecompileCallABI {
     PrecompileCallABI::from_u256(abi_key)
 }
 
-struct State<T> {
-    state: T,
-    round_count: usize,
-}
-
-// check at compile time that the state struct is of the same size as the hashers
-const fn _assert_eq_size<T, K>() {
-    let res = size_of::<T>() == size_of::<K>();
-    if !res {
-        panic!();
-    }
-}
-const _: () = _assert_eq_size::<State<[u32; 8]>, Sha256VarCore>();
-const _: () = _assert_eq_size::<State<[u64; 25]>, Keccak256Core>();
-
-fn get_hasher_state<T, Core>(core: Core, round_count: usize) -> Result<T, EraVmError> {
-    // casts the hasher ptr to the CoreWrapper struct
-    let raw_ptr = &core as *const _ as *const State<T>;
-    // this is not unsafe since we are replicating the structure(thus same layout) of the original ptr
-    // this a hack that allows us to access private fields
-    // also ptr::read does not moved the value from memory
-    let core_r = unsafe { ptr::read(raw_ptr) };
-
-    // sanity check to make sure the cast was ok
-    if core_r.round_count == round_count {
-        Ok(core_r.state)
-    } else {
-        Err(EraVmError::OutOfGas)
-    }
+/// Byte address of the word `offset` words past `first_word`.
+/// `None` if the word would end past the last addressable byte of a heap.
+pub(crate) fn word_address(first_word: u32, offset: u32) -> Option<u32> {
+    let address = first_word.checked_add(offset)?.checked_mul(32)?;
+    address.checked_add(32)?;
+    Some(address)
 }
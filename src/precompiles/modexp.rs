@@ -0,0 +1,147 @@
+use num_bigint::BigUint;
+use u256::U256;
+
+use super::{precompile_abi_in_log, word_address, Precompile, PrecompileOutput};
+use crate::{
+    config::VmConfig,
+    eravm_error::{EraVmError, PrecompileError},
+    heaps::Heaps,
+};
+
+// Same address as the Ethereum precompile
+pub const MODEXP_PRECOMPILE_ADDRESS: u16 = 0x05;
+
+/// Upper bound on the length of the base, exponent and modulus, in bytes
+pub const MODEXP_MAX_INPUT_LENGTH: usize = 1024;
+
+/// Computes `base ** exponent % modulus` on the EIP-198 input
+/// `[base_len, exp_len, mod_len]` followed by the big endian base, exponent and modulus bytes, read
+/// from `input_memory_offset`. Input past the end of the heap reads as zeros.
+/// Writes the success marker and `mod_len` bytes of result, left aligned in the following words.
+/// A failed call writes a zero marker and zeroes those words instead, unless the output itself
+/// doesn't fit in the heap.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ModexpPrecompile;
+
+impl Precompile for ModexpPrecompile {
+    fn execute_precompile(
+        &self,
+        abi_key: U256,
+        heaps: &mut Heaps,
+        config: &VmConfig,
+    ) -> Result<PrecompileOutput, EraVmError> {
+        let params = precompile_abi_in_log(abi_key);
+        let ergs_per_byte = config.memory_growth_ergs_per_byte;
+
+        let mut read_heap = heaps.try_get_mut(params.memory_page_to_read)?;
+        let mut read_word = |offset: u32| {
+            word_address(params.input_memory_offset, offset)
+                .map(|address| read_heap.expanded_read(address, ergs_per_byte).0)
+                .ok_or(PrecompileError::MemoryOutOfBounds)
+        };
+        let lengths = [0, 1, 2].map(&mut read_word);
+        // a failure zeroes the words the result would take, as far as the modulus length is known
+        let failure_len = lengths[2].map_or(0, |mod_len| {
+            mod_len.min(U256::from(MODEXP_MAX_INPUT_LENGTH)).as_usize()
+        });
+        let result = input_lengths(lengths).and_then(|[base_len, exp_len, mod_len]| {
+            let input = read_bytes(&mut read_word, 3, base_len + exp_len + mod_len)?;
+            let (base, rest) = input.split_at(base_len);
+            let (exponent, modulus) = rest.split_at(exp_len);
+            Ok((
+                modexp_inner(base, exponent, modulus),
+                modexp_cycles(base_len, exponent, mod_len),
+            ))
+        });
+
+        let (marker, output, cycles, failure) = match result {
+            Ok((output, cycles)) => (U256::one(), output, cycles, None),
+            Err(err) => (U256::zero(), vec![0; failure_len], 1, Some(err)),
+        };
+        let output_words = output.len().div_ceil(32) as u32;
+        if word_address(params.output_memory_offset, output_words).is_none() {
+            // there is nowhere to write even the marker
+            return Ok(PrecompileOutput {
+                cycles: 1,
+                failure: Some(PrecompileError::MemoryOutOfBounds),
+            });
+        }
+
+        let mut write_heap = heaps.try_get_mut(params.memory_page_to_write)?;
+        let addr = |offset: u32| (params.output_memory_offset + offset) * 32;
+        write_heap.store(addr(0), marker);
+        for (i, chunk) in output.chunks(32).enumerate() {
+            let mut word = [0u8; 32];
+            word[..chunk.len()].copy_from_slice(chunk);
+            write_heap.store(addr(i as u32 + 1), U256::from_big_endian(&word));
+        }
+
+        Ok(PrecompileOutput { cycles, failure })
+    }
+}
+
+fn input_lengths(
+    lengths: [Result<U256, PrecompileError>; 3],
+) -> Result<[usize; 3], PrecompileError> {
+    let mut result = [0; 3];
+    for (length, word) in result.iter_mut().zip(lengths) {
+        let word = word?;
+        if word > U256::from(MODEXP_MAX_INPUT_LENGTH) {
+            return Err(PrecompileError::ModexpInputTooLong);
+        }
+        *length = word.as_usize();
+    }
+    Ok(result)
+}
+
+// `len` bytes from the word `first_word` of the input on
+fn read_bytes(
+    read_word: &mut impl FnMut(u32) -> Result<U256, PrecompileError>,
+    first_word: u32,
+    len: usize,
+) -> Result<Vec<u8>, PrecompileError> {
+    let mut bytes = Vec::with_capacity(len.next_multiple_of(32));
+    for offset in 0..len.div_ceil(32) as u32 {
+        let mut word = [0u8; 32];
+        read_word(first_word + offset)?.to_big_endian(&mut word);
+        bytes.extend_from_slice(&word);
+    }
+    bytes.truncate(len);
+    Ok(bytes)
+}
+
+/// `base ** exponent % modulus` as big endian bytes of the length of `modulus`.
+/// A zero modulus results in zero.
+pub fn modexp_inner(base: &[u8], exponent: &[u8], modulus: &[u8]) -> Vec<u8> {
+    let mut output = vec![0; modulus.len()];
+    if modulus.iter().all(|byte| *byte == 0) {
+        return output;
+    }
+    let result = BigUint::from_bytes_be(base)
+        .modpow(
+            &BigUint::from_bytes_be(exponent),
+            &BigUint::from_bytes_be(modulus),
+        )
+        .to_bytes_be();
+    // the result is below the modulus, so it always fits
+    output[modulus.len() - result.len()..].copy_from_slice(&result);
+    output
+}
+
+/// Cycles following the EIP-2565 pricing: the cost of a multiplication of words of the size of
+/// the largest operand, times the amount of squarings the exponent takes.
+pub fn modexp_cycles(base_len: usize, exponent: &[u8], mod_len: usize) -> usize {
+    let words = base_len.max(mod_len).div_ceil(8);
+    let multiplication_complexity = words * words;
+
+    let head_len = exponent.len().min(32);
+    let head = BigUint::from_bytes_be(&exponent[..head_len]);
+    let head_bits = head.bits() as usize;
+    let iteration_count = if exponent.len() <= 32 {
+        head_bits.saturating_sub(1)
+    } else {
+        8 * (exponent.len() - 32) + head_bits.saturating_sub(1)
+    };
+
+    (multiplication_complexity * iteration_count.max(1) / 3).max(1)
+}
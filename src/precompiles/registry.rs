@@ -0,0 +1,79 @@
+use std::collections::HashMap;
+
+use zkevm_opcode_defs::system_params::{
+    ECRECOVER_INNER_FUNCTION_PRECOMPILE_ADDRESS, KECCAK256_ROUND_FUNCTION_PRECOMPILE_ADDRESS,
+    SECP256R1_VERIFY_PRECOMPILE_ADDRESS, SHA256_ROUND_FUNCTION_PRECOMPILE_ADDRESS,
+};
+
+use super::{
+    bn254::{
+        EcAddPrecompile, EcMulPrecompile, EcPairingPrecompile, ECADD_PRECOMPILE_ADDRESS,
+        ECMUL_PRECOMPILE_ADDRESS, ECPAIRING_PRECOMPILE_ADDRESS,
+    },
+    ecrecover::ECRecoverPrecompile,
+    keccak256::Keccak256Precompile,
+    modexp::{ModexpPrecompile, MODEXP_PRECOMPILE_ADDRESS},
+    secp256r1_verify::Secp256r1VerifyPrecompile,
+    sha256::Sha256Precompile,
+    Precompile,
+};
+
+/// The precompiles the vm runs, by the low 16 bits of the address of the contract calling them.
+/// The default registry has the precompiles of the era protocol, embedders may add their own or
+/// replace those.
+#[derive(Debug)]
+pub struct PrecompileRegistry {
+    precompiles: HashMap<u16, Box<dyn Precompile>>,
+}
+
+impl Default for PrecompileRegistry {
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        registry.register(
+            KECCAK256_ROUND_FUNCTION_PRECOMPILE_ADDRESS,
+            Keccak256Precompile,
+        );
+        registry.register(SHA256_ROUND_FUNCTION_PRECOMPILE_ADDRESS, Sha256Precompile);
+        registry.register(
+            ECRECOVER_INNER_FUNCTION_PRECOMPILE_ADDRESS,
+            ECRecoverPrecompile,
+        );
+        registry.register(
+            SECP256R1_VERIFY_PRECOMPILE_ADDRESS,
+            Secp256r1VerifyPrecompile,
+        );
+        registry.register(MODEXP_PRECOMPILE_ADDRESS, ModexpPrecompile);
+        registry.register(ECADD_PRECOMPILE_ADDRESS, EcAddPrecompile);
+        registry.register(ECMUL_PRECOMPILE_ADDRESS, EcMulPrecompile);
+        registry.register(ECPAIRING_PRECOMPILE_ADDRESS, EcPairingPrecompile);
+        registry
+    }
+}
+
+impl PrecompileRegistry {
+    /// A registry without any precompile, every precompile call just burns gas
+    pub fn empty() -> Self {
+        Self {
+            precompiles: HashMap::new(),
+        }
+    }
+
+    /// Registers `precompile` at `address`, returning the precompile it replaces if any
+    pub fn register(
+        &mut self,
+        address: u16,
+        precompile: impl Precompile + 'static,
+    ) -> Option<Box<dyn Precompile>> {
+        self.precompiles.insert(address, Box::new(precompile))
+    }
+
+    pub fn unregister(&mut self, address: u16) -> Option<Box<dyn Precompile>> {
+        self.precompiles.remove(&address)
+    }
+
+    pub fn get(&self, address: u16) -> Option<&dyn Precompile> {
+        self.precompiles
+            .get(&address)
+            .map(|precompile| &**precompile)
+    }
+}
@@ -1,5 +1,9 @@
-use super::{precompile_abi_in_log, Precompile, DEFAULT_NUM_ROUNDS};
-use crate::{eravm_error::EraVmError, heaps::Heaps};
+use super::{precompile_abi_in_log, Precompile, PrecompileOutput, DEFAULT_NUM_ROUNDS};
+use crate::{
+    config::VmConfig,
+    eravm_error::{EraVmError, PrecompileError},
+    heaps::Heaps,
+};
 use u256::U256;
 use zkevm_opcode_defs::p256::{
     ecdsa::{signature::hazmat::PrehashVerifier, Signature, VerifyingKey},
@@ -11,17 +15,23 @@ use zkevm_opcode_defs::p256::{
 pub struct Secp256r1VerifyPrecompile;
 
 impl Precompile for Secp256r1VerifyPrecompile {
-    fn execute_precompile(&mut self, query: U256, heaps: &mut Heaps) -> Result<usize, EraVmError> {
+    fn execute_precompile(
+        &self,
+        query: U256,
+        heaps: &mut Heaps,
+        config: &VmConfig,
+    ) -> Result<PrecompileOutput, EraVmError> {
         let precompile_call_params = query;
         let params = precompile_abi_in_log(precompile_call_params);
         let addr = |offset: u32| (params.output_memory_offset + offset) * 32;
 
-        let read_heap = heaps.try_get_mut(params.memory_page_to_read)?;
-        let (hash_value, _) = read_heap.expanded_read(addr(0));
-        let (r_value, _) = read_heap.expanded_read(addr(1));
-        let (s_value, _) = read_heap.expanded_read(addr(2));
-        let (x_value, _) = read_heap.expanded_read(addr(3));
-        let (y_value, _) = read_heap.expanded_read(addr(4));
+        let mut read_heap = heaps.try_get_mut(params.memory_page_to_read)?;
+        let ergs_per_byte = config.memory_growth_ergs_per_byte;
+        let (hash_value, _) = read_heap.expanded_read(addr(0), ergs_per_byte);
+        let (r_value, _) = read_heap.expanded_read(addr(1), ergs_per_byte);
+        let (s_value, _) = read_heap.expanded_read(addr(2), ergs_per_byte);
+        let (x_value, _) = read_heap.expanded_read(addr(3), ergs_per_byte);
+        let (y_value, _) = read_heap.expanded_read(addr(4), ergs_per_byte);
 
         // read everything as bytes for ecrecover purposes
 
@@ -41,18 +51,21 @@ impl Precompile for Secp256r1VerifyPrecompile {
         y_value.to_big_endian(&mut buffer[..]);
         let y_bytes = buffer;
 
-        let result = secp256r1_verify_inner(&hash, &r_bytes, &s_bytes, &x_bytes, &y_bytes);
+        let verification = secp256r1_verify_inner(&hash, &r_bytes, &s_bytes, &x_bytes, &y_bytes);
 
-        let (marker, result) = match result {
+        let (marker, result) = match verification {
             Ok(is_valid) => (U256::one(), U256::from(is_valid as u64)),
-            _ => (U256::zero(), U256::zero()),
+            Err(_) => (U256::zero(), U256::zero()),
         };
 
-        let write_heap = heaps.try_get_mut(params.memory_page_to_write)?;
+        let mut write_heap = heaps.try_get_mut(params.memory_page_to_write)?;
         write_heap.store(addr(0), marker);
         write_heap.store(addr(1), result);
 
-        Ok(DEFAULT_NUM_ROUNDS)
+        Ok(PrecompileOutput {
+            cycles: DEFAULT_NUM_ROUNDS,
+            failure: verification.err(),
+        })
     }
 }
 
@@ -62,13 +75,13 @@ pub fn secp256r1_verify_inner(
     s: &[u8; 32],
     x: &[u8; 32],
     y: &[u8; 32],
-) -> Result<bool, ()> {
+) -> Result<bool, PrecompileError> {
     // we expect pre-validation, so this check always works
     let signature = Signature::from_scalars(
         GenericArray::clone_from_slice(r),
         GenericArray::clone_from_slice(s),
     )
-    .map_err(|_| ())?;
+    .map_err(|_| PrecompileError::Secp256r1InvalidSignature)?;
 
     let encoded_pk = EncodedPoint::from_affine_coordinates(
         &GenericArray::clone_from_slice(x),
@@ -78,11 +91,12 @@ pub fn secp256r1_verify_inner(
 
     let may_be_pk_point = AffinePoint::from_encoded_point(&encoded_pk);
     if bool::from(may_be_pk_point.is_none()) {
-        return Err(());
+        return Err(PrecompileError::Secp256r1InvalidPublicKey);
     }
     let pk_point = may_be_pk_point.unwrap();
 
-    let verifier = VerifyingKey::from_affine(pk_point).map_err(|_| ())?;
+    let verifier = VerifyingKey::from_affine(pk_point)
+        .map_err(|_| PrecompileError::Secp256r1InvalidPublicKey)?;
 
     let result = verifier.verify_prehash(digest, &signature);
 
@@ -90,6 +104,10 @@ pub fn secp256r1_verify_inner(
 }
 
 // Verifies an ECDSA signature against a message digest using a given public key.
-pub fn secp256r1_verify_function(abi_key: U256, heaps: &mut Heaps) -> Result<usize, EraVmError> {
-    Secp256r1VerifyPrecompile.execute_precompile(abi_key, heaps)
+// Runs with the default pricing.
+pub fn secp256r1_verify_function(
+    abi_key: U256,
+    heaps: &mut Heaps,
+) -> Result<PrecompileOutput, EraVmError> {
+    Secp256r1VerifyPrecompile.execute_precompile(abi_key, heaps, &VmConfig::default())
 }
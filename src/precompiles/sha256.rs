@@ -1,18 +1,86 @@
-use super::get_hasher_state;
-use super::{precompile_abi_in_log, Precompile};
-use crate::{eravm_error::EraVmError, heaps::Heaps};
+use super::{precompile_abi_in_log, Precompile, PrecompileOutput};
+use crate::{config::VmConfig, eravm_error::EraVmError, heaps::Heaps};
 use u256::U256;
-pub use zkevm_opcode_defs::sha2::Digest;
-pub use zkevm_opcode_defs::sha2::Sha256;
 
 pub const MEMORY_READS_PER_CYCLE: usize = 2;
 
-fn hash_as_bytes32(hash: [u32; 8]) -> [u8; 32] {
-    let mut result = [0; 32];
-    for (chunk, state_word) in result.chunks_mut(4).zip(hash.into_iter()) {
-        chunk.copy_from_slice(&state_word.to_be_bytes());
+const INITIAL_STATE: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+const ROUND_CONSTANTS: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// The SHA-256 state.
+/// The precompile outputs the state after compressing its blocks, which the contract calling it
+/// already padded, so it's the hash once the last block went in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sha256State(pub [u32; 8]);
+
+impl Default for Sha256State {
+    fn default() -> Self {
+        Self(INITIAL_STATE)
+    }
+}
+
+impl Sha256State {
+    pub fn compress(&mut self, block: &[u8; 64]) {
+        let mut w = [0u32; 64];
+        for (word, bytes) in w.iter_mut().zip(block.chunks_exact(4)) {
+            *word = u32::from_be_bytes(bytes.try_into().unwrap());
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = self.0;
+        for (round_constant, word) in ROUND_CONSTANTS.iter().zip(w) {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let temp1 = h
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(*round_constant)
+                .wrapping_add(word);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let temp2 = s0.wrapping_add(maj);
+
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(temp1);
+            d = c;
+            c = b;
+            b = a;
+            a = temp1.wrapping_add(temp2);
+        }
+
+        for (state_word, value) in self.0.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *state_word = state_word.wrapping_add(value);
+        }
+    }
+
+    pub fn hash(&self) -> [u8; 32] {
+        let mut result = [0; 32];
+        for (bytes, state_word) in result.chunks_mut(4).zip(self.0) {
+            bytes.copy_from_slice(&state_word.to_be_bytes());
+        }
+        result
     }
-    result
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
@@ -20,38 +88,43 @@ pub struct Sha256Precompile;
 
 impl Precompile for Sha256Precompile {
     fn execute_precompile(
-        &mut self,
+        &self,
         abi_key: U256,
         heaps: &mut Heaps,
-    ) -> Result<usize, EraVmError> {
+        config: &VmConfig,
+    ) -> Result<PrecompileOutput, EraVmError> {
         let params = precompile_abi_in_log(abi_key);
         let num_rounds = params.precompile_interpreted_data as usize;
         let mut read_addr = params.input_memory_offset;
         let write_addr = params.output_memory_offset * 32;
 
-        let mut hasher = Sha256::new();
-        let heap_to_read = heaps.try_get_mut(params.memory_page_to_read)?;
+        let mut state = Sha256State::default();
+        let mut heap_to_read = heaps.try_get_mut(params.memory_page_to_read)?;
         for _ in 0..num_rounds {
             let mut block = [0u8; 64];
 
             for query_index in 0..MEMORY_READS_PER_CYCLE {
-                let (data, _) = heap_to_read.expanded_read(read_addr * 32);
+                let (data, _) =
+                    heap_to_read.expanded_read(read_addr * 32, config.memory_growth_ergs_per_byte);
                 read_addr += 1;
                 data.to_big_endian(&mut block[(query_index * 32)..(query_index * 32 + 32)]);
             }
 
-            hasher.update(block);
+            state.compress(&block);
         }
-        let state: [u32; 8] = get_hasher_state(hasher.decompose().0, num_rounds)?;
-        let hash = U256::from_big_endian(&hash_as_bytes32(state));
+        let hash = U256::from_big_endian(&state.hash());
         heaps
             .try_get_mut(params.memory_page_to_write)?
             .store(write_addr, hash);
 
-        Ok(num_rounds)
+        Ok(PrecompileOutput::success(num_rounds))
     }
 }
 
-pub fn sha256_rounds_function(abi_key: U256, heaps: &mut Heaps) -> Result<usize, EraVmError> {
-    Sha256Precompile.execute_precompile(abi_key, heaps)
+/// Runs the precompile with the default pricing
+pub fn sha256_rounds_function(
+    abi_key: U256,
+    heaps: &mut Heaps,
+) -> Result<PrecompileOutput, EraVmError> {
+    Sha256Precompile.execute_precompile(abi_key, heaps, &VmConfig::default())
 }
@@ -1,6 +1,10 @@
 use std::{
     collections::{HashMap, HashSet},
     hash::Hash,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
 };
 
 pub trait Rollbackable {
@@ -9,20 +13,234 @@ pub trait Rollbackable {
     fn snapshot(&self) -> Self::Snapshot;
 }
 
+/// Once the undo log of a map, set or `Heaps` holds this many changes, the ones that no live
+/// snapshot can roll back are discarded. The limit is then doubled with what is kept, so
+/// discarding is amortized.
+const MIN_CHANGES_TO_COMPACT: usize = 1024;
+
+/// The snapshots alive of an undo log, shared by the log and the snapshots
+#[derive(Debug, Default)]
+struct LiveSnapshots {
+    count: AtomicUsize,
+    /// Positions of the snapshots alive, in the order they were taken. Snapshots are mostly
+    /// dropped in the reverse order, like the ones of call frames, so removing one from the end
+    /// is O(1).
+    positions: Mutex<Vec<usize>>,
+}
+
+impl LiveSnapshots {
+    fn with_positions(positions: Vec<usize>) -> Self {
+        Self {
+            count: AtomicUsize::new(positions.len()),
+            positions: Mutex::new(positions),
+        }
+    }
+
+    fn add(&self, position: usize) {
+        if let Ok(mut positions) = self.positions.lock() {
+            positions.push(position);
+            self.count.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn remove(&self, position: usize) {
+        if let Ok(mut positions) = self.positions.lock() {
+            if let Some(index) = positions.iter().rposition(|live| *live == position) {
+                positions.remove(index);
+                self.count.fetch_sub(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn any(&self) -> bool {
+        self.count.load(Ordering::Relaxed) > 0
+    }
+
+    // Linear in the snapshots alive, only needed when compacting
+    fn oldest(&self) -> Option<usize> {
+        self.positions.lock().ok()?.iter().min().copied()
+    }
+
+    fn positions(&self) -> Vec<usize> {
+        self.positions
+            .lock()
+            .map(|positions| positions.clone())
+            .unwrap_or_default()
+    }
+}
+
+/// A position in an undo log. The changes made after it are kept as long as it is alive, so it can
+/// be rolled back to; the ones made before the oldest live snapshot are discarded.
+#[derive(Default)]
+pub struct JournalSnapshot {
+    position: usize,
+    // `None` for snapshots that are never rolled back to, like the initial frame's
+    live: Option<Arc<LiveSnapshots>>,
+}
+
+impl Clone for JournalSnapshot {
+    fn clone(&self) -> Self {
+        if let Some(live) = &self.live {
+            live.add(self.position);
+        }
+        Self {
+            position: self.position,
+            live: self.live.clone(),
+        }
+    }
+}
+
+impl Drop for JournalSnapshot {
+    fn drop(&mut self) {
+        if let Some(live) = &self.live {
+            live.remove(self.position);
+        }
+    }
+}
+
+impl PartialEq for JournalSnapshot {
+    fn eq(&self, other: &Self) -> bool {
+        self.position == other.position
+    }
+}
+
+impl std::fmt::Debug for JournalSnapshot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("JournalSnapshot")
+            .field(&self.position)
+            .finish()
+    }
+}
+
+/// Undo log of a map, set or `Heaps`. Changes are only recorded while a snapshot is alive and only
+/// the ones after the oldest live snapshot are kept.
+#[derive(Debug)]
+pub(crate) struct Journal<T> {
+    changes: Vec<T>,
+    /// Changes before the first one in `changes`, positions count from the start of the log
+    discarded: usize,
+    live: Arc<LiveSnapshots>,
+    compact_at: usize,
+}
+
+impl<T: Clone> Clone for Journal<T> {
+    // Snapshots taken from the clone don't keep the changes of the original. The clone keeps the
+    // changes after the snapshots alive at the time for good, since clones of those snapshots,
+    // e.g. the ones in cloned call frames, still count on the original when dropped.
+    fn clone(&self) -> Self {
+        Self {
+            changes: self.changes.clone(),
+            discarded: self.discarded,
+            live: Arc::new(LiveSnapshots::with_positions(self.live.positions())),
+            compact_at: self.compact_at,
+        }
+    }
+}
+
+impl<T> Default for Journal<T> {
+    fn default() -> Self {
+        Self {
+            changes: vec![],
+            discarded: 0,
+            live: Arc::default(),
+            compact_at: MIN_CHANGES_TO_COMPACT,
+        }
+    }
+}
+
+impl<T> Journal<T> {
+    pub(crate) fn push(&mut self, change: T) {
+        // no snapshot can roll back this change or any before it
+        if !self.live.any() {
+            self.discarded += self.changes.len() + 1;
+            self.changes.clear();
+            return;
+        }
+        if self.changes.len() >= self.compact_at {
+            self.compact();
+        }
+        self.changes.push(change);
+    }
+
+    fn compact(&mut self) {
+        let end = self.discarded + self.changes.len();
+        let oldest = self.live.oldest().unwrap_or(end);
+        let unreachable = oldest
+            .saturating_sub(self.discarded)
+            .min(self.changes.len());
+        self.changes.drain(..unreachable);
+        self.discarded += unreachable;
+        self.compact_at = (2 * self.changes.len()).max(MIN_CHANGES_TO_COMPACT);
+    }
+
+    pub(crate) fn snapshot(&self) -> JournalSnapshot {
+        let position = self.discarded + self.changes.len();
+        self.live.add(position);
+        JournalSnapshot {
+            position,
+            live: Some(self.live.clone()),
+        }
+    }
+
+    /// The changes made after the snapshot, oldest first
+    pub(crate) fn since(&self, snapshot: &JournalSnapshot) -> &[T] {
+        &self.changes[self.index_of(snapshot)..]
+    }
+
+    /// Removes the changes made after the snapshot and returns them, oldest first
+    pub(crate) fn split_off(&mut self, snapshot: &JournalSnapshot) -> Vec<T> {
+        let index = self.index_of(snapshot);
+        self.changes.split_off(index)
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    // Positions from before a discarded change map to the first change kept
+    fn index_of(&self, snapshot: &JournalSnapshot) -> usize {
+        snapshot
+            .position
+            .saturating_sub(self.discarded)
+            .min(self.changes.len())
+    }
+}
+
+#[derive(Debug, Clone)]
+enum MapChange<K, V> {
+    /// A key was set, along with the value it had before
+    Set(K, Option<V>),
+    /// Every entry was removed at once
+    Cleared(HashMap<K, V>),
+}
+
+// Snapshots of the maps and sets are positions in an undo log. Taking one appends its position to
+// the live ones and dropping it removes it, O(1) as long as snapshots are dropped in the reverse
+// order they were taken. Rolling back costs as much as the changes being undone. Rolling back invalidates the snapshots taken
+// after the one rolled back to.
 #[derive(Debug, Default, Clone)]
 pub struct RollbackableHashMap<K: Clone + Hash, V: Clone> {
     map: HashMap<K, V>,
+    journal: Journal<MapChange<K, V>>,
 }
 
 impl<K: Clone + Hash + Eq, V: Clone> RollbackableHashMap<K, V> {
     pub fn new() -> Self {
         Self {
             map: HashMap::new(),
+            journal: Journal::default(),
         }
     }
 
     pub fn insert(&mut self, key: K, value: V) {
-        self.map.insert(key, value);
+        let previous = self.map.insert(key.clone(), value);
+        self.journal.push(MapChange::Set(key, previous));
+    }
+
+    /// Removes every entry, the removal can be rolled back like any other change
+    pub fn clear(&mut self) {
+        let previous = std::mem::take(&mut self.map);
+        self.journal.push(MapChange::Cleared(previous));
     }
 
     pub fn get(&self, key: &K) -> Option<&V> {
@@ -33,28 +251,65 @@ impl<K: Clone + Hash + Eq, V: Clone> RollbackableHashMap<K, V> {
         &self.map
     }
 
+    /// Changes the snapshots alive can still roll back, for tests and diagnostics
+    pub fn journal_len(&self) -> usize {
+        self.journal.len()
+    }
+
     pub fn get_logs_after_snapshot(
         &self,
         snapshot: <RollbackableHashMap<K, V> as Rollbackable>::Snapshot,
     ) -> HashMap<K, (Option<V>, V)> {
+        // the first change of a key after the snapshot has the value it had when it was taken,
+        // keys that weren't changed before a clear had the value the clear removed
+        let mut values_at_snapshot: HashMap<&K, &Option<V>> = HashMap::new();
+        let mut cleared = None;
+        for change in self.journal.since(&snapshot) {
+            match change {
+                MapChange::Set(key, previous) => {
+                    values_at_snapshot.entry(key).or_insert(previous);
+                }
+                MapChange::Cleared(previous) => {
+                    cleared = Some(previous);
+                    break;
+                }
+            }
+        }
+
         let mut changes = HashMap::new();
 
         for (key, value) in self.map.iter() {
-            changes.insert(key.clone(), (snapshot.get(key).cloned(), value.clone()));
+            let before = match (values_at_snapshot.get(key), cleared) {
+                (Some(previous), _) => (*previous).clone(),
+                (None, Some(cleared)) => cleared.get(key).cloned(),
+                (None, None) => Some(value.clone()),
+            };
+            changes.insert(key.clone(), (before, value.clone()));
         }
 
         changes
     }
 }
 
-impl<K: Clone + Hash, V: Clone> Rollbackable for RollbackableHashMap<K, V> {
-    type Snapshot = HashMap<K, V>;
+impl<K: Clone + Hash + Eq, V: Clone> Rollbackable for RollbackableHashMap<K, V> {
+    type Snapshot = JournalSnapshot;
     fn rollback(&mut self, snapshot: Self::Snapshot) {
-        self.map = snapshot;
+        let undone = self.journal.split_off(&snapshot);
+        for change in undone.into_iter().rev() {
+            match change {
+                MapChange::Set(key, Some(value)) => {
+                    self.map.insert(key, value);
+                }
+                MapChange::Set(key, None) => {
+                    self.map.remove(&key);
+                }
+                MapChange::Cleared(previous) => self.map = previous,
+            }
+        }
     }
 
     fn snapshot(&self) -> Self::Snapshot {
-        self.map.clone()
+        self.journal.snapshot()
     }
 }
 
@@ -140,22 +395,30 @@ impl<T: Copy> RollbackablePrimitive<T> {
 #[derive(Debug, Default, Clone)]
 pub struct RollbackableHashSet<K: Clone> {
     map: HashSet<K>,
+    /// The values in insertion order
+    journal: Journal<K>,
 }
 
-impl<K: Clone> Rollbackable for RollbackableHashSet<K> {
-    type Snapshot = HashSet<K>;
+impl<K: Clone + Eq + Hash> Rollbackable for RollbackableHashSet<K> {
+    type Snapshot = JournalSnapshot;
     fn rollback(&mut self, snapshot: Self::Snapshot) {
-        self.map = snapshot;
+        for value in self.journal.split_off(&snapshot) {
+            self.map.remove(&value);
+        }
     }
 
     fn snapshot(&self) -> Self::Snapshot {
-        self.map.clone()
+        self.journal.snapshot()
     }
 }
 
 impl<K: Clone + Eq + Hash> RollbackableHashSet<K> {
     pub fn insert(&mut self, value: K) -> bool {
-        self.map.insert(value)
+        let inserted = self.map.insert(value.clone());
+        if inserted {
+            self.journal.push(value);
+        }
+        inserted
     }
 
     pub fn contains(&self, value: &K) -> bool {
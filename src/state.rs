@@ -1,20 +1,14 @@
 use crate::{
+    call_frame::CodePage,
+    config::{ProtocolVersion, VmConfig},
     rollbacks::{
         Rollbackable, RollbackableHashMap, RollbackableHashSet, RollbackablePrimitive,
         RollbackableVec,
     },
-    store::{Storage, StorageKey},
+    store::{StateDiff, Storage, StorageError, StorageKey},
 };
 use std::collections::{HashMap, HashSet};
 use u256::{H160, U256};
-use zkevm_opcode_defs::system_params::{
-    STORAGE_ACCESS_COLD_READ_COST, STORAGE_ACCESS_COLD_WRITE_COST, STORAGE_ACCESS_WARM_READ_COST,
-    STORAGE_ACCESS_WARM_WRITE_COST,
-};
-
-const WARM_READ_REFUND: u32 = STORAGE_ACCESS_COLD_READ_COST - STORAGE_ACCESS_WARM_READ_COST;
-const WARM_WRITE_REFUND: u32 = STORAGE_ACCESS_COLD_WRITE_COST - STORAGE_ACCESS_WARM_WRITE_COST;
-const COLD_WRITE_AFTER_WARM_READ_REFUND: u32 = STORAGE_ACCESS_COLD_READ_COST;
 
 #[derive(Debug, PartialEq, Clone, Default)]
 pub struct L2ToL1Log {
@@ -52,6 +46,10 @@ pub struct VMState {
     read_storage_slots: RollbackableHashSet<StorageKey>,
     written_storage_slots: RollbackableHashSet<StorageKey>,
     decommitted_hashes: RollbackableHashSet<U256>,
+
+    // Decoded code of the decommitted hashes, dropped along with the hash when it's rolled back.
+    // Decoding depends on the protocol version, so it's part of the key.
+    code_cache: HashMap<(U256, ProtocolVersion), CodePage>,
 }
 
 impl Default for VMState {
@@ -74,6 +72,7 @@ impl VMState {
             read_storage_slots: RollbackableHashSet::<StorageKey>::default(),
             written_storage_slots: RollbackableHashSet::<StorageKey>::default(),
             decommitted_hashes: RollbackableHashSet::<U256>::default(),
+            code_cache: HashMap::new(),
         }
     }
 
@@ -138,14 +137,19 @@ impl VMState {
 
     // reads shouldn't be mutable, we should consider change it to a non-mutable reference
     // though that would require a refactor in the integration with the operator
-    pub fn storage_read(&mut self, key: StorageKey, storage: &mut dyn Storage) -> (U256, u32) {
+    pub fn storage_read(
+        &mut self,
+        key: StorageKey,
+        storage: &mut dyn Storage,
+        config: &VmConfig,
+    ) -> Result<(U256, u32), StorageError> {
         let value = self
-            .storage_read_inner(&key, storage)
+            .storage_read_inner(&key, storage)?
             .map_or_else(U256::zero, |val| val);
 
         let refund = if storage.is_free_storage_slot(&key) || self.read_storage_slots.contains(&key)
         {
-            WARM_READ_REFUND
+            config.warm_read_refund()
         } else {
             self.read_storage_slots.insert(key);
             0
@@ -154,16 +158,16 @@ impl VMState {
         self.pubdata_costs.push(0);
         self.refunds.push(refund);
 
-        (value, refund)
+        Ok((value, refund))
     }
 
     pub fn storage_read_with_no_refund(
         &mut self,
         key: StorageKey,
         storage: &mut dyn Storage,
-    ) -> U256 {
+    ) -> Result<U256, StorageError> {
         let value = self
-            .storage_read_inner(&key, storage)
+            .storage_read_inner(&key, storage)?
             .map_or_else(U256::zero, |val| val);
 
         if !storage.is_free_storage_slot(&key) && !self.read_storage_slots.contains(&key) {
@@ -171,13 +175,17 @@ impl VMState {
         };
 
         self.pubdata_costs.push(0);
-        value
+        Ok(value)
     }
 
-    fn storage_read_inner(&self, key: &StorageKey, storage: &mut dyn Storage) -> Option<U256> {
+    fn storage_read_inner(
+        &self,
+        key: &StorageKey,
+        storage: &mut dyn Storage,
+    ) -> Result<Option<U256>, StorageError> {
         match self.storage_changes.get(key) {
             None => storage.storage_read(key),
-            value => value.copied(),
+            value => Ok(value.copied()),
         }
     }
 
@@ -186,31 +194,32 @@ impl VMState {
         key: StorageKey,
         value: U256,
         storage: &mut dyn Storage,
-    ) -> u32 {
-        self.storage_changes.insert(key, value);
-
+        config: &VmConfig,
+    ) -> Result<u32, StorageError> {
         if storage.is_free_storage_slot(&key) {
+            self.storage_changes.insert(key, value);
             self.written_storage_slots.insert(key);
-            let refund = WARM_WRITE_REFUND;
+            let refund = config.warm_write_refund();
             self.refunds.push(refund);
             self.pubdata_costs.push(0);
-            return refund;
+            return Ok(refund);
         }
 
         // after every write, we store the current cost paid
         // on subsequent writes, we don't charge for what has already been paid
         // but for the newer price, which if it is lower might end up in a refund
-        let current_cost = storage.cost_of_writing_storage(&key, value);
+        let current_cost = storage.cost_of_writing_storage(&key, value)?;
+        self.storage_changes.insert(key, value);
         let prev_cost = *self.paid_changes.get(&key).unwrap_or(&0);
         self.paid_changes.insert(key, current_cost);
 
         let refund = if self.written_storage_slots.contains(&key) {
-            WARM_WRITE_REFUND
+            config.warm_write_refund()
         } else {
             self.written_storage_slots.insert(key);
 
             if self.read_storage_slots.contains(&key) {
-                COLD_WRITE_AFTER_WARM_READ_REFUND
+                config.cold_write_after_warm_read_refund()
             } else {
                 self.read_storage_slots.insert(key);
                 0
@@ -227,7 +236,7 @@ impl VMState {
         self.refunds.push(refund);
         self.pubdata_costs.push(pubdata_cost);
 
-        refund
+        Ok(refund)
     }
 
     pub fn transient_storage_read(&mut self, key: StorageKey) -> U256 {
@@ -245,7 +254,7 @@ impl VMState {
     }
 
     pub(crate) fn clear_transient_storage(&mut self) {
-        self.transient_storage = RollbackableHashMap::default();
+        self.transient_storage.clear();
     }
 
     pub fn record_l2_to_l1_log(&mut self, msg: L2ToL1Log) {
@@ -260,11 +269,25 @@ impl VMState {
     /// # Returns
     ///
     /// A tuple containing:
-    /// - `Option<Vec<U256>>`: the contract bytecode
+    /// - `Option<CodePage>`: the contract bytecode, decoded once per hash and protocol version and shared while the hash stays decommitted
     /// - `bool`: A boolean flag indicating whether the hash was decommitted (`true` if it was newly decommitted, `false` if it had already been decommitted).
-    pub fn decommit(&mut self, hash: U256, storage: &mut dyn Storage) -> (Option<Vec<U256>>, bool) {
+    pub fn decommit(
+        &mut self,
+        hash: U256,
+        storage: &mut dyn Storage,
+        version: ProtocolVersion,
+    ) -> Result<(Option<CodePage>, bool), StorageError> {
+        let code = match self.code_cache.get(&(hash, version)) {
+            Some(code) => Some(code.clone()),
+            None => storage
+                .decommit(hash)?
+                .map(|code| CodePage::new(code).decode(version)),
+        };
+        if let Some(code) = &code {
+            self.code_cache.insert((hash, version), code.clone());
+        }
         let was_decommitted = !self.decommitted_hashes.insert(hash);
-        (storage.decommit(hash), was_decommitted)
+        Ok((code, was_decommitted))
     }
 
     pub fn decommitted_hashes(&self) -> &HashSet<U256> {
@@ -282,18 +305,29 @@ impl VMState {
     pub fn get_storage_changes(
         &mut self,
         storage: &mut dyn Storage,
-    ) -> Vec<(StorageKey, Option<U256>, U256)> {
-        self.storage_changes()
-            .iter()
-            .filter_map(|(key, value)| {
-                let initial_value = storage.storage_read(key);
-                if initial_value.unwrap_or_default() == *value {
-                    None
-                } else {
-                    Some((*key, initial_value, *value))
-                }
-            })
-            .collect()
+    ) -> Result<Vec<(StorageKey, Option<U256>, U256)>, StorageError> {
+        let mut changes = vec![];
+        for (key, value) in self.storage_changes() {
+            let initial_value = storage.storage_read(key)?;
+            if initial_value.unwrap_or_default() != *value {
+                changes.push((*key, initial_value, *value));
+            }
+        }
+        Ok(changes)
+    }
+
+    /// Collects everything a backend needs to persist once the run is over:
+    /// the storage changes and the given published bytecode.
+    /// Decommitted hashes only price decommits within the batch, so they aren't part of it.
+    pub fn state_diff(
+        &mut self,
+        storage: &mut dyn Storage,
+        published_contracts: HashMap<U256, Vec<U256>>,
+    ) -> Result<StateDiff, StorageError> {
+        Ok(StateDiff {
+            storage_changes: self.get_storage_changes(storage)?,
+            published_contracts,
+        })
     }
 
     /// Retrieves the values that have changed since the snapshot was taken, or returns the initial values if no changes exist along the current value.
@@ -310,15 +344,13 @@ impl VMState {
         &self,
         snapshot: <RollbackableHashMap<StorageKey, U256> as Rollbackable>::Snapshot,
         storage: &mut dyn Storage,
-    ) -> Vec<(StorageKey, Option<U256>, U256, bool)> {
-        self.storage_changes
-            .get_logs_after_snapshot(snapshot)
-            .iter()
-            .map(|(key, (before, after))| {
-                let initial = storage.storage_read(key);
-                (*key, before.or(initial), *after, initial.is_none())
-            })
-            .collect()
+    ) -> Result<Vec<(StorageKey, Option<U256>, U256, bool)>, StorageError> {
+        let mut changes = vec![];
+        for (key, (before, after)) in self.storage_changes.get_logs_after_snapshot(snapshot) {
+            let initial = storage.storage_read(&key)?;
+            changes.push((key, before.or(initial), after, initial.is_none()));
+        }
+        Ok(changes)
     }
 }
 
@@ -375,6 +407,9 @@ impl VMState {
         self.pubdata_costs.rollback(snapshot.pubdata_costs);
         self.refunds.rollback(snapshot.refunds);
         self.decommitted_hashes.rollback(snapshot.decommited_hashes);
+        let decommitted_hashes = self.decommitted_hashes.inner_ref();
+        self.code_cache
+            .retain(|(hash, _), _| decommitted_hashes.contains(hash));
         self.read_storage_slots
             .rollback(snapshot.read_storage_slots);
         self.written_storage_slots
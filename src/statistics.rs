@@ -1,4 +1,14 @@
+use std::collections::HashMap;
 use u256::U256;
+use zkevm_opcode_defs::system_params::{
+    ECRECOVER_INNER_FUNCTION_PRECOMPILE_ADDRESS, KECCAK256_ROUND_FUNCTION_PRECOMPILE_ADDRESS,
+    SECP256R1_VERIFY_PRECOMPILE_ADDRESS, SHA256_ROUND_FUNCTION_PRECOMPILE_ADDRESS,
+};
+
+use crate::precompiles::{
+    bn254::{ECADD_PRECOMPILE_ADDRESS, ECMUL_PRECOMPILE_ADDRESS, ECPAIRING_PRECOMPILE_ADDRESS},
+    modexp::MODEXP_PRECOMPILE_ADDRESS,
+};
 
 pub const STORAGE_READ_STORAGE_APPLICATION_CYCLES: usize = 1;
 pub const STORAGE_WRITE_STORAGE_APPLICATION_CYCLES: usize = 2;
@@ -6,16 +16,58 @@ pub const STORAGE_WRITE_STORAGE_APPLICATION_CYCLES: usize = 2;
 #[derive(Debug, Default, Clone, PartialEq)]
 pub struct VmStatistics {
     pub monotonic_counter: u32,
-    pub keccak256_cycles: usize,
-    pub ecrecover_cycles: usize,
-    pub sha256_cycles: usize,
-    pub secp255r1_verify_cycles: usize,
     pub code_decommitter_cycles: usize,
     pub storage_application_cycles: usize,
+    /// Cycles of every precompile called, by address
+    pub precompile_cycles: HashMap<u16, usize>,
 }
 
 impl VmStatistics {
     pub fn decommiter_cycle_from_decommit(&mut self, code_page: &[U256]) {
         self.code_decommitter_cycles += (code_page.len() + 1) / 2
     }
+
+    pub fn add_precompile_cycles(&mut self, address: u16, cycles: usize) {
+        *self.precompile_cycles.entry(address).or_default() += cycles;
+    }
+
+    /// Cycles of the precompile at `address`, zero if it was never called
+    pub fn cycles_of(&self, address: u16) -> usize {
+        self.precompile_cycles
+            .get(&address)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn keccak256_cycles(&self) -> usize {
+        self.cycles_of(KECCAK256_ROUND_FUNCTION_PRECOMPILE_ADDRESS)
+    }
+
+    pub fn ecrecover_cycles(&self) -> usize {
+        self.cycles_of(ECRECOVER_INNER_FUNCTION_PRECOMPILE_ADDRESS)
+    }
+
+    pub fn sha256_cycles(&self) -> usize {
+        self.cycles_of(SHA256_ROUND_FUNCTION_PRECOMPILE_ADDRESS)
+    }
+
+    pub fn secp255r1_verify_cycles(&self) -> usize {
+        self.cycles_of(SECP256R1_VERIFY_PRECOMPILE_ADDRESS)
+    }
+
+    pub fn modexp_cycles(&self) -> usize {
+        self.cycles_of(MODEXP_PRECOMPILE_ADDRESS)
+    }
+
+    pub fn ecadd_cycles(&self) -> usize {
+        self.cycles_of(ECADD_PRECOMPILE_ADDRESS)
+    }
+
+    pub fn ecmul_cycles(&self) -> usize {
+        self.cycles_of(ECMUL_PRECOMPILE_ADDRESS)
+    }
+
+    pub fn ecpairing_cycles(&self) -> usize {
+        self.cycles_of(ECPAIRING_PRECOMPILE_ADDRESS)
+    }
 }
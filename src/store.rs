@@ -1,8 +1,15 @@
-use std::{collections::HashMap, fmt::Debug};
+use rocksdb::{ColumnFamily, ColumnFamilyDescriptor, Options, WriteBatch, DB};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+    path::Path,
+};
 use thiserror::Error;
 use u256::{H160, U256};
 use zkevm_opcode_defs::{
-    ethereum_types::Address, system_params::DEPLOYER_SYSTEM_CONTRACT_ADDRESS_LOW,
+    ethereum_types::Address,
+    sha3::{Digest, Keccak256},
+    system_params::DEPLOYER_SYSTEM_CONTRACT_ADDRESS_LOW,
 };
 
 use crate::eravm_error::EraVmError;
@@ -20,52 +27,392 @@ impl StorageKey {
     }
 }
 
+/// Reads return `Ok(None)` for code or slots that don't exist.
+/// Errors are reserved for failures of the backend itself, they stop the execution.
 pub trait Storage: Debug {
-    fn decommit(&mut self, hash: U256) -> Option<Vec<U256>>;
+    fn decommit(&mut self, hash: U256) -> Result<Option<Vec<U256>>, StorageError>;
 
-    fn storage_read(&mut self, key: &StorageKey) -> Option<U256>;
+    fn storage_read(&mut self, key: &StorageKey) -> Result<Option<U256>, StorageError>;
 
-    fn cost_of_writing_storage(&mut self, key: &StorageKey, value: U256) -> u32;
+    fn cost_of_writing_storage(
+        &mut self,
+        key: &StorageKey,
+        value: U256,
+    ) -> Result<u32, StorageError>;
 
     fn is_free_storage_slot(&self, key: &StorageKey) -> bool;
 }
 
+/// Everything a run produced that a backend needs to persist.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StateDiff {
+    /// As returned by `VMState::get_storage_changes`.
+    pub storage_changes: Vec<(StorageKey, Option<U256>, U256)>,
+    /// Bytecode published during the run, keyed by its versioned hash.
+    pub published_contracts: HashMap<U256, Vec<U256>>,
+}
+
+pub trait StorageWrite: Storage {
+    fn apply_storage_changes(
+        &mut self,
+        changes: &[(StorageKey, Option<U256>, U256)],
+    ) -> Result<(), StorageError>;
+
+    fn insert_contract(&mut self, hash: U256, code: Vec<U256>) -> Result<(), StorageError>;
+
+    /// Applies the parts of the diff one after the other, so a failure part-way leaves it partially
+    /// applied. Backends that can fail should override it to write the whole diff atomically.
+    fn commit(&mut self, diff: StateDiff) -> Result<(), StorageError> {
+        self.apply_storage_changes(&diff.storage_changes)?;
+        for (hash, code) in diff.published_contracts {
+            self.insert_contract(hash, code)?;
+        }
+        Ok(())
+    }
+}
+
+const BOOTLOADER_ADDRESS_LOW: u64 = 0x8001;
+const L2_BASE_TOKEN_ADDRESS_LOW: u64 = 0x800a;
+const SYSTEM_CONTEXT_ADDRESS_LOW: u64 = 0x800b;
+
+/// Pubdata pricing for storage writes, the default matches what the zkSync operator charges.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoragePricing {
+    /// Bytes published for the key of a slot that is written for the first time.
+    pub bytes_per_derived_key: u32,
+    /// Bytes published for the key of a slot that was already written in the past.
+    pub bytes_per_enumeration_index: u32,
+    /// Every slot of these contracts is free to write.
+    pub free_addresses: HashSet<H160>,
+    pub free_slots: HashSet<StorageKey>,
+}
+
+impl Default for StoragePricing {
+    fn default() -> Self {
+        let bootloader_balance = StorageKey::new(
+            H160::from_low_u64_be(L2_BASE_TOKEN_ADDRESS_LOW),
+            balance_key(H160::from_low_u64_be(BOOTLOADER_ADDRESS_LOW)),
+        );
+        Self {
+            bytes_per_derived_key: 32,
+            bytes_per_enumeration_index: 4,
+            free_addresses: HashSet::from([H160::from_low_u64_be(SYSTEM_CONTEXT_ADDRESS_LOW)]),
+            free_slots: HashSet::from([bootloader_balance]),
+        }
+    }
+}
+
+impl StoragePricing {
+    /// Pricing under which every write is free, useful for tests that don't care about pubdata.
+    pub fn free() -> Self {
+        Self {
+            bytes_per_derived_key: 0,
+            bytes_per_enumeration_index: 0,
+            free_addresses: HashSet::new(),
+            free_slots: HashSet::new(),
+        }
+    }
+
+    pub fn is_free_storage_slot(&self, key: &StorageKey) -> bool {
+        self.free_addresses.contains(&key.address) || self.free_slots.contains(key)
+    }
+
+    /// `initial_value` is the value the slot had before the batch, `None` if it was never written.
+    pub fn cost_of_writing_storage(&self, initial_value: Option<U256>, value: U256) -> u32 {
+        let is_initial_write = initial_value.is_none();
+        let initial_value = initial_value.unwrap_or_default();
+        if initial_value == value {
+            return 0;
+        }
+
+        let key_size = if is_initial_write {
+            self.bytes_per_derived_key
+        } else {
+            self.bytes_per_enumeration_index
+        };
+        if key_size == 0 {
+            return 0;
+        }
+        key_size + compressed_value_size(initial_value, value)
+    }
+}
+
+// Size of the value diff once compressed with the cheapest strategy:
+// the value itself, its difference with the initial one (either way), or no compression at all.
+// There is always one extra byte of metadata that states the strategy and the length.
+fn compressed_value_size(initial_value: U256, value: U256) -> u32 {
+    let size_in_bytes = |diff: U256| (diff.bits() as u32).div_ceil(8);
+    let add = size_in_bytes(value.overflowing_sub(initial_value).0);
+    let sub = size_in_bytes(initial_value.overflowing_sub(value).0);
+    let transform = size_in_bytes(value);
+
+    1 + add.min(sub).min(transform).min(32)
+}
+
+// Key of the balance of `address` in the base token contract, a solidity mapping stored at slot 0.
+fn balance_key(address: H160) -> U256 {
+    let mut preimage = [0u8; 64];
+    preimage[12..32].copy_from_slice(address.as_bytes());
+    U256::from_big_endian(&Keccak256::digest(preimage))
+}
+
+/// Writes are free unless a pricing is given through `with_pricing`.
+/// Struct literals have to set `pricing` as well, `StoragePricing::free()` keeps writes free.
 #[derive(Debug, Clone)]
 pub struct InitialStorageMemory {
     pub contracts: HashMap<U256, Vec<U256>>,
     pub storage: HashMap<StorageKey, U256>,
+    pub pricing: StoragePricing,
+}
+
+impl InitialStorageMemory {
+    pub fn new(contracts: HashMap<U256, Vec<U256>>, storage: HashMap<StorageKey, U256>) -> Self {
+        Self {
+            contracts,
+            storage,
+            pricing: StoragePricing::free(),
+        }
+    }
+
+    /// Prices writes like `pricing`, e.g. `StoragePricing::default()` to charge what the operator does
+    pub fn with_pricing(mut self, pricing: StoragePricing) -> Self {
+        self.pricing = pricing;
+        self
+    }
 }
 
 // The initial storage acts as a read-only storage with initial values
 // Any changes to the storage are stored in the state storage
 // This specific implementation is just a simple way of doing it, so that the compiler tester can use it for testing
 impl Storage for InitialStorageMemory {
-    fn storage_read(&mut self, key: &StorageKey) -> Option<U256> {
-        self.storage.get(key).copied()
+    fn storage_read(&mut self, key: &StorageKey) -> Result<Option<U256>, StorageError> {
+        Ok(self.storage.get(key).copied())
+    }
+
+    fn decommit(&mut self, hash: U256) -> Result<Option<Vec<U256>>, StorageError> {
+        Ok(self.contracts.get(&hash).cloned())
+    }
+
+    fn cost_of_writing_storage(
+        &mut self,
+        key: &StorageKey,
+        value: U256,
+    ) -> Result<u32, StorageError> {
+        let initial_value = self.storage.get(key).copied();
+        Ok(self.pricing.cost_of_writing_storage(initial_value, value))
+    }
+
+    fn is_free_storage_slot(&self, key: &StorageKey) -> bool {
+        self.pricing.is_free_storage_slot(key)
+    }
+}
+
+impl StorageWrite for InitialStorageMemory {
+    fn apply_storage_changes(
+        &mut self,
+        changes: &[(StorageKey, Option<U256>, U256)],
+    ) -> Result<(), StorageError> {
+        for (key, _, value) in changes {
+            self.storage.insert(*key, *value);
+        }
+        Ok(())
+    }
+
+    fn insert_contract(&mut self, hash: U256, code: Vec<U256>) -> Result<(), StorageError> {
+        self.contracts.insert(hash, code);
+        Ok(())
+    }
+}
+
+const CONTRACTS_CF: &str = "contracts";
+const STORAGE_CF: &str = "storage";
+
+/// Persistent storage backed by RocksDB.
+/// Contract bytecode and storage slots live in separate column families,
+/// so the state of a local chain survives between runs.
+pub struct RocksDbStorage {
+    db: DB,
+    pub pricing: StoragePricing,
+}
+
+impl Debug for RocksDbStorage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RocksDbStorage").finish_non_exhaustive()
+    }
+}
+
+impl RocksDbStorage {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, StorageError> {
+        let mut options = Options::default();
+        options.create_if_missing(true);
+        options.create_missing_column_families(true);
+
+        let column_families = [CONTRACTS_CF, STORAGE_CF]
+            .into_iter()
+            .map(|name| ColumnFamilyDescriptor::new(name, Options::default()));
+
+        let db = DB::open_cf_descriptors(&options, path, column_families)
+            .map_err(|err| StorageError::OpenError(err.to_string()))?;
+        Ok(Self {
+            db,
+            pricing: StoragePricing::default(),
+        })
     }
 
-    fn decommit(&mut self, hash: U256) -> Option<Vec<U256>> {
-        self.contracts.get(&hash).cloned()
+    /// Opens an existing database without locking it, e.g. to inspect the state of a running
+    /// chain. Every write fails.
+    pub fn open_read_only<P: AsRef<Path>>(path: P) -> Result<Self, StorageError> {
+        let db =
+            DB::open_cf_for_read_only(&Options::default(), path, [CONTRACTS_CF, STORAGE_CF], false)
+                .map_err(|err| StorageError::OpenError(err.to_string()))?;
+        Ok(Self {
+            db,
+            pricing: StoragePricing::default(),
+        })
     }
 
-    fn cost_of_writing_storage(&mut self, _key: &StorageKey, _value: U256) -> u32 {
-        0
+    fn column_family(&self, name: &str) -> Result<&ColumnFamily, StorageError> {
+        self.db
+            .cf_handle(name)
+            .ok_or(StorageError::MissingColumnFamily)
+    }
+}
+
+impl Storage for RocksDbStorage {
+    fn decommit(&mut self, hash: U256) -> Result<Option<Vec<U256>>, StorageError> {
+        let contracts = self.column_family(CONTRACTS_CF)?;
+        let code = self
+            .db
+            .get_cf(contracts, u256_to_bytes(hash))
+            .map_err(|err| StorageError::ReadError(err.to_string()))?;
+        Ok(code.map(|code| decode_code(&code)))
     }
 
-    fn is_free_storage_slot(&self, _key: &StorageKey) -> bool {
-        false
+    fn storage_read(&mut self, key: &StorageKey) -> Result<Option<U256>, StorageError> {
+        let storage = self.column_family(STORAGE_CF)?;
+        let value = self
+            .db
+            .get_cf(storage, encode_storage_key(key))
+            .map_err(|err| StorageError::ReadError(err.to_string()))?;
+        Ok(value.map(|value| U256::from_big_endian(&value)))
+    }
+
+    fn cost_of_writing_storage(
+        &mut self,
+        key: &StorageKey,
+        value: U256,
+    ) -> Result<u32, StorageError> {
+        let initial_value = self.storage_read(key)?;
+        Ok(self.pricing.cost_of_writing_storage(initial_value, value))
+    }
+
+    fn is_free_storage_slot(&self, key: &StorageKey) -> bool {
+        self.pricing.is_free_storage_slot(key)
     }
 }
 
+impl RocksDbStorage {
+    fn batch_storage_changes(
+        &self,
+        batch: &mut WriteBatch,
+        changes: &[(StorageKey, Option<U256>, U256)],
+    ) -> Result<(), StorageError> {
+        let storage = self.column_family(STORAGE_CF)?;
+        for (key, _, value) in changes {
+            batch.put_cf(storage, encode_storage_key(key), u256_to_bytes(*value));
+        }
+        Ok(())
+    }
+
+    fn batch_contract(
+        &self,
+        batch: &mut WriteBatch,
+        hash: U256,
+        code: &[U256],
+    ) -> Result<(), StorageError> {
+        let contracts = self.column_family(CONTRACTS_CF)?;
+        batch.put_cf(contracts, u256_to_bytes(hash), encode_code(code));
+        Ok(())
+    }
+
+    fn write(&self, batch: WriteBatch) -> Result<(), StorageError> {
+        self.db
+            .write(batch)
+            .map_err(|err| StorageError::WriteError(err.to_string()))
+    }
+}
+
+impl StorageWrite for RocksDbStorage {
+    fn apply_storage_changes(
+        &mut self,
+        changes: &[(StorageKey, Option<U256>, U256)],
+    ) -> Result<(), StorageError> {
+        let mut batch = WriteBatch::default();
+        self.batch_storage_changes(&mut batch, changes)?;
+        self.write(batch)
+    }
+
+    fn insert_contract(&mut self, hash: U256, code: Vec<U256>) -> Result<(), StorageError> {
+        let mut batch = WriteBatch::default();
+        self.batch_contract(&mut batch, hash, &code)?;
+        self.write(batch)
+    }
+
+    // the whole diff is written in a single atomic batch, so it's either fully applied or not at all
+    fn commit(&mut self, diff: StateDiff) -> Result<(), StorageError> {
+        let mut batch = WriteBatch::default();
+        self.batch_storage_changes(&mut batch, &diff.storage_changes)?;
+        for (hash, code) in &diff.published_contracts {
+            self.batch_contract(&mut batch, *hash, code)?;
+        }
+        self.write(batch)
+    }
+}
+
+fn u256_to_bytes(value: U256) -> [u8; 32] {
+    let mut bytes = [0; 32];
+    value.to_big_endian(&mut bytes);
+    bytes
+}
+
+// the address followed by the key, both big endian
+fn encode_storage_key(key: &StorageKey) -> [u8; 52] {
+    let mut bytes = [0; 52];
+    bytes[..20].copy_from_slice(key.address.as_bytes());
+    key.key.to_big_endian(&mut bytes[20..]);
+    bytes
+}
+
+fn encode_code(code: &[U256]) -> Vec<u8> {
+    code.iter().flat_map(|word| u256_to_bytes(*word)).collect()
+}
+
+fn decode_code(bytes: &[u8]) -> Vec<U256> {
+    bytes.chunks(32).map(U256::from_big_endian).collect()
+}
+
 /// Error type for storage operations.
+/// Backend failures carry the message of the backend's own error.
 #[derive(Error, Debug, PartialEq)]
 pub enum StorageError {
     #[error("Key not present in storage")]
     KeyNotPresent,
-    #[error("Error writing to storage")]
-    WriteError,
-    #[error("Error reading from storage")]
-    ReadError,
+    #[error("Error writing to storage: {0}")]
+    WriteError(String),
+    #[error("Error reading from storage: {0}")]
+    ReadError(String),
+    #[error("Error opening storage: {0}")]
+    OpenError(String),
+    #[error("Column family not found in storage")]
+    MissingColumnFamily,
+}
+
+impl StorageError {
+    /// Whether the backend itself failed, as opposed to the code or slot not being there.
+    /// The execution can't go on after such a failure without diverging.
+    pub fn is_backend_failure(&self) -> bool {
+        !matches!(self, Self::KeyNotPresent)
+    }
 }
 
 /// May be used to load code when the VM first starts up.
@@ -79,7 +426,7 @@ pub fn initial_decommit(
     let deployer_system_contract_address =
         Address::from_low_u64_be(DEPLOYER_SYSTEM_CONTRACT_ADDRESS_LOW as u64);
     let storage_key = StorageKey::new(deployer_system_contract_address, address_into_u256(address));
-    let code_info = storage.storage_read(&storage_key).unwrap();
+    let code_info = storage.storage_read(&storage_key)?.unwrap();
 
     let mut code_info_bytes = [0; 32];
     code_info.to_big_endian(&mut code_info_bytes);
@@ -91,7 +438,7 @@ pub fn initial_decommit(
     code_info_bytes[1] = 0;
     let code_key: U256 = U256::from_big_endian(&code_info_bytes);
 
-    let code = storage.decommit(code_key);
+    let code = storage.decommit(code_key)?;
     match code {
         Some(code) => Ok(code),
         None => Err(EraVmError::StorageError(StorageError::KeyNotPresent)),
@@ -0,0 +1,275 @@
+use serde::{Serialize, Serializer};
+use u256::H160;
+use zkevm_opcode_defs::{FarCallOpcode, RetOpcode};
+
+use super::tracer::Tracer;
+use crate::{
+    execution::Execution,
+    op_handlers::far_call::address_from_u256,
+    state::VMState,
+    value::{FatPointer, TaggedValue},
+    Opcode, Variant,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum CallType {
+    Normal,
+    Mimic,
+    Delegate,
+    Near,
+    /// A frame that was already running when the tracer was attached
+    Unknown,
+}
+
+impl From<FarCallOpcode> for CallType {
+    fn from(far_call: FarCallOpcode) -> Self {
+        match far_call {
+            FarCallOpcode::Normal => Self::Normal,
+            FarCallOpcode::Mimic => Self::Mimic,
+            FarCallOpcode::Delegate => Self::Delegate,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum CallOutcome {
+    Ok,
+    Revert,
+    Panic,
+}
+
+impl From<RetOpcode> for CallOutcome {
+    fn from(ret: RetOpcode) -> Self {
+        match ret {
+            RetOpcode::Ok => Self::Ok,
+            RetOpcode::Revert => Self::Revert,
+            RetOpcode::Panic => Self::Panic,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Call {
+    #[serde(rename = "type")]
+    pub call_type: CallType,
+    pub from: H160,
+    pub to: H160,
+    pub code_address: H160,
+    pub is_system: bool,
+    pub is_constructor: bool,
+    pub is_static: bool,
+    /// Gas the callee started with
+    pub gas: u32,
+    pub gas_used: u32,
+    #[serde(serialize_with = "serialize_bytes")]
+    pub input: Vec<u8>,
+    #[serde(serialize_with = "serialize_bytes")]
+    pub output: Vec<u8>,
+    pub outcome: CallOutcome,
+    pub calls: Vec<Call>,
+}
+
+impl Call {
+    fn new(call_type: CallType, from: H160, to: H160, code_address: H160, gas: u32) -> Self {
+        Self {
+            call_type,
+            from,
+            to,
+            code_address,
+            is_system: false,
+            is_constructor: false,
+            is_static: false,
+            gas,
+            gas_used: 0,
+            input: vec![],
+            output: vec![],
+            outcome: CallOutcome::Ok,
+            calls: vec![],
+        }
+    }
+}
+
+fn serialize_bytes<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&format!("0x{}", hex::encode(bytes)))
+}
+
+// What the instruction about to be executed may do to the frame stack
+enum PendingCall {
+    Far {
+        call_type: CallType,
+        target: H160,
+        gas_before: u32,
+    },
+    Near,
+    Ret(RetOpcode),
+}
+
+struct OpenCall {
+    call: Call,
+    // gas left before the last instruction executed in this frame
+    gas_left: u32,
+}
+
+/// Reconstructs the tree of far and near calls, along with their gas, calldata and return data.
+/// Frames are tracked by depth: a frame that goes away without a `ret` was panicked, and every
+/// frame above the new depth is closed when several go away in a single step.
+#[derive(Default)]
+pub struct CallTracer {
+    stack: Vec<OpenCall>,
+    pending: Option<PendingCall>,
+}
+
+impl CallTracer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The calls made by the initial frame which have already returned.
+    pub fn calls(&self) -> &[Call] {
+        self.stack
+            .first()
+            .map(|root| root.call.calls.as_slice())
+            .unwrap_or_default()
+    }
+
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self.calls())
+    }
+
+    fn enter_call(&mut self, vm: &Execution, pending: Option<PendingCall>) {
+        let (Ok(context), Ok(gas)) = (vm.current_context(), vm.gas_left()) else {
+            return;
+        };
+        let call = match pending {
+            Some(PendingCall::Far { call_type, .. }) => {
+                // far_call leaves the call flags in r2 and the calldata pointer in r1
+                let flags = vm.get_register(2).value.low_u32();
+                Call {
+                    is_constructor: flags & 1 != 0,
+                    is_system: flags & 2 != 0,
+                    is_static: context.is_static,
+                    input: read_pointed_bytes(vm, vm.get_register(1)),
+                    ..Call::new(
+                        call_type,
+                        context.caller,
+                        context.contract_address,
+                        context.code_address,
+                        gas,
+                    )
+                }
+            }
+            Some(PendingCall::Near) => Call::new(
+                CallType::Near,
+                context.contract_address,
+                context.contract_address,
+                context.code_address,
+                gas,
+            ),
+            _ => Call::new(
+                CallType::Unknown,
+                context.caller,
+                context.contract_address,
+                context.code_address,
+                gas,
+            ),
+        };
+        self.stack.push(OpenCall {
+            call,
+            gas_left: gas,
+        });
+    }
+
+    fn exit_call(&mut self, vm: &Execution, outcome: CallOutcome) {
+        let Some(OpenCall { mut call, gas_left }) = self.stack.pop() else {
+            return;
+        };
+        call.outcome = outcome;
+        call.gas_used = call.gas.saturating_sub(gas_left);
+        if call.call_type != CallType::Near && call.outcome != CallOutcome::Panic {
+            // the returned pointer is left in r1 of the caller
+            call.output = read_pointed_bytes(vm, vm.get_register(1));
+        }
+        if let Some(parent) = self.stack.last_mut() {
+            parent.call.calls.push(call);
+        }
+    }
+
+    // far_call panicked before pushing the new frame
+    fn failed_far_call(&mut self, vm: &Execution, call_type: CallType, target: H160, gas: u32) {
+        let Some(parent) = self.stack.last_mut() else {
+            return;
+        };
+        let from = parent.call.to;
+        let mut call = Call::new(call_type, from, target, target, 0);
+        call.gas_used = gas.saturating_sub(vm.gas_left().unwrap_or_default());
+        call.outcome = CallOutcome::Panic;
+        parent.call.calls.push(call);
+    }
+}
+
+fn read_pointed_bytes(vm: &Execution, register: TaggedValue) -> Vec<u8> {
+    if !register.is_pointer {
+        return vec![];
+    }
+    let pointer = FatPointer::decode(register.value);
+    vm.heaps
+        .get(pointer.page)
+        .and_then(|heap| heap.read_unaligned_from_pointer(&pointer).ok())
+        .unwrap_or_default()
+}
+
+impl Tracer for CallTracer {
+    fn before_decoding(&mut self, vm: &mut Execution, _state: &mut VMState) {
+        let depth = vm.frame_depth();
+        let pending = self.pending.take();
+
+        if self.stack.is_empty() {
+            // frames that were running before the tracer was attached
+            while self.stack.len() < depth {
+                self.enter_call(vm, None);
+            }
+            return;
+        }
+
+        if depth > self.stack.len() {
+            self.enter_call(vm, pending);
+        } else if depth < self.stack.len() {
+            // a frame going away takes the frames above it along, all of them end the same way
+            let outcome = match pending {
+                Some(PendingCall::Ret(ret)) => ret.into(),
+                _ => CallOutcome::Panic,
+            };
+            while self.stack.len() > depth {
+                self.exit_call(vm, outcome);
+            }
+        } else if let Some(PendingCall::Far {
+            call_type,
+            target,
+            gas_before,
+        }) = pending
+        {
+            self.failed_far_call(vm, call_type, target, gas_before);
+        }
+    }
+
+    fn before_execution(&mut self, opcode: &Opcode, vm: &mut Execution, _state: &mut VMState) {
+        if let (Some(open_call), Ok(gas_left)) = (self.stack.last_mut(), vm.gas_left()) {
+            open_call.gas_left = gas_left;
+        }
+
+        if !matches!(vm.can_execute(opcode), Ok(true)) {
+            return;
+        }
+        self.pending = match opcode.variant {
+            Variant::FarCall(far_call) => Some(PendingCall::Far {
+                call_type: far_call.into(),
+                target: address_from_u256(&vm.get_register(opcode.src1_index).value),
+                gas_before: vm.gas_left().unwrap_or_default(),
+            }),
+            Variant::NearCall(_) => Some(PendingCall::Near),
+            Variant::Ret(ret) => Some(PendingCall::Ret(ret)),
+            _ => None,
+        };
+    }
+}
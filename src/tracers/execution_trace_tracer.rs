@@ -0,0 +1,103 @@
+use std::io::{self, Write};
+
+use serde::Serialize;
+use u256::{H160, U256};
+
+use super::tracer::Tracer;
+use crate::{execution::Execution, state::VMState, Opcode};
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TraceRegister {
+    pub value: U256,
+    pub is_pointer: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TraceFlags {
+    pub lt_of: bool,
+    pub gt: bool,
+    pub eq: bool,
+}
+
+/// The vm state right before an instruction is executed.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TraceStep {
+    pub pc: u64,
+    pub opcode: String,
+    /// The instruction in assembly syntax
+    pub instruction: String,
+    pub gas_before: u32,
+    /// Missing if the instruction panicked or ended the execution.
+    pub gas_after: Option<u32>,
+    pub sp: u32,
+    /// r1 to r15, r0 is always zero
+    pub registers: Vec<TraceRegister>,
+    pub flags: TraceFlags,
+    pub contract_address: H160,
+    pub code_address: H160,
+    /// Number of far call frames, the initial one included
+    pub depth: usize,
+}
+
+/// Records every executed step so a run can be diffed against the reference zk_evm trace.
+#[derive(Debug, Default)]
+pub struct ExecutionTraceTracer {
+    pub steps: Vec<TraceStep>,
+}
+
+impl ExecutionTraceTracer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Writes one JSON object per step, one per line.
+    pub fn write_json_lines<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        for step in &self.steps {
+            serde_json::to_writer(&mut writer, step)?;
+            writer.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+}
+
+impl Tracer for ExecutionTraceTracer {
+    fn before_execution(&mut self, opcode: &Opcode, vm: &mut Execution, _state: &mut VMState) {
+        let (Ok(context), Ok(frame)) = (vm.current_context(), vm.current_frame()) else {
+            return;
+        };
+
+        let registers = (1..=15)
+            .map(|index| {
+                let register = vm.get_register(index);
+                TraceRegister {
+                    value: register.value,
+                    is_pointer: register.is_pointer,
+                }
+            })
+            .collect();
+
+        self.steps.push(TraceStep {
+            pc: frame.pc,
+            opcode: format!("{:?}", opcode.variant),
+            instruction: opcode.to_string(),
+            gas_before: frame.gas_left.0,
+            gas_after: None,
+            sp: frame.sp,
+            registers,
+            flags: TraceFlags {
+                lt_of: vm.flag_lt_of,
+                gt: vm.flag_gt,
+                eq: vm.flag_eq,
+            },
+            contract_address: context.contract_address,
+            code_address: context.code_address,
+            depth: vm.running_contexts.len(),
+        });
+    }
+
+    fn after_execution(&mut self, _opcode: &Opcode, vm: &mut Execution, _state: &mut VMState) {
+        if let (Some(step), Ok(gas_left)) = (self.steps.last_mut(), vm.gas_left()) {
+            step.gas_after = Some(gas_left);
+        }
+    }
+}
@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use u256::H160;
+use zkevm_opcode_defs::{LogOpcode, ERGS_PER_CODE_WORD_DECOMMITTMENT};
+
+use super::tracer::Tracer;
+use crate::{call_frame::CallFrame, execution::Execution, state::VMState, Opcode, Variant};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct GasUsage {
+    /// Ergs spent, memory growth and decommits included, refunds not subtracted
+    pub ergs: u64,
+    pub memory_growth: u64,
+    pub decommit: u64,
+    pub refunds: u64,
+    pub count: u64,
+}
+
+impl GasUsage {
+    fn add(&mut self, other: &GasUsage) {
+        self.ergs += other.ergs;
+        self.memory_growth += other.memory_growth;
+        self.decommit += other.decommit;
+        self.refunds += other.refunds;
+        self.count += other.count;
+    }
+}
+
+// The state of the frame right before an instruction is executed
+struct StepStart {
+    variant: Variant,
+    base_cost: u32,
+    code_address: H160,
+    pc: u64,
+    call_stack: String,
+    depth: usize,
+    gas_left: u32,
+    heap_sizes: Vec<(u32, usize)>,
+    refunds: usize,
+    decommitted_hashes: usize,
+}
+
+/// Attributes the ergs spent to each instruction location and to each opcode.
+/// Gas handed to a callee, or given back to the caller on return, is not counted as spent.
+#[derive(Default)]
+pub struct GasProfilerTracer {
+    pub by_location: HashMap<(H160, u64), GasUsage>,
+    pub by_opcode: HashMap<String, GasUsage>,
+    pub total: GasUsage,
+    // ergs spent by each stack of code addresses, with the opcode as the leaf
+    folded_stacks: HashMap<String, u64>,
+    current: Option<StepStart>,
+}
+
+impl GasProfilerTracer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Writes the profile in the folded stack format consumed by flamegraph tools,
+    /// one `caller;callee;Opcode ergs` line per stack.
+    pub fn write_folded_stacks<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let mut stacks: Vec<_> = self.folded_stacks.iter().collect();
+        stacks.sort();
+        for (stack, ergs) in stacks {
+            writeln!(writer, "{} {}", stack, ergs)?;
+        }
+        Ok(())
+    }
+
+    fn record(&mut self, start: &StepStart, usage: GasUsage) {
+        self.by_location
+            .entry((start.code_address, start.pc))
+            .or_default()
+            .add(&usage);
+        self.by_opcode
+            .entry(format!("{:?}", start.variant))
+            .or_default()
+            .add(&usage);
+        self.total.add(&usage);
+        if usage.ergs > 0 {
+            *self
+                .folded_stacks
+                .entry(format!("{};{:?}", start.call_stack, start.variant))
+                .or_default() += usage.ergs;
+        }
+    }
+}
+
+// The frame right below the current one, i.e. the caller after a call has been made
+fn caller_frame(vm: &Execution) -> Option<&CallFrame> {
+    let context = vm.running_contexts.last()?;
+    match context.near_call_frames.len() {
+        0 => {
+            let caller_context = vm.running_contexts.iter().rev().nth(1)?;
+            Some(
+                caller_context
+                    .near_call_frames
+                    .last()
+                    .unwrap_or(&caller_context.frame),
+            )
+        }
+        1 => Some(&context.frame),
+        len => context.near_call_frames.get(len - 2),
+    }
+}
+
+fn memory_growth(vm: &Execution, heap_sizes: &[(u32, usize)]) -> u64 {
+    heap_sizes
+        .iter()
+        .filter_map(|(id, size)| Some(vm.heaps.get(*id)?.len().saturating_sub(*size)))
+        .sum::<usize>() as u64
+        * vm.config.memory_growth_ergs_per_byte as u64
+}
+
+impl Tracer for GasProfilerTracer {
+    fn before_decoding(&mut self, _vm: &mut Execution, _state: &mut VMState) {
+        // the previous instruction panicked, only its base cost can be attributed
+        if let Some(start) = self.current.take() {
+            let usage = GasUsage {
+                ergs: start.base_cost.min(start.gas_left) as u64,
+                count: 1,
+                ..Default::default()
+            };
+            self.record(&start, usage);
+        }
+    }
+
+    fn before_execution(&mut self, opcode: &Opcode, vm: &mut Execution, state: &mut VMState) {
+        let (Ok(context), Ok(frame)) = (vm.current_context(), vm.current_frame()) else {
+            return;
+        };
+        let call_stack = vm
+            .running_contexts
+            .iter()
+            .map(|context| format!("{:?}", context.code_address))
+            .collect::<Vec<_>>()
+            .join(";");
+        let heap_sizes = [context.heap_id, context.aux_heap_id]
+            .into_iter()
+            .filter_map(|id| Some((id, vm.heaps.get(id)?.len())))
+            .collect();
+
+        self.current = Some(StepStart {
+            variant: opcode.variant,
+            base_cost: opcode.gas_cost,
+            code_address: context.code_address,
+            pc: frame.pc,
+            call_stack,
+            depth: vm.frame_depth(),
+            gas_left: frame.gas_left.0,
+            heap_sizes,
+            refunds: state.refunds().len(),
+            decommitted_hashes: state.decommitted_hashes().len(),
+        });
+    }
+
+    fn after_execution(&mut self, _opcode: &Opcode, vm: &mut Execution, state: &mut VMState) {
+        let Some(start) = self.current.take() else {
+            return;
+        };
+        let depth = vm.frame_depth();
+        let memory_growth = memory_growth(vm, &start.heap_sizes);
+        let refunds = state.refunds()[start.refunds..]
+            .iter()
+            .map(|refund| *refund as u64)
+            .sum::<u64>();
+
+        let spent = if depth > start.depth {
+            // a call: what the caller lost minus what the callee was given
+            let (Some(caller), Ok(callee)) = (caller_frame(vm), vm.current_frame()) else {
+                return;
+            };
+            let passed = (callee.gas_left - callee.stipend).0;
+            (start.gas_left as u64).saturating_sub(caller.gas_left.0 as u64 + passed as u64)
+        } else if depth < start.depth {
+            // a return: the callee's gas goes back to the caller
+            start.base_cost as u64 + memory_growth
+        } else {
+            let gas_left = vm.gas_left().unwrap_or_default();
+            (start.gas_left as u64 + refunds).saturating_sub(gas_left as u64)
+        };
+
+        let decommit = if state.decommitted_hashes().len() <= start.decommitted_hashes {
+            0
+        } else {
+            match start.variant {
+                Variant::FarCall(_) => vm
+                    .current_context()
+                    .map(|context| {
+                        context.code_page.len() as u64 * ERGS_PER_CODE_WORD_DECOMMITTMENT as u64
+                    })
+                    .unwrap_or_default(),
+                Variant::Log(LogOpcode::Decommit) => {
+                    spent.saturating_sub(start.base_cost as u64 + memory_growth)
+                }
+                _ => 0,
+            }
+        };
+
+        let usage = GasUsage {
+            ergs: spent,
+            memory_growth,
+            decommit,
+            refunds,
+            count: 1,
+        };
+        self.record(&start, usage);
+    }
+}
@@ -1,4 +1,7 @@
 pub mod blob_saver_tracer;
+pub mod call_tracer;
+pub mod execution_trace_tracer;
+pub mod gas_profiler_tracer;
 pub mod last_state_saver_tracer;
 pub mod no_tracer;
 pub mod print_tracer;
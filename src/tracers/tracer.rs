@@ -1,4 +1,4 @@
-use crate::{execution::Execution, state::VMState, Opcode};
+use crate::{eravm_error::PrecompileError, execution::Execution, state::VMState, Opcode};
 
 pub trait Tracer {
     fn before_decoding(&mut self, _execution: &mut Execution, _state: &mut VMState) {}
@@ -23,4 +23,12 @@ pub trait Tracer {
         _state: &mut VMState,
     ) {
     }
+    /// Called when a precompile call writes a failure instead of its output, before `after_execution`
+    fn on_precompile_failure(
+        &mut self,
+        _error: &PrecompileError,
+        _execution: &mut Execution,
+        _state: &mut VMState,
+    ) {
+    }
 }
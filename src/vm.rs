@@ -1,51 +1,19 @@
-use u256::U256;
-use zkevm_opcode_defs::{
-    BinopOpcode, ContextOpcode, LogOpcode, PtrOpcode, RetOpcode, ShiftOpcode, UMAOpcode,
-};
-
-use crate::address_operands::{address_operands_read, address_operands_store};
-use crate::eravm_error::{HeapError, OpcodeError};
+use crate::config::VmConfig;
+use crate::dispatch::{Flow, Instruction};
+use crate::eravm_error::{ConfigError, HeapError};
 use crate::execution::ExecutionSnapshot;
-use crate::op_handlers::add::add;
-use crate::op_handlers::and::and;
-use crate::op_handlers::aux_heap_read::aux_heap_read;
-use crate::op_handlers::aux_heap_write::aux_heap_write;
-use crate::op_handlers::context::{
-    caller, code_address, ergs_left, get_context_u128, increment_tx_number, meta, set_context_u128,
-    sp, this,
-};
-use crate::op_handlers::div::div;
-use crate::op_handlers::event::event;
-use crate::op_handlers::far_call::far_call;
-use crate::op_handlers::fat_pointer_read::fat_pointer_read;
-use crate::op_handlers::heap_read::heap_read;
-use crate::op_handlers::heap_write::heap_write;
-use crate::op_handlers::jump::jump;
-use crate::op_handlers::log::{
-    add_l2_to_l1_message, storage_read, storage_write, transient_storage_read,
-    transient_storage_write,
-};
-use crate::op_handlers::mul::mul;
-use crate::op_handlers::near_call::near_call;
-use crate::op_handlers::opcode_decommit::opcode_decommit;
-use crate::op_handlers::or::or;
-use crate::op_handlers::precompile_call::precompile_call;
-use crate::op_handlers::ptr_add::ptr_add;
-use crate::op_handlers::ptr_pack::ptr_pack;
-use crate::op_handlers::ptr_shrink::ptr_shrink;
-use crate::op_handlers::ptr_sub::ptr_sub;
-use crate::op_handlers::ret::{inexplicit_panic, panic_from_far_call, ret};
-use crate::op_handlers::shift::{rol, ror, shl, shr};
-use crate::op_handlers::sub::sub;
-use crate::op_handlers::unimplemented::unimplemented;
-use crate::op_handlers::xor::xor;
+use crate::op_handlers::ret::inexplicit_panic;
+use crate::precompiles::registry::PrecompileRegistry;
 use crate::state::{ExternalStateSnapshot, VMState};
 use crate::statistics::VmStatistics;
-use crate::store::Storage;
+use crate::store::{Storage, StorageError, StorageWrite};
 use crate::tracers::no_tracer::NoTracer;
-use crate::value::{FatPointer, TaggedValue};
+use crate::value::FatPointer;
 use crate::{eravm_error::EraVmError, tracers::tracer::Tracer, Execution};
 use crate::{Opcode, Variant};
+use std::collections::HashMap;
+use std::sync::Arc;
+use u256::U256;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ExecutionOutput {
@@ -60,6 +28,7 @@ pub struct EraVM {
     pub state: VMState,
     pub statistics: VmStatistics,
     pub execution: Execution,
+    pub precompiles: Arc<PrecompileRegistry>,
 }
 
 pub struct VmSnapshot {
@@ -68,6 +37,7 @@ pub struct VmSnapshot {
     state: ExternalStateSnapshot,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum EncodingMode {
     Production,
     Testing,
@@ -75,10 +45,41 @@ pub enum EncodingMode {
 
 impl EraVM {
     pub fn new(execution: Execution) -> Self {
+        Self::build(
+            execution,
+            VmConfig::default(),
+            PrecompileRegistry::default(),
+        )
+    }
+
+    /// Creates a vm with the given pricing, limits and protocol version instead of mainnet's.
+    /// Fails if the config doesn't pass `VmConfig::validate`.
+    pub fn with_config(execution: Execution, config: VmConfig) -> Result<Self, ConfigError> {
+        Self::with_precompiles(execution, config, PrecompileRegistry::default())
+    }
+
+    /// Creates a vm that runs the given precompiles instead of just the ones of the protocol.
+    /// Fails if the config doesn't pass `VmConfig::validate`.
+    pub fn with_precompiles(
+        execution: Execution,
+        config: VmConfig,
+        precompiles: PrecompileRegistry,
+    ) -> Result<Self, ConfigError> {
+        config.validate()?;
+        Ok(Self::build(execution, config, precompiles))
+    }
+
+    fn build(mut execution: Execution, config: VmConfig, precompiles: PrecompileRegistry) -> Self {
+        execution.config = config;
+        // The initial code doesn't go through the decommitter, so it gets decoded here instead
+        if let Ok(context) = execution.current_context_mut() {
+            context.code_page = context.code_page.clone().decode(config.protocol_version);
+        }
         Self {
             state: VMState::new(),
             statistics: VmStatistics::default(),
             execution,
+            precompiles: Arc::new(precompiles),
         }
     }
 
@@ -139,6 +140,17 @@ impl EraVM {
         self.statistics = snapshot.statistics;
     }
 
+    /// Persists the result of the run into the given storage.
+    /// `published_contracts` is the bytecode published during the run, e.g. the blobs collected by the `BlobSaverTracer`.
+    pub fn commit(
+        &mut self,
+        storage: &mut dyn StorageWrite,
+        published_contracts: HashMap<U256, Vec<U256>>,
+    ) -> Result<(), StorageError> {
+        let diff = self.state.state_diff(storage, published_contracts)?;
+        storage.commit(diff)
+    }
+
     /// Run a vm program from the given path using a custom state.
     /// Returns the value stored at storage with key 0 and the final vm state.
     pub fn program_from_file(&self, bin_path: &str) -> Result<Vec<U256>, EraVmError> {
@@ -161,211 +173,79 @@ impl EraVM {
         Ok(program_code)
     }
 
-    #[allow(non_upper_case_globals)]
     pub fn run(
         &mut self,
         tracer: &mut dyn Tracer,
         enc_mode: EncodingMode,
         storage: &mut dyn Storage,
     ) -> Result<ExecutionOutput, EraVmError> {
+        self.decode_code(enc_mode);
         loop {
-            tracer.before_decoding(&mut self.execution, &mut self.state);
-            let opcode = match enc_mode {
-                EncodingMode::Testing => self.execution.get_opcode_with_test_encode()?,
-                EncodingMode::Production => self.execution.get_opcode()?,
-            };
-            tracer.after_decoding(&opcode, &mut self.execution, &mut self.state);
-
-            tracer.before_execution(&opcode, &mut self.execution, &mut self.state);
-            let can_execute = self.execution.can_execute(&opcode);
+            if let Some(output) = self.run_step(tracer, enc_mode, storage)? {
+                return Ok(output);
+            }
+        }
+    }
 
-            if self.execution.decrease_gas(opcode.gas_cost).is_err() || can_execute.is_err() {
-                match inexplicit_panic(&mut self.execution, &mut self.state) {
-                    Ok(false) => continue,
-                    _ => return Ok(ExecutionOutput::Panic),
-                }
+    /// Decodes the code of the running frame up front for the given encoding if it isn't yet.
+    /// Code decommitted later on is decoded for the production encoding.
+    pub(crate) fn decode_code(&mut self, enc_mode: EncodingMode) {
+        let version = self.execution.config.protocol_version;
+        if let Ok(context) = self.execution.current_context_mut() {
+            if !context.code_page.is_decoded_with(enc_mode) {
+                context.code_page = context.code_page.clone().decode_with(enc_mode, version);
             }
+        }
+    }
 
-            if can_execute? {
-                let result = match opcode.variant {
-                    Variant::Invalid(_) => Err(OpcodeError::InvalidOpCode.into()),
-                    Variant::Nop(_) => {
-                        address_operands_read(&mut self.execution, &opcode)?;
-                        address_operands_store(
-                            &mut self.execution,
-                            &opcode,
-                            TaggedValue::new_raw_integer(0.into()),
-                        )
-                    }
-                    Variant::Add(_) => add(&mut self.execution, &opcode),
-                    Variant::Sub(_) => sub(&mut self.execution, &opcode),
-                    Variant::Jump(_) => jump(&mut self.execution, &opcode),
-                    Variant::Mul(_) => mul(&mut self.execution, &opcode),
-                    Variant::Div(_) => div(&mut self.execution, &opcode),
-                    Variant::Context(context_variant) => match context_variant {
-                        ContextOpcode::AuxMutating0 => unimplemented(&mut self.execution, &opcode),
-                        ContextOpcode::Caller => caller(&mut self.execution, &opcode),
-                        ContextOpcode::CodeAddress => code_address(&mut self.execution, &opcode),
-                        ContextOpcode::ErgsLeft => ergs_left(&mut self.execution, &opcode),
-                        ContextOpcode::GetContextU128 => {
-                            get_context_u128(&mut self.execution, &opcode)
-                        }
-                        ContextOpcode::IncrementTxNumber => {
-                            increment_tx_number(&mut self.execution, &opcode, &mut self.state)
-                        }
-                        ContextOpcode::Meta => meta(&mut self.execution, &opcode, &self.state),
-                        ContextOpcode::SetContextU128 => {
-                            set_context_u128(&mut self.execution, &opcode)
-                        }
-                        ContextOpcode::Sp => sp(&mut self.execution, &opcode),
-                        ContextOpcode::This => this(&mut self.execution, &opcode),
-                    },
-                    Variant::Shift(shift_variant) => match shift_variant {
-                        ShiftOpcode::Shl => shl(&mut self.execution, &opcode),
-                        ShiftOpcode::Shr => shr(&mut self.execution, &opcode),
-                        ShiftOpcode::Rol => rol(&mut self.execution, &opcode),
-                        ShiftOpcode::Ror => ror(&mut self.execution, &opcode),
-                    },
-                    Variant::Binop(binop) => match binop {
-                        BinopOpcode::Xor => xor(&mut self.execution, &opcode),
-                        BinopOpcode::And => and(&mut self.execution, &opcode),
-                        BinopOpcode::Or => or(&mut self.execution, &opcode),
-                    },
-                    Variant::Ptr(ptr_variant) => match ptr_variant {
-                        PtrOpcode::Add => ptr_add(&mut self.execution, &opcode),
-                        PtrOpcode::Sub => ptr_sub(&mut self.execution, &opcode),
-                        PtrOpcode::Pack => ptr_pack(&mut self.execution, &opcode),
-                        PtrOpcode::Shrink => ptr_shrink(&mut self.execution, &opcode),
-                    },
-                    Variant::NearCall(_) => near_call(&mut self.execution, &opcode, &self.state),
-                    Variant::Log(log_variant) => match log_variant {
-                        LogOpcode::StorageRead => storage_read(
-                            &mut self.execution,
-                            &opcode,
-                            &mut self.state,
-                            &mut self.statistics,
-                            storage,
-                        ),
-                        LogOpcode::StorageWrite => storage_write(
-                            &mut self.execution,
-                            &opcode,
-                            &mut self.state,
-                            &mut self.statistics,
-                            storage,
-                        ),
-                        LogOpcode::ToL1Message => {
-                            add_l2_to_l1_message(&mut self.execution, &opcode, &mut self.state)
-                        }
-                        LogOpcode::PrecompileCall => precompile_call(
-                            &mut self.execution,
-                            &opcode,
-                            &mut self.state,
-                            &mut self.statistics,
-                        ),
-                        LogOpcode::Event => event(&mut self.execution, &opcode, &mut self.state),
-                        LogOpcode::Decommit => opcode_decommit(
-                            &mut self.execution,
-                            &opcode,
-                            &mut self.state,
-                            &mut self.statistics,
-                            storage,
-                        ),
-                        LogOpcode::TransientStorageRead => {
-                            transient_storage_read(&mut self.execution, &opcode, &mut self.state)
-                        }
-                        LogOpcode::TransientStorageWrite => {
-                            transient_storage_write(&mut self.execution, &opcode, &mut self.state)
-                        }
-                    },
-                    Variant::FarCall(far_call_variant) => {
-                        let res = far_call(
-                            &mut self.execution,
-                            &opcode,
-                            &far_call_variant,
-                            &mut self.state,
-                            &mut self.statistics,
-                            storage,
-                        );
-                        if res.is_err() {
-                            panic_from_far_call(&mut self.execution, &opcode)?;
-                            continue;
-                        }
-                        Ok(())
-                    }
-                    Variant::Ret(ret_variant) => match ret_variant {
-                        RetOpcode::Ok => {
-                            match ret(&mut self.execution, &opcode, &mut self.state, ret_variant) {
-                                Ok(should_break) => {
-                                    if should_break {
-                                        let result = retrieve_result(&mut self.execution)?;
-                                        return Ok(ExecutionOutput::Ok(result));
-                                    }
-                                    Ok(())
-                                }
-                                Err(e) => Err(e),
-                            }
-                        }
-                        RetOpcode::Revert => {
-                            match ret(&mut self.execution, &opcode, &mut self.state, ret_variant) {
-                                Ok(should_break) => {
-                                    if should_break {
-                                        let result = retrieve_result(&mut self.execution)?;
-                                        return Ok(ExecutionOutput::Revert(result));
-                                    }
-                                    Ok(())
-                                }
-                                Err(e) => Err(e),
-                            }
-                        }
-                        RetOpcode::Panic => {
-                            match ret(&mut self.execution, &opcode, &mut self.state, ret_variant) {
-                                Ok(should_break) => {
-                                    if should_break {
-                                        return Ok(ExecutionOutput::Panic);
-                                    }
-                                    Ok(())
-                                }
-                                Err(e) => Err(e),
-                            }
-                        }
-                    },
-                    Variant::UMA(uma_variant) => match uma_variant {
-                        UMAOpcode::HeapRead => heap_read(&mut self.execution, &opcode),
-                        UMAOpcode::HeapWrite => {
-                            let result = heap_write(&mut self.execution, &opcode);
-                            match result {
-                                exec_hook @ Ok(ExecutionOutput::SuspendedOnHook { .. }) => {
-                                    return exec_hook
-                                }
-                                Ok(_) => Ok(()),
-                                Err(e) => Err(e),
-                            }
-                        }
+    /// Decodes and executes a single instruction.
+    /// Returns the output once the execution finishes or gets suspended on a hook.
+    pub(crate) fn run_step(
+        &mut self,
+        tracer: &mut dyn Tracer,
+        enc_mode: EncodingMode,
+        storage: &mut dyn Storage,
+    ) -> Result<Option<ExecutionOutput>, EraVmError> {
+        tracer.before_decoding(&mut self.execution, &mut self.state);
+        let Instruction { opcode, handler } = match enc_mode {
+            EncodingMode::Testing => self.execution.get_instruction_with_test_encode()?,
+            EncodingMode::Production => self.execution.get_instruction()?,
+        };
+        tracer.after_decoding(&opcode, &mut self.execution, &mut self.state);
 
-                        UMAOpcode::AuxHeapRead => aux_heap_read(&mut self.execution, &opcode),
-                        UMAOpcode::AuxHeapWrite => aux_heap_write(&mut self.execution, &opcode),
-                        UMAOpcode::FatPointerRead => fat_pointer_read(&mut self.execution, &opcode),
-                        UMAOpcode::StaticMemoryRead => unimplemented(&mut self.execution, &opcode),
-                        UMAOpcode::StaticMemoryWrite => unimplemented(&mut self.execution, &opcode),
-                    },
-                };
-                if let Err(err) = result {
-                    if let EraVmError::OpcodeError(OpcodeError::UnimplementedOpcode) = err {
-                        return Ok(ExecutionOutput::Panic);
-                    }
+        tracer.before_execution(&opcode, &mut self.execution, &mut self.state);
+        let can_execute = self.execution.can_execute(&opcode);
 
-                    match inexplicit_panic(&mut self.execution, &mut self.state) {
-                        Ok(false) => continue,
-                        _ => return Ok(ExecutionOutput::Panic),
-                    }
+        if self.execution.decrease_gas(opcode.gas_cost).is_err() || can_execute.is_err() {
+            match inexplicit_panic(&mut self.execution, &mut self.state) {
+                Ok(false) => return Ok(None),
+                _ => return Ok(Some(ExecutionOutput::Panic)),
+            }
+        }
+
+        if can_execute? {
+            match handler(self, &opcode, storage) {
+                Ok(Flow::Continue) => {}
+                Ok(Flow::PrecompileFailed(error)) => {
+                    tracer.on_precompile_failure(&error, &mut self.execution, &mut self.state)
+                }
+                Ok(Flow::Redirected) => return Ok(None),
+                Ok(Flow::Exit(output)) => return Ok(Some(output)),
+                Err(EraVmError::StorageError(err)) if err.is_backend_failure() => {
+                    return Err(err.into())
                 }
-                set_pc(&mut self.execution, &opcode)?;
-            } else {
-                self.execution.current_frame_mut()?.pc += 1;
+                Err(_) => match inexplicit_panic(&mut self.execution, &mut self.state) {
+                    Ok(false) => return Ok(None),
+                    _ => return Ok(Some(ExecutionOutput::Panic)),
+                },
             }
-            self.statistics.monotonic_counter += 1;
-            tracer.after_execution(&opcode, &mut self.execution, &mut self.state);
+            set_pc(&mut self.execution, &opcode)?;
+        } else {
+            self.execution.current_frame_mut()?.pc += 1;
         }
+        self.statistics.monotonic_counter += 1;
+        tracer.after_execution(&opcode, &mut self.execution, &mut self.state);
+        Ok(None)
     }
 }
 
@@ -384,7 +264,7 @@ fn set_pc(vm: &mut Execution, opcode: &Opcode) -> Result<(), EraVmError> {
     Ok(())
 }
 
-fn retrieve_result(vm: &mut Execution) -> Result<Vec<u8>, EraVmError> {
+pub(crate) fn retrieve_result(vm: &mut Execution) -> Result<Vec<u8>, EraVmError> {
     let fat_pointer_src0 = FatPointer::decode(vm.get_register(1).value);
     let range = fat_pointer_src0.start..fat_pointer_src0.start + fat_pointer_src0.len;
     let mut result: Vec<u8> = vec![0; range.len()];
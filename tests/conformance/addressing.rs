@@ -0,0 +1,142 @@
+use u256::U256;
+
+use crate::common::Fixture;
+
+#[test]
+fn largest_immediate() {
+    let outcome = Fixture::new(
+        "
+        add 65535, r0, r1
+        add 0xffff, r0, r2
+        ret.ok r0
+        ",
+    )
+    .run();
+    assert_eq!(outcome.reg(1), U256::from(u16::MAX));
+    assert_eq!(outcome.reg(2), U256::from(u16::MAX));
+}
+
+#[test]
+fn stack_addressing_modes() {
+    let outcome = Fixture::new(
+        "
+        nop r0, stack+=[3]
+        add 11, r0, stack[0]
+        add 22, r0, stack-[2]
+        add 1, r0, r7
+        add 33, r0, stack[r7+1]
+        add stack[0], r0, r1
+        add stack-[2], r0, r2
+        add stack-[r7], r0, r3
+        context.sp r4
+        add stack-=[r7+1], r0, r5
+        context.sp r6
+        ret.ok r0
+        ",
+    )
+    .run();
+    outcome.assert_ok();
+    assert_eq!(outcome.reg(1), U256::from(11));
+    assert_eq!(outcome.reg(2), U256::from(22));
+    assert_eq!(outcome.reg(3), U256::from(33));
+    assert_eq!(outcome.reg(4), U256::from(3));
+    assert_eq!(outcome.reg(5), U256::from(22));
+    assert_eq!(outcome.reg(6), U256::one());
+    assert_eq!(outcome.gas_used(), outcome.opcode_gas);
+}
+
+#[test]
+fn reading_above_the_stack_pointer_panics() {
+    let outcome = Fixture::new(
+        "
+        nop r0, stack+=[1]
+        add stack[1], r0, r1
+        ret.ok r0
+        ",
+    )
+    .run();
+    assert_eq!(outcome.output, era_vm::vm::ExecutionOutput::Panic);
+}
+
+#[test]
+fn writing_past_the_stack_size_limit_panics() {
+    let outcome = Fixture::new(
+        "
+        nop r0, stack+=[65535]
+        nop r0, stack+=[65535]
+        add 1, r0, stack-[1]
+        ret.ok r0
+        ",
+    )
+    .run();
+    assert_eq!(outcome.output, era_vm::vm::ExecutionOutput::Panic);
+    assert!(outcome
+        .vm
+        .execution
+        .current_context()
+        .unwrap()
+        .stack
+        .stack
+        .is_empty());
+}
+
+#[test]
+fn code_page_constants() {
+    let outcome = Fixture::new(
+        "
+        .text
+        add code[@CPI0], r0, r1
+        add 1, r0, r2
+        add code[r2+@CPI0], r0, r3
+        ret.ok r0
+        .rodata
+    CPI0:
+        .cell 0xdeadbeefdeadbeefdeadbeefdeadbeefdeadbeef
+        .cell 12345
+        ",
+    )
+    .run();
+    assert_eq!(
+        outcome.reg(1),
+        U256::from_str_radix("deadbeefdeadbeefdeadbeefdeadbeefdeadbeef", 16).unwrap()
+    );
+    assert_eq!(outcome.reg(3), U256::from(12345));
+}
+
+#[test]
+fn code_page_past_the_end_reads_zero() {
+    let outcome = Fixture::new(
+        "
+        add code[1000], r0, r1
+        ret.ok r0
+        ",
+    )
+    .run();
+    outcome.assert_ok();
+    assert_eq!(outcome.reg(1), U256::zero());
+}
+
+#[test]
+fn jump_to_label_and_register() {
+    let outcome = Fixture::new(
+        "
+        jump @skip, r1
+        add 1, r0, r2
+    skip:
+        add 5, r0, r3
+        add 7, r0, r4
+        jump r4
+        add 1, r0, r5
+        ret.ok r0
+        add 9, r0, r6
+        ret.ok r0
+        ",
+    )
+    .run();
+    outcome.assert_ok();
+    // jump stores the target it jumped to
+    assert_eq!(outcome.reg(1), U256::from(2));
+    assert_eq!(outcome.reg(2), U256::zero());
+    assert_eq!(outcome.reg(5), U256::zero());
+    assert_eq!(outcome.reg(6), U256::from(9));
+}
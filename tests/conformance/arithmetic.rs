@@ -0,0 +1,199 @@
+use std::collections::HashMap;
+
+use era_vm::{
+    assembler::assemble,
+    config::ProtocolVersion,
+    store::InitialStorageMemory,
+    vm::{EncodingMode, ExecutionOutput},
+    EraVM, Execution,
+};
+use u256::U256;
+
+use crate::common::{caller_address, user_address, Fixture, INITIAL_GAS};
+
+#[test]
+fn add_registers_and_immediates() {
+    let outcome = Fixture::new(
+        "
+        add 7, r0, r1
+        add 5, r1, r2
+        add r1, r2, r3
+        ret.ok r0
+        ",
+    )
+    .run();
+    outcome.assert_ok();
+    assert_eq!(outcome.reg(2), U256::from(12));
+    assert_eq!(outcome.reg(3), U256::from(19));
+    // flags are left alone without `!`
+    assert_eq!(outcome.flags(), (false, false, false));
+    assert_eq!(outcome.gas_used(), outcome.opcode_gas);
+}
+
+#[test]
+fn add_sets_overflow_and_eq() {
+    let outcome = Fixture::new(
+        "
+        sub.s 1, r0, r1
+        add! 1, r1, r2
+        ret.ok r0
+        ",
+    )
+    .run();
+    assert_eq!(outcome.reg(1), U256::MAX);
+    assert_eq!(outcome.reg(2), U256::zero());
+    assert_eq!(outcome.flags(), (true, false, true));
+}
+
+#[test]
+fn sub_with_swap_and_flags() {
+    let outcome = Fixture::new(
+        "
+        add 5, r0, r1
+        sub! 3, r1, r2
+        sub.s! 3, r1, r3
+        ret.ok r0
+        ",
+    )
+    .run();
+    assert_eq!(outcome.reg(2), U256::MAX - 1);
+    assert_eq!(outcome.reg(3), U256::from(2));
+    assert_eq!(outcome.flags(), (false, true, false));
+}
+
+#[test]
+fn mul_writes_low_and_high_halves() {
+    let outcome = Fixture::new(
+        "
+        add 6, r0, r1
+        mul 7, r1, r2, r3
+        sub.s 1, r0, r4
+        mul! 2, r4, r5, r6
+        ret.ok r0
+        ",
+    )
+    .run();
+    assert_eq!(outcome.reg(2), U256::from(42));
+    assert_eq!(outcome.reg(3), U256::zero());
+    assert_eq!(outcome.reg(5), U256::MAX - 1);
+    assert_eq!(outcome.reg(6), U256::one());
+    assert_eq!(outcome.flags(), (true, false, false));
+}
+
+#[test]
+fn div_writes_quotient_and_remainder() {
+    let outcome = Fixture::new(
+        "
+        add 47, r0, r1
+        div.s! 5, r1, r2, r3
+        ret.ok r0
+        ",
+    )
+    .run();
+    assert_eq!(outcome.reg(2), U256::from(9));
+    assert_eq!(outcome.reg(3), U256::from(2));
+    assert_eq!(outcome.flags(), (false, false, false));
+}
+
+#[test]
+fn div_by_zero_sets_overflow() {
+    let outcome = Fixture::new(
+        "
+        add 47, r0, r1
+        div! r1, r0, r2, r3
+        ret.ok r0
+        ",
+    )
+    .run();
+    outcome.assert_ok();
+    assert_eq!(outcome.reg(2), U256::zero());
+    assert_eq!(outcome.reg(3), U256::zero());
+    assert_eq!(outcome.flags(), (true, false, true));
+}
+
+#[test]
+fn shifts_and_rotations() {
+    let outcome = Fixture::new(
+        "
+        add 1, r0, r1
+        shl.s 255, r1, r2
+        shr.s 255, r2, r3
+        rol.s 1, r2, r4
+        ror.s 1, r1, r5
+        shr.s! 1, r1, r6
+        ret.ok r0
+        ",
+    )
+    .run();
+    assert_eq!(outcome.reg(2), U256::one() << 255);
+    assert_eq!(outcome.reg(3), U256::one());
+    assert_eq!(outcome.reg(4), U256::one());
+    assert_eq!(outcome.reg(5), U256::one() << 255);
+    assert_eq!(outcome.reg(6), U256::zero());
+    assert_eq!(outcome.flags(), (false, false, true));
+}
+
+#[test]
+fn bitwise_operations() {
+    let outcome = Fixture::new(
+        "
+        add 12, r0, r1
+        and 10, r1, r2
+        or 10, r1, r3
+        xor 10, r1, r4
+        and! 3, r1, r5
+        ret.ok r0
+        ",
+    )
+    .run();
+    assert_eq!(outcome.reg(2), U256::from(8));
+    assert_eq!(outcome.reg(3), U256::from(14));
+    assert_eq!(outcome.reg(4), U256::from(6));
+    assert_eq!(outcome.reg(5), U256::zero());
+    assert_eq!(outcome.flags(), (false, false, true));
+}
+
+#[test]
+fn invalid_opcode_panics() {
+    let outcome = Fixture::new(
+        "
+        add 1, r0, r1
+        invalid
+        ",
+    )
+    .run();
+    assert_eq!(outcome.output, era_vm::vm::ExecutionOutput::Panic);
+}
+
+#[test]
+fn testing_encoding_runs_pre_decoded() {
+    let code = assemble(
+        "
+        add 5, r0, r1
+        sub.s 2, r1, r2
+        ret.ok r0
+        ",
+        EncodingMode::Testing,
+        ProtocolVersion::default(),
+    )
+    .unwrap();
+    let execution = Execution::new(
+        code,
+        vec![],
+        user_address(),
+        caller_address(),
+        0,
+        [0; 32],
+        [0; 32],
+        0,
+        false,
+        INITIAL_GAS,
+    );
+    let mut vm = EraVM::new(execution);
+    let mut storage = InitialStorageMemory::new(HashMap::new(), HashMap::new());
+    let output = vm.run_program_with_test_encode(&mut storage);
+    assert_eq!(output, ExecutionOutput::Ok(vec![]));
+    let context = vm.execution.current_context().unwrap();
+    assert!(context.code_page.is_decoded_with(EncodingMode::Testing));
+    assert_eq!(vm.execution.get_register(2).value, U256::from(3));
+}
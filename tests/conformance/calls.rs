@@ -0,0 +1,514 @@
+use era_vm::{
+    config::VmConfig,
+    eravm_error::ConfigError,
+    store::{InitialStorageMemory, Storage, StorageError, StorageKey},
+    tracers::no_tracer::NoTracer,
+    utils::address_into_u256,
+    vm::{EncodingMode, ExecutionOutput},
+    EraVM,
+};
+use u256::{H160, U256};
+
+use crate::common::{caller_address, cost_of, kernel_address, user_address, Fixture};
+
+#[test]
+fn near_call_and_return() {
+    let outcome = Fixture::new(
+        "
+        near_call r0, @callee, @handler
+        add 2, r0, r3
+        ret.ok r0
+    callee:
+        add 1, r0, r2
+        ret.ok r0
+    handler:
+        add 99, r0, r4
+        ret.ok r0
+        ",
+    )
+    .run();
+    outcome.assert_ok();
+    assert_eq!(outcome.reg(2), U256::one());
+    assert_eq!(outcome.reg(3), U256::from(2));
+    assert_eq!(outcome.reg(4), U256::zero());
+    assert_eq!(outcome.gas_used(), outcome.opcode_gas);
+}
+
+#[test]
+fn near_call_passes_the_requested_gas() {
+    let outcome = Fixture::new(
+        "
+        add 1000, r0, r1
+        near_call r1, @callee, @handler
+        ret.ok r0
+    callee:
+        context.ergs_left r2
+        ret.ok r0
+    handler:
+        ret.ok r0
+        ",
+    )
+    .run();
+    assert_eq!(
+        outcome.reg(2),
+        U256::from(1000 - cost_of("context.ergs_left r2"))
+    );
+    // what the callee didn't spend goes back to the caller
+    assert_eq!(outcome.gas_used(), outcome.opcode_gas);
+}
+
+#[test]
+fn near_call_panic_jumps_to_the_exception_handler() {
+    let outcome = Fixture::new(
+        "
+        near_call r0, @callee, @handler
+        add 2, r0, r3
+        ret.ok r0
+    callee:
+        add 5, r0, r1
+        add 6, r0, r2
+        sstore r1, r2
+        ret.panic
+    handler:
+        add 99, r0, r4
+        ret.ok r0
+        ",
+    )
+    .run();
+    outcome.assert_ok();
+    assert_eq!(outcome.reg(3), U256::zero());
+    assert_eq!(outcome.reg(4), U256::from(99));
+    assert_eq!(outcome.flags(), (true, false, false));
+    // the storage write is rolled back with the frame
+    assert!(outcome.vm.state.storage_changes().is_empty());
+}
+
+#[test]
+fn near_call_past_the_depth_limit_panics() {
+    let config = VmConfig {
+        max_near_call_depth: 1,
+        ..VmConfig::default()
+    };
+    let outcome = Fixture::new(
+        "
+        near_call r0, @first, @handler
+        ret.ok r0
+    first:
+        near_call r0, @second, @handler
+        ret.ok r0
+    second:
+        add 1, r0, r2
+        ret.ok r0
+    handler:
+        add 99, r0, r4
+        ret.ok r0
+        ",
+    )
+    .with_config(config)
+    .run();
+    outcome.assert_ok();
+    assert_eq!(outcome.reg(2), U256::zero());
+    assert_eq!(outcome.reg(4), U256::from(99));
+    assert_eq!(outcome.flags(), (true, false, false));
+}
+
+#[test]
+fn near_call_return_to_label() {
+    let outcome = Fixture::new(
+        "
+        near_call r0, @callee, @handler
+        add 2, r0, r3
+    handler:
+        ret.ok r0
+    callee:
+        ret.ok.to_label r0, @label
+    label:
+        add 7, r0, r4
+        ret.ok r0
+        ",
+    )
+    .run();
+    outcome.assert_ok();
+    assert_eq!(outcome.reg(3), U256::zero());
+    assert_eq!(outcome.reg(4), U256::from(7));
+}
+
+fn callee_address() -> H160 {
+    H160::from_low_u64_be(0x100000)
+}
+
+// Calls the callee passing 10000 ergs and then reads the first word it returned into r3.
+// The exception handler sets r5.
+const FAR_CALLER: &str = "
+        add 10000, r0, r1
+        shl.s 192, r1, r1
+        add 1, r0, r2
+        shl.s 20, r2, r2
+        add 3, r0, r15
+        shl.s 16, r15, r15
+        {call} r1, r2, @handler
+        ld r1, r3
+        ret.ok r0
+    handler:
+        add 1, r0, r5
+        ld r1, r3
+        ret.ok r0
+";
+
+// Returns `value` from its heap in a 32 byte slice
+fn returning(value: &str) -> String {
+    format!(
+        "
+        {value}
+        st.1 0, r2
+        add 32, r0, r1
+        shl.s 96, r1, r1
+        ret.ok r1
+        "
+    )
+}
+
+fn far_call(call: &str, callee: &str) -> crate::common::Outcome {
+    Fixture::new(&FAR_CALLER.replace("{call}", call))
+        .with_contract(callee_address(), callee)
+        .run()
+}
+
+#[test]
+fn far_call_returns_data() {
+    let outcome = far_call("far_call", &returning("add 42, r0, r2"));
+    outcome.assert_ok();
+    assert_eq!(outcome.reg(3), U256::from(42));
+    assert_eq!(outcome.reg(5), U256::zero());
+    // registers other than the returned pointer are cleared
+    assert_eq!(outcome.reg(2), U256::zero());
+}
+
+#[test]
+fn returned_data_outlives_the_reuse_of_callee_heaps() {
+    let outcome = Fixture::new(
+        "
+        nop r0, stack+=[1]
+        add 10000, r0, r1
+        shl.s 192, r1, r1
+        add 1, r0, r2
+        shl.s 20, r2, r2
+        far_call r1, r2, @handler
+        ptr.add r1, r0, stack[0]
+        add 10000, r0, r1
+        shl.s 192, r1, r1
+        add 1, r0, r2
+        shl.s 20, r2, r2
+        add 1, r2, r2
+        far_call r1, r2, @handler
+        ptr.add stack[0], r0, r4
+        ld r4, r3
+        ret.ok r0
+    handler:
+        add 1, r0, r5
+        ret.ok r0
+        ",
+    )
+    .with_contract(callee_address(), &returning("add 42, r0, r2"))
+    .with_contract(H160::from_low_u64_be(0x100001), &returning("add 7, r0, r2"))
+    .run();
+    outcome.assert_ok();
+    assert_eq!(outcome.reg(5), U256::zero());
+    assert_eq!(outcome.reg(3), U256::from(42));
+}
+
+#[test]
+fn returned_heaps_are_freed_once_unreachable() {
+    let (mut vm, mut storage) = Fixture::new(
+        "
+        nop r0, stack+=[1]
+        add 3000, r0, stack[0]
+    again:
+        add 10000, r0, r1
+        shl.s 192, r1, r1
+        add 1, r0, r2
+        shl.s 20, r2, r2
+        far_call r1, r2, @handler
+        add stack[0], r0, r3
+        sub.s! 1, r3, stack[0]
+        jump.ne @again
+        ret.ok r0
+    handler:
+        ret.panic
+        ",
+    )
+    .with_contract(callee_address(), &returning("add 42, r0, r2"))
+    .build();
+    let output = vm
+        .run(
+            &mut NoTracer::default(),
+            EncodingMode::Production,
+            &mut storage,
+        )
+        .unwrap();
+    assert_eq!(output, ExecutionOutput::Ok(vec![]));
+    // the reserved heaps, the initial frame's and the one the last call returned data in
+    assert!(vm.execution.heaps.allocated() <= 8);
+}
+
+#[test]
+fn far_call_past_the_depth_limit_frees_the_heaps_it_allocated() {
+    let config = VmConfig {
+        max_far_call_depth: 2,
+        ..VmConfig::default()
+    };
+    let run = |callee: &str| {
+        Fixture::new(&FAR_CALLER.replace("{call}", "far_call"))
+            .with_contract(callee_address(), callee)
+            .with_config(config)
+            .run()
+    };
+    // the callee calls itself, which is one frame too many
+    let too_deep = run("
+        add 10000, r0, r1
+        shl.s 192, r1, r1
+        add 1, r0, r2
+        shl.s 20, r2, r2
+        far_call r1, r2, @handler
+        ret.ok r0
+    handler:
+        ret.ok r0
+        ");
+    too_deep.assert_ok();
+    assert_eq!(too_deep.reg(5), U256::zero());
+
+    let returning_at_once = run("ret.ok r0");
+    assert_eq!(
+        too_deep.vm.execution.heaps.allocated(),
+        returning_at_once.vm.execution.heaps.allocated()
+    );
+}
+
+/// Counts how many times code is loaded from the storage
+#[derive(Debug)]
+struct CountingDecommits {
+    storage: InitialStorageMemory,
+    decommits: usize,
+}
+
+impl Storage for CountingDecommits {
+    fn decommit(&mut self, hash: U256) -> Result<Option<Vec<U256>>, StorageError> {
+        self.decommits += 1;
+        self.storage.decommit(hash)
+    }
+
+    fn storage_read(&mut self, key: &StorageKey) -> Result<Option<U256>, StorageError> {
+        self.storage.storage_read(key)
+    }
+
+    fn cost_of_writing_storage(
+        &mut self,
+        key: &StorageKey,
+        value: U256,
+    ) -> Result<u32, StorageError> {
+        self.storage.cost_of_writing_storage(key, value)
+    }
+
+    fn is_free_storage_slot(&self, key: &StorageKey) -> bool {
+        self.storage.is_free_storage_slot(key)
+    }
+}
+
+#[test]
+fn decoded_code_lives_as_long_as_the_decommit() {
+    let (mut vm, storage) = Fixture::new(&FAR_CALLER.replace("{call}", "far_call"))
+        .with_contract(callee_address(), &returning("add 42, r0, r2"))
+        .build();
+    let mut storage = CountingDecommits {
+        storage,
+        decommits: 0,
+    };
+    let initial = vm.execution.clone();
+    let snapshot = vm.snapshot();
+    assert_eq!(
+        vm.run_program_with_custom_bytecode(&mut storage),
+        ExecutionOutput::Ok(vec![])
+    );
+    assert_eq!(storage.decommits, 1);
+
+    // rolling back the decommit drops the decoded code with it
+    vm.rollback(snapshot);
+    assert_eq!(
+        vm.run_program_with_custom_bytecode(&mut storage),
+        ExecutionOutput::Ok(vec![])
+    );
+    assert_eq!(storage.decommits, 2);
+
+    // and so does a reset
+    vm.state.reset();
+    vm.execution = initial;
+    assert_eq!(
+        vm.run_program_with_custom_bytecode(&mut storage),
+        ExecutionOutput::Ok(vec![])
+    );
+    assert_eq!(storage.decommits, 3);
+}
+
+#[test]
+fn far_call_sets_up_the_callee_context() {
+    let this = far_call("far_call", &returning("context.this r2"));
+    assert_eq!(this.reg(3), address_into_u256(callee_address()));
+    let caller = far_call("far_call", &returning("context.caller r2"));
+    assert_eq!(caller.reg(3), address_into_u256(user_address()));
+}
+
+#[test]
+fn delegate_call_keeps_the_caller_context() {
+    let this = far_call("far_call.delegate", &returning("context.this r2"));
+    assert_eq!(this.reg(3), address_into_u256(user_address()));
+    let caller = far_call("far_call.delegate", &returning("context.caller r2"));
+    assert_eq!(caller.reg(3), address_into_u256(caller_address()));
+    let code = far_call("far_call.delegate", &returning("context.code_source r2"));
+    assert_eq!(code.reg(3), address_into_u256(callee_address()));
+}
+
+#[test]
+fn mimic_call_takes_the_caller_from_r15() {
+    let outcome = Fixture::new(&FAR_CALLER.replace("{call}", "far_call.mimic"))
+        .at(kernel_address())
+        .with_contract(callee_address(), &returning("context.caller r2"))
+        .run();
+    outcome.assert_ok();
+    assert_eq!(outcome.reg(3), U256::from(3) << 16);
+}
+
+#[test]
+fn far_call_limits_gas_to_the_callee() {
+    let outcome = far_call("far_call", &returning("context.ergs_left r2"));
+    assert_eq!(
+        outcome.reg(3),
+        U256::from(10000 - cost_of("context.ergs_left r2"))
+    );
+}
+
+#[test]
+fn configs_that_would_overflow_gas_are_rejected() {
+    let default = VmConfig::default();
+    let invalid = [
+        (
+            VmConfig {
+                storage_warm_read_cost: default.storage_cold_read_cost + 1,
+                ..default
+            },
+            ConfigError::WarmReadAboveCold,
+        ),
+        (
+            VmConfig {
+                storage_warm_write_cost: default.storage_cold_write_cost + 1,
+                ..default
+            },
+            ConfigError::WarmWriteAboveCold,
+        ),
+        (
+            VmConfig {
+                storage_cold_read_cost: default.storage_cold_write_cost + 1,
+                ..default
+            },
+            ConfigError::ColdReadAboveColdWrite,
+        ),
+        (
+            VmConfig {
+                far_call_gas_divisor: 0,
+                ..default
+            },
+            ConfigError::ZeroFarCallGasDivisor,
+        ),
+        (
+            VmConfig {
+                far_call_gas_dividend: 65,
+                ..default
+            },
+            ConfigError::FarCallGasDividendAboveDivisor,
+        ),
+    ];
+    for (config, error) in invalid {
+        let (vm, _) = Fixture::new("ret.ok r0").build();
+        assert_eq!(EraVM::with_config(vm.execution, config).err(), Some(error));
+    }
+    assert_eq!(default.validate(), Ok(()));
+}
+
+#[test]
+fn revert_jumps_to_the_exception_handler_and_rolls_back() {
+    let outcome = far_call(
+        "far_call",
+        "
+        add 1, r0, r1
+        sstore r1, r1
+        add 42, r0, r2
+        st.1 0, r2
+        add 32, r0, r1
+        shl.s 96, r1, r1
+        ret.revert r1
+        ",
+    );
+    outcome.assert_ok();
+    assert_eq!(outcome.reg(5), U256::one());
+    // revert data is still returned
+    assert_eq!(outcome.reg(3), U256::from(42));
+    let key = StorageKey::new(callee_address(), U256::one());
+    assert!(!outcome.vm.state.storage_changes().contains_key(&key));
+}
+
+#[test]
+fn callee_panic_jumps_to_the_exception_handler() {
+    let outcome = far_call("far_call", "ret.panic");
+    outcome.assert_ok();
+    assert_eq!(outcome.reg(5), U256::one());
+    assert_eq!(outcome.reg(3), U256::zero());
+    assert!(outcome.flags().0);
+}
+
+#[test]
+fn static_call_cannot_write_storage() {
+    let callee = "
+        add 1, r0, r1
+        sstore r1, r1
+        ret.ok r0
+    ";
+    let outcome = far_call("far_call.static", callee);
+    assert_eq!(outcome.reg(5), U256::one());
+
+    let outcome = far_call("far_call", callee);
+    assert_eq!(outcome.reg(5), U256::zero());
+    let key = StorageKey::new(callee_address(), U256::one());
+    assert_eq!(
+        outcome.vm.state.storage_changes().get(&key),
+        Some(&U256::one())
+    );
+}
+
+#[test]
+fn far_call_to_an_address_without_code_fails() {
+    let outcome = Fixture::new(&FAR_CALLER.replace("{call}", "far_call")).run();
+    outcome.assert_ok();
+    assert_eq!(outcome.reg(5), U256::one());
+}
+
+#[test]
+fn panic_in_the_initial_frame() {
+    let outcome = Fixture::new("ret.panic").run();
+    assert_eq!(outcome.output, ExecutionOutput::Panic);
+}
+
+#[test]
+fn revert_in_the_initial_frame() {
+    let outcome = Fixture::new(
+        "
+        add 7, r0, r2
+        st.1 0, r2
+        add 32, r0, r1
+        shl.s 96, r1, r1
+        ret.revert r1
+        ",
+    )
+    .run();
+    let mut expected = vec![0; 32];
+    expected[31] = 7;
+    assert_eq!(outcome.output, ExecutionOutput::Revert(expected));
+}
@@ -0,0 +1,216 @@
+use std::collections::HashMap;
+
+use era_vm::{
+    assembler::assemble,
+    config::{ProtocolVersion, VmConfig},
+    debugger::{DebugStop, Debugger},
+    execution::Execution,
+    opcode::Variant,
+    precompiles::{registry::PrecompileRegistry, Precompile},
+    store::{InitialStorageMemory, StorageKey},
+    utils::address_into_u256,
+    value::TaggedValue,
+    vm::{EncodingMode, ExecutionOutput},
+    EraVM, Opcode,
+};
+use u256::{H160, U256};
+
+pub const INITIAL_GAS: u32 = 1 << 24;
+const MAX_STEPS: usize = 10_000;
+const DEPLOYER_SYSTEM_CONTRACT: u64 = 0x8006;
+
+pub fn user_address() -> H160 {
+    H160::from_low_u64_be(0x10000)
+}
+
+pub fn caller_address() -> H160 {
+    H160::from_low_u64_be(0x20000)
+}
+
+pub fn kernel_address() -> H160 {
+    H160::from_low_u64_be(0x8001)
+}
+
+/// Base cost of a single instruction, memory growth and other dynamic costs excluded.
+pub fn cost_of(instruction: &str) -> u32 {
+    let code = assemble(
+        instruction,
+        EncodingMode::Production,
+        ProtocolVersion::default(),
+    )
+    .unwrap();
+    Opcode::try_from_raw_opcode((code[0] >> 192).low_u64(), ProtocolVersion::default())
+        .unwrap()
+        .gas_cost
+}
+
+/// A program to run in the initial frame, along with the contracts it can call.
+pub struct Fixture {
+    source: String,
+    address: H160,
+    calldata: Vec<u8>,
+    /// Sources of the contracts to deploy, assembled for the configured protocol version
+    contracts: Vec<(H160, String)>,
+    storage: HashMap<StorageKey, U256>,
+    hook_address: Option<u32>,
+    config: VmConfig,
+    precompiles: PrecompileRegistry,
+}
+
+pub struct Outcome {
+    pub output: ExecutionOutput,
+    /// The vm right before the initial frame returned, or when the execution stopped otherwise
+    pub before_ret: Execution,
+    pub vm: EraVM,
+    /// Base cost of the instructions executed before the initial frame returned
+    pub opcode_gas: u32,
+}
+
+impl Fixture {
+    pub fn new(source: &str) -> Self {
+        Self {
+            source: source.to_owned(),
+            address: user_address(),
+            calldata: vec![],
+            contracts: vec![],
+            storage: HashMap::new(),
+            hook_address: None,
+            config: VmConfig::default(),
+            precompiles: PrecompileRegistry::default(),
+        }
+    }
+
+    pub fn at(mut self, address: H160) -> Self {
+        self.address = address;
+        self
+    }
+
+    pub fn with_calldata(mut self, calldata: Vec<u8>) -> Self {
+        self.calldata = calldata;
+        self
+    }
+
+    pub fn with_storage(mut self, key: StorageKey, value: U256) -> Self {
+        self.storage.insert(key, value);
+        self
+    }
+
+    pub fn with_hooks(mut self, hook_address: u32) -> Self {
+        self.hook_address = Some(hook_address);
+        self
+    }
+
+    pub fn with_config(mut self, config: VmConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn with_precompile(mut self, address: u16, precompile: impl Precompile + 'static) -> Self {
+        self.precompiles.register(address, precompile);
+        self
+    }
+
+    /// Deploys a constructed contract at `address`.
+    pub fn with_contract(mut self, address: H160, source: &str) -> Self {
+        self.contracts.push((address, source.to_owned()));
+        self
+    }
+
+    pub fn build(mut self) -> (EraVM, InitialStorageMemory) {
+        let version = self.config.protocol_version;
+        let mut contracts = HashMap::new();
+        for (address, source) in &self.contracts {
+            let code = assemble(source, EncodingMode::Production, version).unwrap();
+            let mut code_info = [0u8; 32];
+            // contract version, constructed
+            code_info[0] = 1;
+            code_info[2..4].copy_from_slice(&(code.len() as u16).to_be_bytes());
+            code_info[12..].copy_from_slice(address.as_bytes());
+            let hash = U256::from_big_endian(&code_info);
+
+            let deployer = H160::from_low_u64_be(DEPLOYER_SYSTEM_CONTRACT);
+            self.storage
+                .insert(StorageKey::new(deployer, address_into_u256(*address)), hash);
+            contracts.insert(hash, code);
+        }
+
+        let code = assemble(&self.source, EncodingMode::Production, version).unwrap();
+        let execution = Execution::new(
+            code,
+            self.calldata,
+            self.address,
+            caller_address(),
+            0,
+            [0; 32],
+            [0; 32],
+            self.hook_address.unwrap_or_default(),
+            self.hook_address.is_some(),
+            INITIAL_GAS,
+        );
+        let storage = InitialStorageMemory::new(contracts, self.storage);
+        let vm = EraVM::with_precompiles(execution, self.config, self.precompiles).unwrap();
+        (vm, storage)
+    }
+
+    pub fn run(self) -> Outcome {
+        let (vm, mut storage) = self.build();
+        let mut debugger = Debugger::new(vm, EncodingMode::Production);
+        let mut before_ret = None;
+        let mut opcode_gas: u32 = 0;
+
+        for _ in 0..MAX_STEPS {
+            if let (None, Some(opcode)) = (&before_ret, debugger.next_opcode()) {
+                let execution = &debugger.vm().execution;
+                let returns_from_initial_frame = matches!(opcode.variant, Variant::Ret(_))
+                    && execution.frame_depth() == 1
+                    && matches!(execution.can_execute(&opcode), Ok(true));
+                if returns_from_initial_frame {
+                    before_ret = Some(execution.clone());
+                } else {
+                    opcode_gas = opcode_gas.saturating_add(opcode.gas_cost);
+                }
+            }
+
+            let output = match debugger.step(&mut storage) {
+                Ok(DebugStop::Output(output)) => output,
+                Ok(_) => continue,
+                Err(_) => ExecutionOutput::Panic,
+            };
+            let vm = debugger.into_vm();
+            return Outcome {
+                output,
+                before_ret: before_ret.unwrap_or_else(|| vm.execution.clone()),
+                vm,
+                opcode_gas,
+            };
+        }
+        panic!("program did not finish in {MAX_STEPS} steps");
+    }
+}
+
+impl Outcome {
+    pub fn reg(&self, index: u8) -> U256 {
+        self.before_ret.get_register(index).value
+    }
+
+    pub fn tagged(&self, index: u8) -> TaggedValue {
+        self.before_ret.get_register(index)
+    }
+
+    /// `(lt_of, gt, eq)`
+    pub fn flags(&self) -> (bool, bool, bool) {
+        (
+            self.before_ret.flag_lt_of,
+            self.before_ret.flag_gt,
+            self.before_ret.flag_eq,
+        )
+    }
+
+    pub fn gas_used(&self) -> u32 {
+        INITIAL_GAS - self.before_ret.gas_left().unwrap()
+    }
+
+    pub fn assert_ok(&self) {
+        assert_eq!(self.output, ExecutionOutput::Ok(vec![]));
+    }
+}
@@ -0,0 +1,118 @@
+use era_vm::{utils::address_into_u256, vm::ExecutionOutput};
+use u256::U256;
+
+use crate::common::{caller_address, kernel_address, user_address, Fixture, INITIAL_GAS};
+
+#[test]
+fn addresses() {
+    let outcome = Fixture::new(
+        "
+        context.this r1
+        context.caller r2
+        context.code_source r3
+        ret.ok r0
+        ",
+    )
+    .run();
+    assert_eq!(outcome.reg(1), address_into_u256(user_address()));
+    assert_eq!(outcome.reg(2), address_into_u256(caller_address()));
+    assert_eq!(outcome.reg(3), address_into_u256(user_address()));
+}
+
+#[test]
+fn ergs_left_and_sp() {
+    let outcome = Fixture::new(
+        "
+        nop r0, stack+=[4]
+        context.ergs_left r1
+        context.sp r2
+        context.get_context_u128 r3
+        ret.ok r0
+        ",
+    )
+    .run();
+    let spent = outcome.opcode_gas
+        - crate::common::cost_of("context.sp r2")
+        - crate::common::cost_of("context.get_context_u128 r3");
+    assert_eq!(outcome.reg(1), U256::from(INITIAL_GAS - spent));
+    assert_eq!(outcome.reg(2), U256::from(4));
+    assert_eq!(outcome.reg(3), U256::zero());
+}
+
+#[test]
+fn kernel_only_instructions() {
+    let source = "
+        add 77, r0, r1
+        context.set_context_u128 r1
+        context.inc_tx_num
+        ret.ok r0
+    ";
+    let outcome = Fixture::new(source).run();
+    assert_eq!(outcome.output, ExecutionOutput::Panic);
+
+    let outcome = Fixture::new(source).at(kernel_address()).run();
+    outcome.assert_ok();
+    assert_eq!(outcome.before_ret.register_context_u128, 77);
+    assert_eq!(outcome.before_ret.tx_number, 1);
+}
+
+#[test]
+fn aux_mutating0_is_a_kernel_only_nop() {
+    let source = "
+        add 5, r0, r1
+        context.aux_mutating0
+        add 1, r0, r2
+        ret.ok r0
+    ";
+    let outcome = Fixture::new(source).run();
+    assert_eq!(outcome.output, ExecutionOutput::Panic);
+    assert_eq!(outcome.reg(2), U256::zero());
+
+    let outcome = Fixture::new(source).at(kernel_address()).run();
+    outcome.assert_ok();
+    assert_eq!(outcome.reg(1), U256::from(5));
+    assert_eq!(outcome.reg(2), U256::one());
+}
+
+#[test]
+fn aux_mutating0_leaves_the_state_untouched() {
+    let program = |instruction: &str| {
+        format!(
+            "
+            add 5, r0, r1
+            sub.s! 7, r1, r2
+            add 32, r0, r3
+            st.1 r3, r1
+            context.set_context_u128 r1
+            {instruction}
+            ret.ok r0
+            "
+        )
+    };
+    let with_aux = Fixture::new(&program("context.aux_mutating0"))
+        .at(kernel_address())
+        .run();
+    let with_nop = Fixture::new(&program("nop")).at(kernel_address()).run();
+    with_aux.assert_ok();
+    with_nop.assert_ok();
+
+    assert_eq!(
+        with_aux.gas_used() - crate::common::cost_of("context.aux_mutating0"),
+        with_nop.gas_used() - crate::common::cost_of("nop")
+    );
+    let mut aux_state = with_aux.before_ret.snapshot();
+    let nop_state = with_nop.before_ret.snapshot();
+    // Only the code and the gas left may differ between the two runs
+    aux_state.running_contexts[0].frame.gas_left = nop_state.running_contexts[0].frame.gas_left;
+    aux_state.running_contexts[0].code_page = nop_state.running_contexts[0].code_page.clone();
+    assert_eq!(aux_state, nop_state);
+    assert_eq!(
+        with_aux
+            .before_ret
+            .heaps
+            .get(with_aux.before_ret.current_context().unwrap().heap_id)
+            .unwrap()
+            .read(32),
+        U256::from(5)
+    );
+}
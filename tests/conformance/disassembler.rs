@@ -0,0 +1,96 @@
+use era_vm::{
+    assembler::{assemble, AssemblerError},
+    config::ProtocolVersion,
+    disassembler::disassemble,
+    vm::EncodingMode,
+};
+use u256::U256;
+
+// Disassembles the assembled program and assembles the listing again
+fn round_trip(source: &str, encoding_mode: EncodingMode) -> (Vec<U256>, Vec<U256>, usize) {
+    let code = assemble(source, encoding_mode, ProtocolVersion::default()).unwrap();
+    let disassembly = disassemble(&code, encoding_mode, ProtocolVersion::default());
+    let listing = disassembly.to_string();
+    let reassembled = assemble(&listing, encoding_mode, ProtocolVersion::default())
+        .unwrap_or_else(|error| panic!("{error} in\n{listing}"));
+    (code, reassembled, disassembly.constants_start)
+}
+
+const PROGRAM: &str = "
+        add 3, r0, r1
+    again:
+        sub.s! 1, r1, r1
+        jump.ne @again
+        near_call r0, @callee, @handler
+        add code[@CPI0], r0, r2
+        add 1, r0, r3
+        add code[r3+@CPI0], r0, r4
+        st.1.inc r3, r4, r5
+        ret.ok r0
+    callee:
+        context.ergs_left r6
+        ret.ok.to_label r0, @again
+    handler:
+        ret.panic
+        .rodata
+    CPI0:
+        .cell 0xdeadbeefdeadbeefdeadbeefdeadbeefdeadbeef
+        .cell 12345
+";
+
+#[test]
+fn disassembly_assembles_back_to_the_same_code() {
+    for encoding_mode in [EncodingMode::Production, EncodingMode::Testing] {
+        let (code, reassembled, constants_start) = round_trip(PROGRAM, encoding_mode);
+        assert_eq!(reassembled, code);
+        assert_eq!(constants_start, code.len() - 2);
+    }
+}
+
+#[test]
+fn unreferenced_constants_are_not_taken_for_instructions() {
+    // constants no `code[...]` operand reads, some of them decode as valid instructions
+    let source = "
+        add 1, r0, r1
+        ret.ok r0
+        .rodata
+    CPI0:
+        .cell 42
+        .cell 0
+        .cell 0xffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff
+        .cell 0x0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef
+    ";
+    for encoding_mode in [EncodingMode::Production, EncodingMode::Testing] {
+        let (code, reassembled, constants_start) = round_trip(source, encoding_mode);
+        assert_eq!(reassembled, code);
+        assert_eq!(constants_start, 1);
+    }
+}
+
+#[test]
+fn code_past_an_unconditional_return_is_kept_when_jumped_to() {
+    let source = "
+        jump @end
+        add 1, r0, r1
+        ret.ok r0
+        add 2, r0, r1
+    end:
+        add 3, r0, r1
+        ret.ok r0
+        .rodata
+        .cell 42
+    ";
+    let (code, reassembled, constants_start) = round_trip(source, EncodingMode::Production);
+    assert_eq!(reassembled, code);
+    assert_eq!(constants_start, 2);
+}
+
+#[test]
+fn instructions_the_protocol_version_lacks_do_not_assemble() {
+    let source = "tload r1, r2";
+    assert!(assemble(source, EncodingMode::Production, ProtocolVersion::Vm1_5).is_ok());
+    assert_eq!(
+        assemble(source, EncodingMode::Production, ProtocolVersion::Vm1_4),
+        Err(AssemblerError::UnknownInstruction(1, "tload".to_owned()))
+    );
+}
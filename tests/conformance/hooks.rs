@@ -0,0 +1,40 @@
+use u256::U256;
+
+use crate::common::Fixture;
+
+const HOOK_ADDRESS: u32 = 1024;
+
+const PROGRAM: &str = "
+        add 7, r0, r2
+        st.1 1024, r2
+        add 1, r0, r3
+        ret.ok r0
+";
+
+#[test]
+fn writing_to_the_hook_address_suspends() {
+    let (mut vm, mut storage) = Fixture::new(PROGRAM).with_hooks(HOOK_ADDRESS).build();
+    let output = vm.run_program_with_custom_bytecode(&mut storage);
+    let era_vm::vm::ExecutionOutput::SuspendedOnHook {
+        hook,
+        pc_to_resume_from,
+    } = output
+    else {
+        panic!("expected to be suspended, got {output:?}");
+    };
+    assert_eq!(hook, 7);
+    assert_eq!(pc_to_resume_from, 2);
+    assert_eq!(vm.execution.get_register(3).value, U256::zero());
+
+    vm.execution.current_frame_mut().unwrap().pc = pc_to_resume_from as u64;
+    let output = vm.run_program_with_custom_bytecode(&mut storage);
+    assert_eq!(output, era_vm::vm::ExecutionOutput::Ok(vec![]));
+    assert_eq!(vm.execution.get_register(3).value, U256::one());
+}
+
+#[test]
+fn hooks_are_ignored_when_disabled() {
+    let outcome = Fixture::new(PROGRAM).run();
+    outcome.assert_ok();
+    assert_eq!(outcome.reg(3), U256::one());
+}
@@ -0,0 +1,17 @@
+//! Opcode conformance suite. Every case is a small zkasm program assembled in-tree, so the suite
+//! runs offline without the era-compiler-tester toolchain.
+
+mod common;
+
+mod addressing;
+mod arithmetic;
+mod calls;
+mod context;
+mod disassembler;
+mod hooks;
+mod memory;
+mod pointers;
+mod precompiles;
+mod predicates;
+mod storage;
+mod tracers;
@@ -0,0 +1,251 @@
+use era_vm::{
+    config::VmConfig, heaps::Heaps, rollbacks::Rollbackable, value::FatPointer, vm::ExecutionOutput,
+};
+use u256::U256;
+use zkevm_opcode_defs::MEMORY_GROWTH_ERGS_PER_BYTE;
+
+use crate::common::{kernel_address, Fixture};
+
+#[test]
+fn heap_write_and_read() {
+    let outcome = Fixture::new(
+        "
+        add 100, r0, r1
+        st.1 64, r1
+        ld.1 64, r2
+        st.1.inc 0, r1, r3
+        ld.1.inc 0, r4, r5
+        ret.ok r0
+        ",
+    )
+    .run();
+    outcome.assert_ok();
+    assert_eq!(outcome.reg(2), U256::from(100));
+    assert_eq!(outcome.reg(3), U256::from(32));
+    assert_eq!(outcome.reg(4), U256::from(100));
+    assert_eq!(outcome.reg(5), U256::from(32));
+    // the heap grows up to the end of the highest word written
+    assert_eq!(
+        outcome.gas_used(),
+        outcome.opcode_gas + 96 * MEMORY_GROWTH_ERGS_PER_BYTE
+    );
+}
+
+#[test]
+fn memory_growth_price_is_configurable() {
+    let config = VmConfig {
+        memory_growth_ergs_per_byte: 3,
+        ..VmConfig::default()
+    };
+    let outcome = Fixture::new(
+        "
+        st.1 0, r0
+        ret.ok r0
+        ",
+    )
+    .with_config(config)
+    .run();
+    assert_eq!(outcome.gas_used(), outcome.opcode_gas + 32 * 3);
+}
+
+#[test]
+fn aux_heap_is_separate() {
+    let outcome = Fixture::new(
+        "
+        add 100, r0, r1
+        st.2 0, r1
+        ld.2 0, r2
+        ld.1 0, r3
+        ret.ok r0
+        ",
+    )
+    .run();
+    assert_eq!(outcome.reg(2), U256::from(100));
+    assert_eq!(outcome.reg(3), U256::zero());
+    assert_eq!(
+        outcome.gas_used(),
+        outcome.opcode_gas + 64 * MEMORY_GROWTH_ERGS_PER_BYTE
+    );
+}
+
+#[test]
+fn static_memory_write_and_read() {
+    let outcome = Fixture::new(
+        "
+        add 100, r0, r1
+        st.static.inc 32, r1, r2
+        ld.static.inc 32, r3, r4
+        ld.1 32, r5
+        ret.ok r0
+        ",
+    )
+    .at(kernel_address())
+    .run();
+    outcome.assert_ok();
+    assert_eq!(outcome.reg(2), U256::from(64));
+    assert_eq!(outcome.reg(3), U256::from(100));
+    assert_eq!(outcome.reg(4), U256::from(64));
+    assert_eq!(outcome.reg(5), U256::zero());
+    assert_eq!(
+        outcome.gas_used(),
+        outcome.opcode_gas + 2 * 64 * MEMORY_GROWTH_ERGS_PER_BYTE
+    );
+}
+
+#[test]
+fn static_memory_is_written_only_by_the_kernel() {
+    let outcome = Fixture::new(
+        "
+        ld.static 0, r1
+        add 1, r0, r2
+        st.static 0, r2
+        ret.ok r0
+        ",
+    )
+    .run();
+    assert_eq!(outcome.output, ExecutionOutput::Panic);
+    assert_eq!(outcome.reg(2), U256::one());
+}
+
+#[test]
+fn heap_access_through_a_pointer_panics() {
+    let outcome = Fixture::new(
+        "
+        ld.1 r1, r2
+        ret.ok r0
+        ",
+    )
+    .run();
+    assert_eq!(outcome.output, ExecutionOutput::Panic);
+}
+
+#[test]
+fn fat_pointer_read() {
+    let calldata: Vec<u8> = (0..32).collect();
+    let outcome = Fixture::new(
+        "
+        ld.inc r1, r2, r3
+        ld r3, r4
+        ret.ok r0
+        ",
+    )
+    .with_calldata(calldata.clone())
+    .run();
+    assert_eq!(outcome.reg(2), U256::from_big_endian(&calldata));
+    assert!(outcome.tagged(3).is_pointer);
+    assert_eq!(FatPointer::decode(outcome.reg(3)).offset, 32);
+    // reading past the length of the pointer yields zero
+    assert_eq!(outcome.reg(4), U256::zero());
+}
+
+#[test]
+fn fat_pointer_read_of_an_integer_panics() {
+    let outcome = Fixture::new(
+        "
+        ld r0, r2
+        ret.ok r0
+        ",
+    )
+    .run();
+    assert_eq!(outcome.output, ExecutionOutput::Panic);
+}
+
+#[test]
+fn rollback_restores_heaps() {
+    let (mut vm, mut storage) = Fixture::new(
+        "
+        add 100, r0, r1
+        st.1 64, r1
+        st.2 0, r1
+        ret.ok r0
+        ",
+    )
+    .build();
+    let heaps = vm.execution.heaps.clone();
+    let snapshot = vm.snapshot();
+
+    let output = vm.run_program_with_custom_bytecode(&mut storage);
+    assert_eq!(output, ExecutionOutput::Ok(vec![]));
+    assert_ne!(vm.execution.heaps, heaps);
+
+    vm.rollback(snapshot);
+    assert_eq!(vm.execution.heaps, heaps);
+}
+
+#[test]
+fn unaligned_reads_stop_at_the_last_written_byte() {
+    let outcome = Fixture::new(
+        "
+        add 1, r0, r1
+        st.1 0, r1
+        ld.1 1000, r2
+        ret.ok r0
+        ",
+    )
+    .run();
+    let heap_id = outcome.before_ret.current_context().unwrap().heap_id;
+    let heap = outcome.before_ret.heaps.get(heap_id).unwrap();
+    assert_eq!(heap.len(), 1032);
+    let pointer = FatPointer {
+        offset: 0,
+        page: heap_id,
+        start: 0,
+        len: 2000,
+    };
+    let bytes = heap.read_unaligned_from_pointer(&pointer).unwrap();
+    assert_eq!(bytes.len(), 32);
+    assert_eq!(bytes[31], 1);
+}
+
+#[test]
+fn heap_journal_is_released_without_snapshots() {
+    let source = "
+        add 1024, r0, r1
+    again:
+        st.1 r1, r1
+        sub.s! 32, r1, r1
+        jump.ne @again
+        ret.ok r0
+    ";
+    let (mut vm, mut storage) = Fixture::new(source).build();
+    let heaps = vm.execution.heaps.clone();
+    let snapshot = vm.snapshot();
+    let output = vm.run_program_with_custom_bytecode(&mut storage);
+    assert_eq!(output, ExecutionOutput::Ok(vec![]));
+    // the stores are kept for as long as the snapshot can roll them back
+    assert!(vm.execution.heaps.journal_len() > 0);
+    vm.rollback(snapshot);
+    assert_eq!(vm.execution.heaps, heaps);
+
+    let (mut vm, mut storage) = Fixture::new(source).build();
+    let output = vm.run_program_with_custom_bytecode(&mut storage);
+    assert_eq!(output, ExecutionOutput::Ok(vec![]));
+    assert_eq!(vm.execution.heaps.journal_len(), 0);
+}
+
+#[test]
+fn rollback_frees_the_ids_it_took_back() {
+    let mut heaps = Heaps::new(vec![]);
+    let [a, b, c] = [0, 1, 2].map(|_| heaps.allocate(0));
+    for id in [a, b, c] {
+        heaps.get_mut(id).unwrap().store(0, U256::from(id));
+    }
+    let before = heaps.clone();
+    let snapshot = heaps.snapshot();
+
+    heaps.deallocate(a);
+    heaps.deallocate(b);
+    let reused = heaps.allocate(0);
+    assert_eq!(reused, b);
+    heaps.deallocate(c);
+    heaps.deallocate(reused);
+    heaps.allocate(0);
+
+    heaps.rollback(snapshot);
+    assert_eq!(heaps, before);
+    for id in [a, b, c] {
+        assert_eq!(heaps.get(id).unwrap().read(0), U256::from(id));
+    }
+    // no id is free anymore, so the next heap is a new one
+    assert_eq!(heaps.allocate(0), c + 1);
+}
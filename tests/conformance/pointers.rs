@@ -0,0 +1,77 @@
+use era_vm::{value::FatPointer, vm::ExecutionOutput};
+use u256::U256;
+
+use crate::common::Fixture;
+
+// r1 starts as a pointer to the 64 bytes of calldata
+fn run(source: &str) -> crate::common::Outcome {
+    Fixture::new(source).with_calldata(vec![1; 64]).run()
+}
+
+#[test]
+fn ptr_add_and_sub_move_the_offset() {
+    let outcome = run("
+        ptr.add.s 10, r1, r2
+        ptr.sub.s 4, r2, r3
+        ret.ok r0
+        ");
+    outcome.assert_ok();
+    let added = FatPointer::decode(outcome.reg(2));
+    assert!(outcome.tagged(2).is_pointer);
+    assert_eq!((added.offset, added.len), (10, 64));
+    let subbed = FatPointer::decode(outcome.reg(3));
+    assert!(outcome.tagged(3).is_pointer);
+    assert_eq!((subbed.offset, subbed.len), (6, 64));
+}
+
+#[test]
+fn ptr_shrink_reduces_the_length() {
+    let outcome = run("
+        ptr.shrink.s 16, r1, r2
+        ret.ok r0
+        ");
+    let pointer = FatPointer::decode(outcome.reg(2));
+    assert_eq!((pointer.offset, pointer.start, pointer.len), (0, 0, 48));
+}
+
+#[test]
+fn ptr_pack_sets_the_high_bits() {
+    let outcome = run("
+        add 7, r0, r2
+        shl.s 128, r2, r2
+        ptr.pack r1, r2, r3
+        ret.ok r0
+        ");
+    assert!(outcome.tagged(3).is_pointer);
+    assert_eq!(outcome.reg(3) >> 128, U256::from(7));
+    assert_eq!(outcome.reg(3).low_u128(), outcome.reg(1).low_u128());
+}
+
+#[test]
+fn ptr_pack_with_low_bits_set_panics() {
+    let outcome = run("
+        add 7, r0, r2
+        ptr.pack r1, r2, r3
+        ret.ok r0
+        ");
+    assert_eq!(outcome.output, ExecutionOutput::Panic);
+}
+
+#[test]
+fn ptr_add_on_an_integer_panics() {
+    let outcome = run("
+        add 7, r0, r2
+        ptr.add.s 1, r2, r3
+        ret.ok r0
+        ");
+    assert_eq!(outcome.output, ExecutionOutput::Panic);
+}
+
+#[test]
+fn ptr_sub_below_zero_panics() {
+    let outcome = run("
+        ptr.sub.s 1, r1, r2
+        ret.ok r0
+        ");
+    assert_eq!(outcome.output, ExecutionOutput::Panic);
+}
@@ -0,0 +1,480 @@
+use era_vm::{
+    config::VmConfig,
+    eravm_error::{EraVmError, PrecompileError},
+    heaps::Heaps,
+    precompiles::{
+        bn254::{
+            ECADD_PRECOMPILE_ADDRESS, ECMUL_PRECOMPILE_ADDRESS, ECPAIRING_MAX_PAIRS,
+            ECPAIRING_PRECOMPILE_ADDRESS,
+        },
+        modexp::{MODEXP_MAX_INPUT_LENGTH, MODEXP_PRECOMPILE_ADDRESS},
+        precompile_abi_in_log, Precompile, PrecompileOutput,
+    },
+    state::VMState,
+    tracers::tracer::Tracer,
+    vm::{EncodingMode, ExecutionOutput},
+    Execution,
+};
+use u256::{H160, U256};
+use zkevm_opcode_defs::system_params::{
+    ECRECOVER_INNER_FUNCTION_PRECOMPILE_ADDRESS, KECCAK256_ROUND_FUNCTION_PRECOMPILE_ADDRESS,
+    SHA256_ROUND_FUNCTION_PRECOMPILE_ADDRESS,
+};
+
+use crate::common::{Fixture, Outcome};
+
+const CUSTOM_PRECOMPILE_ADDRESS: u16 = 0x0fff;
+
+/// Writes 42 to the output and takes 3 cycles
+#[derive(Debug)]
+struct Answer;
+
+impl Precompile for Answer {
+    fn execute_precompile(
+        &self,
+        abi_key: U256,
+        heaps: &mut Heaps,
+        _config: &VmConfig,
+    ) -> Result<PrecompileOutput, EraVmError> {
+        let params = precompile_abi_in_log(abi_key);
+        heaps
+            .try_get_mut(params.memory_page_to_write)?
+            .store(params.output_memory_offset * 32, U256::from(42));
+        Ok(PrecompileOutput::success(3))
+    }
+}
+
+/// Reads its input word and takes as many cycles as the ergs the heap growth costs
+#[derive(Debug)]
+struct GrowthCost;
+
+impl Precompile for GrowthCost {
+    fn execute_precompile(
+        &self,
+        abi_key: U256,
+        heaps: &mut Heaps,
+        config: &VmConfig,
+    ) -> Result<PrecompileOutput, EraVmError> {
+        let params = precompile_abi_in_log(abi_key);
+        let (_, cost) = heaps
+            .try_get_mut(params.memory_page_to_read)?
+            .expanded_read(
+                params.input_memory_offset * 32,
+                config.memory_growth_ergs_per_byte,
+            );
+        Ok(PrecompileOutput::success(cost as usize))
+    }
+}
+
+#[test]
+fn precompile_reads_grow_memory_at_the_configured_price() {
+    let config = VmConfig {
+        memory_growth_ergs_per_byte: 3,
+        ..VmConfig::default()
+    };
+    let outcome = Fixture::new(
+        "
+        precompile r0, r0, r1
+        ret.ok r0
+    ",
+    )
+    .at(H160::from_low_u64_be(CUSTOM_PRECOMPILE_ADDRESS.into()))
+    .with_config(config)
+    .with_precompile(CUSTOM_PRECOMPILE_ADDRESS, GrowthCost)
+    .run();
+    outcome.assert_ok();
+    assert_eq!(
+        outcome.vm.statistics.precompile_cycles[&CUSTOM_PRECOMPILE_ADDRESS],
+        32 * 3
+    );
+}
+
+#[test]
+fn custom_precompiles_run_and_report_their_cycles() {
+    let source = "
+        precompile r0, r0, r1
+        ld.1 0, r2
+        ret.ok r0
+    ";
+    let address = H160::from_low_u64_be(CUSTOM_PRECOMPILE_ADDRESS.into());
+
+    let outcome = Fixture::new(source)
+        .at(address)
+        .with_precompile(CUSTOM_PRECOMPILE_ADDRESS, Answer)
+        .run();
+    outcome.assert_ok();
+    assert_eq!(outcome.reg(1), U256::one());
+    assert_eq!(outcome.reg(2), U256::from(42));
+    assert_eq!(
+        outcome.vm.statistics.precompile_cycles[&CUSTOM_PRECOMPILE_ADDRESS],
+        3
+    );
+
+    // without a precompile registered the call only burns gas
+    let outcome = Fixture::new(source).at(address).run();
+    outcome.assert_ok();
+    assert_eq!(outcome.reg(1), U256::one());
+    assert_eq!(outcome.reg(2), U256::zero());
+    assert!(outcome.vm.statistics.precompile_cycles.is_empty());
+}
+
+/// A program calling the precompile at its address with `input` laid out from the start of the
+/// heap, the output overwrites the input
+fn precompile_program(input: &[U256], interpreted_data: u64) -> String {
+    let mut source = String::from(".text\n");
+    for i in 0..input.len() {
+        source += &format!(
+            "add {i}, r0, r2\nadd code[r2+@INPUT], r0, r1\nst.1 {}, r1\n",
+            i * 32
+        );
+    }
+    source += "add code[@ABI], r0, r1\nprecompile r1, r0, r1\nret.ok r0\n.rodata\nABI:\n";
+    source += &format!(".cell {:#x}\nINPUT:\n", U256::from(interpreted_data) << 192);
+    for word in input {
+        source += &format!(".cell {word:#x}\n");
+    }
+    source
+}
+
+/// Runs `precompile_program` at `address`.
+/// Returns the outcome and the first `output_words` words of the heap.
+fn run_precompile(
+    address: u16,
+    input: &[U256],
+    interpreted_data: u64,
+    output_words: u32,
+) -> (Outcome, Vec<U256>) {
+    let outcome = Fixture::new(&precompile_program(input, interpreted_data))
+        .at(H160::from_low_u64_be(address.into()))
+        .run();
+    outcome.assert_ok();
+    let heap_id = outcome.before_ret.current_context().unwrap().heap_id;
+    let heap = outcome.before_ret.heaps.get(heap_id).unwrap();
+    let output = (0..output_words).map(|i| heap.read(i * 32)).collect();
+    (outcome, output)
+}
+
+fn word(hex: &str) -> U256 {
+    U256::from_str_radix(hex, 16).unwrap()
+}
+
+#[test]
+fn keccak256_hashes_its_input() {
+    let (outcome, output) = run_precompile(KECCAK256_ROUND_FUNCTION_PRECOMPILE_ADDRESS, &[], 0, 1);
+    assert_eq!(
+        output[0],
+        word("c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470")
+    );
+    assert_eq!(outcome.vm.statistics.keccak256_cycles(), 1);
+}
+
+#[test]
+fn sha256_compresses_padded_blocks() {
+    // "abc" padded to a single block
+    let block = [
+        word("6162638000000000000000000000000000000000000000000000000000000000"),
+        word("0000000000000000000000000000000000000000000000000000000000000018"),
+    ];
+    let (outcome, output) = run_precompile(SHA256_ROUND_FUNCTION_PRECOMPILE_ADDRESS, &block, 1, 1);
+    assert_eq!(
+        output[0],
+        word("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad")
+    );
+    assert_eq!(outcome.vm.statistics.sha256_cycles(), 1);
+}
+
+#[test]
+fn ecrecover_reports_invalid_input_without_failing() {
+    let input = [
+        word("456e9aea5e197a1f1af7a3e85a3212fa4049a3ba34c2289b4c860fc0b0c64ef3"),
+        U256::from(27),
+        word("9242685bf161793cc25603c231bc2f568eb630ea16aa137d2664ac8038825608"),
+        word("4f8ae3bd7535248d0bd448298cc2e2071e56992d0774dc340c368ae950852ada"),
+    ];
+    let (_, output) = run_precompile(ECRECOVER_INNER_FUNCTION_PRECOMPILE_ADDRESS, &input, 0, 2);
+    assert_eq!(output, [U256::zero(); 2]);
+
+    let (mut vm, mut storage) = Fixture::new(&precompile_program(&input, 0))
+        .at(H160::from_low_u64_be(
+            ECRECOVER_INNER_FUNCTION_PRECOMPILE_ADDRESS.into(),
+        ))
+        .build();
+    let mut tracer = PrecompileFailures::default();
+    vm.run(&mut tracer, EncodingMode::Production, &mut storage)
+        .unwrap();
+    assert_eq!(tracer.0, [PrecompileError::EcRecoverInvalidByte]);
+}
+
+/// Calls the precompile at `address` with the raw `abi`, returning why the calls failed
+fn precompile_failures(address: u16, abi: U256) -> Vec<PrecompileError> {
+    let source = format!(
+        ".text\nadd code[@ABI], r0, r1\nprecompile r1, r0, r1\nret.ok r0\n\
+        .rodata\nABI:\n.cell {abi:#x}\n"
+    );
+    let (mut vm, mut storage) = Fixture::new(&source)
+        .at(H160::from_low_u64_be(address.into()))
+        .build();
+    let mut tracer = PrecompileFailures::default();
+    let output = vm
+        .run(&mut tracer, EncodingMode::Production, &mut storage)
+        .unwrap();
+    assert_eq!(output, ExecutionOutput::Ok(vec![]));
+    tracer.0
+}
+
+/// Records why precompile calls failed
+#[derive(Default)]
+struct PrecompileFailures(Vec<PrecompileError>);
+
+impl Tracer for PrecompileFailures {
+    fn on_precompile_failure(
+        &mut self,
+        error: &PrecompileError,
+        _execution: &mut Execution,
+        _state: &mut VMState,
+    ) {
+        self.0.push(*error);
+    }
+}
+
+fn g1_generator() -> [U256; 2] {
+    [U256::one(), U256::from(2)]
+}
+
+fn g1_generator_doubled() -> [U256; 2] {
+    [
+        word("030644e72e131a029b85045b68181585d97816a916871ca8d3c208c16d87cfd3"),
+        word("15ed738c0e0a7c92e7845f96b2ae9c0a68a6a449e3538fc7ff3ebf7a5a18a2c4"),
+    ]
+}
+
+/// `[x_im, x_re, y_im, y_re]`
+fn g2_generator() -> [U256; 4] {
+    [
+        word("198e9393920d483a7260bfb731fb5d25f1aa493335a9e71297e485b7aef312c2"),
+        word("1800deef121f1e76426a00665e5c4479674322d4f75edadd46debd5cd992f6ed"),
+        word("090689d0585ff075ec9e99ad690c3395bc4b313370b38ef355acdadcd122975b"),
+        word("12c85ea5db8c6deb4aab71808dcb408fe3d1e7690c43d37b4ce6cc0166fa7daa"),
+    ]
+}
+
+#[test]
+fn ecadd_adds_points() {
+    let [x, y] = g1_generator();
+    let (outcome, output) = run_precompile(ECADD_PRECOMPILE_ADDRESS, &[x, y, x, y], 0, 3);
+    assert_eq!(output[0], U256::one());
+    assert_eq!(output[1..], g1_generator_doubled());
+    assert_eq!(outcome.vm.statistics.ecadd_cycles(), 1);
+
+    // the point at infinity is the identity
+    let (_, output) = run_precompile(ECADD_PRECOMPILE_ADDRESS, &[x, y, 0.into(), 0.into()], 0, 3);
+    assert_eq!(output, [U256::one(), x, y]);
+}
+
+#[test]
+fn ecmul_multiplies_points() {
+    let [x, y] = g1_generator();
+    let (outcome, output) = run_precompile(ECMUL_PRECOMPILE_ADDRESS, &[x, y, 2.into()], 0, 3);
+    assert_eq!(output[0], U256::one());
+    assert_eq!(output[1..], g1_generator_doubled());
+    assert_eq!(outcome.vm.statistics.ecmul_cycles(), 1);
+
+    let (_, output) = run_precompile(ECMUL_PRECOMPILE_ADDRESS, &[x, y, 0.into()], 0, 3);
+    assert_eq!(output, [U256::one(), U256::zero(), U256::zero()]);
+}
+
+#[test]
+fn bn254_precompiles_reject_invalid_points() {
+    // (1, 3) is not on the curve
+    let input = [U256::one(), U256::from(3), U256::one(), U256::from(2)];
+    let (_, output) = run_precompile(ECADD_PRECOMPILE_ADDRESS, &input, 0, 3);
+    assert_eq!(output, [U256::zero(); 3]);
+
+    // coordinates must be below the field modulus
+    let modulus = word("30644e72e131a029b85045b68181585d97816a916871ca8d3c208c16d87cfd47");
+    let (_, output) = run_precompile(
+        ECMUL_PRECOMPILE_ADDRESS,
+        &[modulus, 0.into(), 1.into()],
+        0,
+        3,
+    );
+    assert_eq!(output, [U256::zero(); 3]);
+}
+
+#[test]
+fn ecpairing_checks_pairings() {
+    let [x, y] = g1_generator();
+    let minus_y = word("30644e72e131a029b85045b68181585d97816a916871ca8d3c208c16d87cfd45");
+    let g2 = g2_generator();
+
+    // e(G1, G2) * e(-G1, G2) = 1
+    let input: Vec<U256> = [[x, y], [x, minus_y]]
+        .into_iter()
+        .flat_map(|g1| g1.into_iter().chain(g2))
+        .collect();
+    let (outcome, output) = run_precompile(ECPAIRING_PRECOMPILE_ADDRESS, &input, 2, 2);
+    assert_eq!(output, [U256::one(), U256::one()]);
+    assert_eq!(outcome.vm.statistics.ecpairing_cycles(), 2);
+
+    let (_, output) = run_precompile(ECPAIRING_PRECOMPILE_ADDRESS, &input[..6], 1, 2);
+    assert_eq!(output, [U256::one(), U256::zero()]);
+
+    // an empty product is one
+    let (_, output) = run_precompile(ECPAIRING_PRECOMPILE_ADDRESS, &[], 0, 2);
+    assert_eq!(output, [U256::one(), U256::one()]);
+
+    // swapping the G2 coordinates moves the point off the curve
+    let mut invalid = input[..6].to_vec();
+    invalid.swap(2, 3);
+    let (_, output) = run_precompile(ECPAIRING_PRECOMPILE_ADDRESS, &invalid, 1, 2);
+    assert_eq!(output, [U256::zero(), U256::zero()]);
+}
+
+#[test]
+fn bn254_precompiles_report_input_past_the_end_of_the_heap() {
+    // the input offset is in words, so the pair starts past the end of the heap
+    let abi = U256::from(u32::MAX - 3) | (U256::one() << 192);
+    let failures = precompile_failures(ECPAIRING_PRECOMPILE_ADDRESS, abi);
+    assert_eq!(failures, [PrecompileError::MemoryOutOfBounds]);
+
+    let failures = precompile_failures(ECADD_PRECOMPILE_ADDRESS, U256::from(u32::MAX));
+    assert_eq!(failures, [PrecompileError::MemoryOutOfBounds]);
+}
+
+#[test]
+fn ecpairing_rejects_too_many_pairs() {
+    // the pair count is checked before any input is read
+    let (outcome, output) = run_precompile(
+        ECPAIRING_PRECOMPILE_ADDRESS,
+        &[],
+        ECPAIRING_MAX_PAIRS + 1,
+        2,
+    );
+    assert_eq!(output, [U256::zero(), U256::zero()]);
+    assert_eq!(outcome.vm.statistics.ecpairing_cycles(), 1);
+}
+
+/// Runs modexp on the hex encoded operands, returning the result bytes and the cycles it took
+fn modexp(base: &str, exponent: &str, modulus: &str) -> (Option<Vec<u8>>, usize) {
+    let operands = [base, exponent, modulus].map(|operand| hex::decode(operand).unwrap());
+    let mut bytes = vec![];
+    for operand in &operands {
+        let mut length = [0u8; 32];
+        U256::from(operand.len()).to_big_endian(&mut length);
+        bytes.extend_from_slice(&length);
+    }
+    for operand in &operands {
+        bytes.extend_from_slice(operand);
+    }
+    let input: Vec<U256> = bytes
+        .chunks(32)
+        .map(|chunk| {
+            let mut word = [0u8; 32];
+            word[..chunk.len()].copy_from_slice(chunk);
+            U256::from_big_endian(&word)
+        })
+        .collect();
+
+    let mod_len = operands[2].len();
+    let output_words = 1 + mod_len.div_ceil(32) as u32;
+    let (outcome, output) = run_precompile(MODEXP_PRECOMPILE_ADDRESS, &input, 0, output_words);
+    let cycles = outcome.vm.statistics.modexp_cycles();
+    if output[0].is_zero() {
+        return (None, cycles);
+    }
+    let mut result = vec![];
+    for word in &output[1..] {
+        let mut bytes = [0u8; 32];
+        word.to_big_endian(&mut bytes);
+        result.extend_from_slice(&bytes);
+    }
+    result.truncate(mod_len);
+    (Some(result), cycles)
+}
+
+// Vectors from EIP-198 and the go-ethereum modexp suite, cycles follow the EIP-2565 gas
+#[test]
+fn modexp_matches_ethereum_vectors() {
+    let secp256k1_prime = "fffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc2f";
+    let secp256k1_prime_minus_one =
+        "fffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc2e";
+    let nagydani_1_base = "e09ad9675465c53a109fac66a445c91b292d2bb2c5268addb30cd82f80fcb0033ff97c\
+        80a5fc6f39193ae969c6ede6710a6b7ac27078a06d90ef1c72e5c85fb5";
+    let nagydani_1_modulus =
+        "fc9e1f6beb81516545975218075ec2af118cd8798df6e08a147c60fd6095ac2bb02c2\
+        908cf4dd7c81f11c289e4bce98f3553768f392a80ce22bf5c4f4a248c6b";
+
+    let cases = [
+        // eip_example1, Fermat's little theorem
+        (
+            "03",
+            secp256k1_prime_minus_one,
+            secp256k1_prime,
+            "0000000000000000000000000000000000000000000000000000000000000001",
+            1360,
+        ),
+        // eip_example2, an empty base is zero
+        (
+            "",
+            secp256k1_prime_minus_one,
+            secp256k1_prime,
+            "0000000000000000000000000000000000000000000000000000000000000000",
+            1360,
+        ),
+        (
+            nagydani_1_base,
+            "02",
+            nagydani_1_modulus,
+            "60008f1614cc01dcfb6bfb09c625cf90b47d4468db81b5f8b7a39d42f332eab9b2da8f2d95311648a8f2\
+            43f4bb13cfb3d8f7f2a3c014122ebb3ed41b02783adc",
+            21,
+        ),
+        (
+            nagydani_1_base,
+            "010001",
+            nagydani_1_modulus,
+            "c36d804180c35d4426b57b50c5bfcca5c01856d104564cd513b461d3c8b8409128a5573e416d0ebe38f5\
+            f736766d9dc27143e4da981dfa4d67f7dc474cbee6d2",
+            341,
+        ),
+        // operands aren't word aligned
+        ("02", "0a", "03e8", "0018", 1),
+        // a zero modulus results in zero
+        ("03", "05", "0000", "0000", 1),
+        // so does an empty one
+        ("03", "05", "", "", 1),
+    ];
+    for (base, exponent, modulus, expected, expected_cycles) in cases {
+        let (result, cycles) = modexp(base, exponent, modulus);
+        assert_eq!(result, Some(hex::decode(expected).unwrap()));
+        assert_eq!(cycles, expected_cycles);
+    }
+}
+
+#[test]
+fn modexp_rejects_operands_past_the_length_limit() {
+    let base = "01".repeat(MODEXP_MAX_INPUT_LENGTH + 1);
+    let (result, _) = modexp(&base, "01", "05");
+    assert_eq!(result, None);
+
+    // the words the result would take are zeroed instead of keeping the input laid out there
+    let input = [
+        U256::from(MODEXP_MAX_INPUT_LENGTH + 1),
+        U256::one(),
+        U256::from(33),
+        U256::MAX,
+    ];
+    let (_, output) = run_precompile(MODEXP_PRECOMPILE_ADDRESS, &input, 0, 4);
+    assert_eq!(
+        output,
+        [U256::zero(), U256::zero(), U256::zero(), U256::MAX]
+    );
+}
+
+#[test]
+fn modexp_reports_input_past_the_end_of_the_heap() {
+    // the input offset is in words, so the first length word doesn't fit in the heap
+    let abi = U256::from(u32::MAX);
+    let failures = precompile_failures(MODEXP_PRECOMPILE_ADDRESS, abi);
+    assert_eq!(failures, [PrecompileError::MemoryOutOfBounds]);
+}
@@ -0,0 +1,60 @@
+use u256::U256;
+
+use crate::common::Fixture;
+
+const PREDICATES: [&str; 7] = ["gt", "lt", "eq", "ge", "le", "ne", "gtlt"];
+
+// Which of the predicates hold after `set_flags`, all of them are charged for either way
+fn taken(set_flags: &str) -> Vec<&'static str> {
+    let branches: String = PREDICATES
+        .iter()
+        .enumerate()
+        .map(|(i, predicate)| format!("add.{predicate} 1, r0, r{}\n", i + 2))
+        .collect();
+    let outcome = Fixture::new(&format!(
+        "
+        add 5, r0, r1
+        {set_flags}
+        {branches}
+        ret.ok r0
+        "
+    ))
+    .run();
+    assert_eq!(outcome.gas_used(), outcome.opcode_gas);
+    PREDICATES
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| outcome.reg(*i as u8 + 2) == U256::one())
+        .map(|(_, predicate)| *predicate)
+        .collect()
+}
+
+#[test]
+fn predicates_when_greater() {
+    assert_eq!(taken("sub.s! 3, r1, r0"), ["gt", "ge", "ne", "gtlt"]);
+}
+
+#[test]
+fn predicates_when_less() {
+    assert_eq!(taken("sub.s! 7, r1, r0"), ["lt", "le", "ne", "gtlt"]);
+}
+
+#[test]
+fn predicates_when_equal() {
+    assert_eq!(taken("sub.s! 5, r1, r0"), ["eq", "ge", "le"]);
+}
+
+#[test]
+fn skipped_instruction_has_no_effect_on_flags() {
+    let outcome = Fixture::new(
+        "
+        add 5, r0, r1
+        sub.s! 5, r1, r0
+        sub.lt! 1, r0, r2
+        ret.ok r0
+        ",
+    )
+    .run();
+    assert_eq!(outcome.reg(2), U256::zero());
+    assert_eq!(outcome.flags(), (false, false, true));
+}
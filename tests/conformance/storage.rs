@@ -0,0 +1,390 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use era_vm::{
+    eravm_error::EraVmError,
+    rollbacks::{Rollbackable, RollbackableHashMap},
+    store::{
+        InitialStorageMemory, RocksDbStorage, Storage, StorageError, StorageKey, StoragePricing,
+    },
+    tracers::no_tracer::NoTracer,
+    vm::{EncodingMode, ExecutionOutput},
+    EraVM,
+};
+use u256::{H160, U256};
+
+use crate::common::{kernel_address, user_address, Fixture};
+
+#[test]
+fn storage_write_then_read() {
+    let outcome = Fixture::new(
+        "
+        add 5, r0, r1
+        add 77, r0, r2
+        sstore r1, r2
+        sload r1, r3
+        ret.ok r0
+        ",
+    )
+    .run();
+    outcome.assert_ok();
+    assert_eq!(outcome.reg(3), U256::from(77));
+    let key = StorageKey::new(user_address(), U256::from(5));
+    assert_eq!(
+        outcome.vm.state.storage_changes().get(&key),
+        Some(&U256::from(77))
+    );
+}
+
+#[test]
+fn storage_read_from_initial_storage() {
+    let outcome = Fixture::new(
+        "
+        add 5, r0, r1
+        sload r1, r2
+        add 6, r0, r1
+        sload r1, r3
+        ret.ok r0
+        ",
+    )
+    .with_storage(StorageKey::new(user_address(), U256::from(5)), 123.into())
+    .run();
+    assert_eq!(outcome.reg(2), U256::from(123));
+    assert_eq!(outcome.reg(3), U256::zero());
+    assert!(outcome.vm.state.storage_changes().is_empty());
+}
+
+#[test]
+fn storage_writes_are_only_priced_when_asked_to() {
+    let source = "
+        add 5, r0, r1
+        add 77, r0, r2
+        sstore r1, r2
+        ret.ok r0
+    ";
+
+    let outcome = Fixture::new(source).run();
+    outcome.assert_ok();
+    assert_eq!(outcome.vm.state.pubdata(), 0);
+
+    let (mut vm, storage) = Fixture::new(source).build();
+    let mut storage = storage.with_pricing(StoragePricing::default());
+    let output = vm.run_program_with_custom_bytecode(&mut storage);
+    assert_eq!(output, ExecutionOutput::Ok(vec![]));
+    // a derived key and the value 77 in a single byte, plus the compression metadata
+    assert_eq!(vm.state.pubdata(), 32 + 1 + 1);
+}
+
+/// A storage whose database is unreachable
+#[derive(Debug)]
+struct FailingStorage;
+
+impl Storage for FailingStorage {
+    fn decommit(&mut self, _hash: U256) -> Result<Option<Vec<U256>>, StorageError> {
+        Err(StorageError::ReadError("unreachable".to_owned()))
+    }
+
+    fn storage_read(&mut self, _key: &StorageKey) -> Result<Option<U256>, StorageError> {
+        Err(StorageError::ReadError("unreachable".to_owned()))
+    }
+
+    fn cost_of_writing_storage(
+        &mut self,
+        _key: &StorageKey,
+        _value: U256,
+    ) -> Result<u32, StorageError> {
+        Err(StorageError::ReadError("unreachable".to_owned()))
+    }
+
+    fn is_free_storage_slot(&self, _key: &StorageKey) -> bool {
+        false
+    }
+}
+
+#[test]
+fn storage_failures_stop_the_execution() {
+    for source in [
+        "add 5, r0, r1\nsload r1, r2\nret.ok r0",
+        "add 5, r0, r1\nsstore r1, r1\nret.ok r0",
+    ] {
+        let (mut vm, _) = Fixture::new(source).build();
+        let result = vm.run(
+            &mut NoTracer::default(),
+            EncodingMode::Production,
+            &mut FailingStorage,
+        );
+        assert!(matches!(
+            result,
+            Err(EraVmError::StorageError(StorageError::ReadError(message))) if message == "unreachable"
+        ));
+    }
+}
+
+#[test]
+fn panic_restores_overwritten_values() {
+    let outcome = Fixture::new(
+        "
+        add 5, r0, r1
+        add 1, r0, r2
+        sstore r1, r2
+        near_call r0, @callee, @handler
+        ret.ok r0
+    callee:
+        add 2, r0, r2
+        sstore r1, r2
+        add 6, r0, r3
+        sstore r3, r2
+        ret.panic
+    handler:
+        sload r1, r4
+        ret.ok r0
+        ",
+    )
+    .run();
+    outcome.assert_ok();
+    assert_eq!(outcome.reg(4), U256::one());
+    let changes = outcome.vm.state.storage_changes();
+    assert_eq!(
+        changes.get(&StorageKey::new(user_address(), U256::from(5))),
+        Some(&U256::one())
+    );
+    assert!(!changes.contains_key(&StorageKey::new(user_address(), U256::from(6))));
+}
+
+#[test]
+fn transient_storage() {
+    let outcome = Fixture::new(
+        "
+        add 5, r0, r1
+        add 77, r0, r2
+        tstore r1, r2
+        tload r1, r3
+        ret.ok r0
+        ",
+    )
+    .run();
+    assert_eq!(outcome.reg(3), U256::from(77));
+    let key = StorageKey::new(user_address(), U256::from(5));
+    assert_eq!(
+        outcome.vm.state.transient_storage().get(&key),
+        Some(&U256::from(77))
+    );
+    assert!(outcome.vm.state.storage_changes().is_empty());
+}
+
+#[test]
+fn events_are_recorded_for_the_event_writer() {
+    let source = "
+        add 1, r0, r1
+        add 2, r0, r2
+        event.first r1, r2
+        event r2, r1
+        ret.ok r0
+    ";
+    let event_writer = H160::from_low_u64_be(0x800d);
+    let outcome = Fixture::new(source).at(event_writer).run();
+    outcome.assert_ok();
+    let events: Vec<_> = outcome
+        .vm
+        .state
+        .events()
+        .iter()
+        .map(|event| (event.key.as_u32(), event.value.as_u32(), event.is_first))
+        .collect();
+    assert_eq!(events, [(1, 2, true), (2, 1, false)]);
+
+    // other kernel contracts may use the opcode but nothing is recorded
+    let outcome = Fixture::new(source).at(kernel_address()).run();
+    outcome.assert_ok();
+    assert!(outcome.vm.state.events().is_empty());
+
+    let outcome = Fixture::new(source).run();
+    assert_eq!(outcome.output, ExecutionOutput::Panic);
+}
+
+#[test]
+fn messages_to_l1() {
+    let outcome = Fixture::new(
+        "
+        add 1, r0, r1
+        add 2, r0, r2
+        to_l1.first r1, r2
+        ret.ok r0
+        ",
+    )
+    .at(kernel_address())
+    .run();
+    outcome.assert_ok();
+    let logs = outcome.vm.state.l2_to_l1_logs();
+    assert_eq!(logs.len(), 1);
+    assert_eq!((logs[0].key, logs[0].value), (1.into(), 2.into()));
+    assert_eq!(logs[0].address, kernel_address());
+}
+
+#[test]
+fn map_journals_only_keep_what_live_snapshots_need() {
+    let mut map = RollbackableHashMap::new();
+    for i in 0..10_000u32 {
+        map.insert(i % 10, i);
+    }
+    // nothing can roll these back
+    assert_eq!(map.journal_len(), 0);
+
+    let snapshot = map.snapshot();
+    for i in 0..5_000u32 {
+        map.insert(i, i);
+    }
+    assert_eq!(map.journal_len(), 5_000);
+    map.rollback(snapshot);
+    assert_eq!(map.inner_ref().len(), 10);
+    assert_eq!(map.get(&3), Some(&9_993));
+
+    let snapshot = map.snapshot();
+    map.clear();
+    // a clear is a single change however many entries it removes
+    assert_eq!(map.journal_len(), 1);
+    assert!(map.inner_ref().is_empty());
+    map.rollback(snapshot);
+    assert_eq!(map.inner_ref().len(), 10);
+
+    let early = map.snapshot();
+    for i in 0..3_000u32 {
+        map.insert(i % 10, i);
+    }
+    let kept = map.snapshot();
+    drop(early);
+    for i in 0..10_000u32 {
+        map.insert(i % 10, i);
+    }
+    // the changes before `kept` were discarded, the ones after it are needed to roll back to it
+    assert_eq!(map.journal_len(), 10_000);
+    map.rollback(kept);
+    assert_eq!(map.get(&3), Some(&2_993));
+    map.insert(0, 0);
+    assert_eq!(map.journal_len(), 0);
+}
+
+#[test]
+fn cloned_maps_journal_on_their_own() {
+    let mut map = RollbackableHashMap::new();
+    map.insert(1u32, 1u32);
+    let mut clone = map.clone();
+    let snapshot = clone.snapshot();
+    // the clone's snapshot doesn't keep the original's changes
+    map.insert(2, 2);
+    assert_eq!(map.journal_len(), 0);
+    clone.insert(3, 3);
+    assert_eq!(clone.journal_len(), 1);
+    clone.rollback(snapshot);
+    assert_eq!(clone.inner_ref().len(), 1);
+
+    // snapshots alive when cloning can be rolled back to in the clone
+    let snapshot = map.snapshot();
+    let mut clone = map.clone();
+    clone.insert(4, 4);
+    clone.rollback(snapshot.clone());
+    assert_eq!(clone.inner_ref().len(), 2);
+    map.insert(5, 5);
+    map.rollback(snapshot);
+    assert_eq!(map.inner_ref().len(), 2);
+}
+
+const WRITE_77: &str = "
+    add 5, r0, r1
+    add 77, r0, r2
+    sstore r1, r2
+    ret.ok r0
+";
+
+const READ_INTO_R2: &str = "
+    add 5, r0, r1
+    sload r1, r2
+    ret.ok r0
+";
+
+/// Runs `source` in the initial frame against `storage`
+fn run_against(source: &str, storage: &mut dyn Storage) -> EraVM {
+    let (mut vm, _) = Fixture::new(source).build();
+    let output = vm
+        .run(&mut NoTracer::default(), EncodingMode::Production, storage)
+        .unwrap();
+    assert_eq!(output, ExecutionOutput::Ok(vec![]));
+    vm
+}
+
+fn published_contract() -> (U256, Vec<U256>) {
+    (U256::from(0xc0de), vec![U256::from(1), U256::from(2)])
+}
+
+#[test]
+fn commit_round_trips_into_a_fresh_memory_storage() {
+    let mut storage = InitialStorageMemory::new(HashMap::new(), HashMap::new());
+    let mut vm = run_against(WRITE_77, &mut storage);
+    let (hash, code) = published_contract();
+    vm.commit(&mut storage, HashMap::from([(hash, code.clone())]))
+        .unwrap();
+
+    assert_eq!(storage.decommit(hash), Ok(Some(code)));
+    let vm = run_against(READ_INTO_R2, &mut storage);
+    assert_eq!(vm.execution.get_register(2).value, U256::from(77));
+}
+
+/// A database directory of its own for each test, removed once done
+struct TempDb(PathBuf);
+
+impl TempDb {
+    fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("era_vm_{name}_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        Self(path)
+    }
+}
+
+impl Drop for TempDb {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+#[test]
+fn commit_persists_into_rocksdb() {
+    let dir = TempDb::new("commit_persists");
+    let (hash, code) = published_contract();
+    {
+        let mut storage = RocksDbStorage::open(&dir.0).unwrap();
+        let mut vm = run_against(WRITE_77, &mut storage);
+        vm.commit(&mut storage, HashMap::from([(hash, code.clone())]))
+            .unwrap();
+    }
+
+    // the contract lands in the contracts column family and the slot in the storage one
+    let mut storage = RocksDbStorage::open(&dir.0).unwrap();
+    assert_eq!(storage.decommit(hash), Ok(Some(code)));
+    let key = StorageKey::new(user_address(), U256::from(5));
+    assert_eq!(storage.storage_read(&key), Ok(Some(U256::from(77))));
+    let vm = run_against(READ_INTO_R2, &mut storage);
+    assert_eq!(vm.execution.get_register(2).value, U256::from(77));
+}
+
+#[test]
+fn failed_rocksdb_commit_leaves_storage_untouched() {
+    let dir = TempDb::new("failed_commit");
+    let key = StorageKey::new(user_address(), U256::from(5));
+    {
+        let mut storage = RocksDbStorage::open(&dir.0).unwrap();
+        run_against(WRITE_77, &mut storage)
+            .commit(&mut storage, HashMap::new())
+            .unwrap();
+    }
+
+    let (hash, code) = published_contract();
+    {
+        let mut storage = RocksDbStorage::open_read_only(&dir.0).unwrap();
+        let mut vm = run_against("add 5, r0, r1\nsstore r1, r1\nret.ok r0", &mut storage);
+        let result = vm.commit(&mut storage, HashMap::from([(hash, code)]));
+        assert!(matches!(result, Err(StorageError::WriteError(_))));
+    }
+
+    let mut storage = RocksDbStorage::open(&dir.0).unwrap();
+    assert_eq!(storage.storage_read(&key), Ok(Some(U256::from(77))));
+    assert_eq!(storage.decommit(hash), Ok(None));
+}
@@ -0,0 +1,349 @@
+use era_vm::{
+    assembler::assemble,
+    config::ProtocolVersion,
+    opcode::Variant,
+    tracers::{
+        call_tracer::{Call, CallOutcome, CallTracer, CallType},
+        execution_trace_tracer::ExecutionTraceTracer,
+        gas_profiler_tracer::GasProfilerTracer,
+    },
+    vm::{EncodingMode, ExecutionOutput},
+};
+use serde_json::Value;
+use u256::{H160, U256};
+use zkevm_opcode_defs::{FarCallOpcode, ERGS_PER_CODE_WORD_DECOMMITTMENT};
+
+use crate::common::{cost_of, user_address, Fixture, INITIAL_GAS};
+
+fn json<T: serde::Serialize>(value: T) -> Value {
+    serde_json::to_value(value).unwrap()
+}
+
+#[test]
+fn execution_trace_writes_a_json_line_per_step() {
+    let (mut vm, mut storage) = Fixture::new(
+        "
+        add 5, r0, r1
+        sub! 1, r1, r2
+        ret.ok r0
+        ",
+    )
+    .build();
+    let mut tracer = ExecutionTraceTracer::new();
+    let output = vm
+        .run(&mut tracer, EncodingMode::Production, &mut storage)
+        .unwrap();
+    assert_eq!(output, ExecutionOutput::Ok(vec![]));
+
+    let mut lines = vec![];
+    tracer.write_json_lines(&mut lines).unwrap();
+    let steps: Vec<Value> = String::from_utf8(lines)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(steps.len(), 3);
+
+    let mut fields: Vec<_> = steps[0].as_object().unwrap().keys().cloned().collect();
+    fields.sort();
+    assert_eq!(
+        fields,
+        [
+            "code_address",
+            "contract_address",
+            "depth",
+            "flags",
+            "gas_after",
+            "gas_before",
+            "instruction",
+            "opcode",
+            "pc",
+            "registers",
+            "sp"
+        ]
+    );
+
+    // gas is taken right before and after each step, the base cost being all the first two pay
+    assert_eq!(steps[0]["gas_before"], json(INITIAL_GAS));
+    for (step, instruction) in steps[..2].iter().zip(["add 5, r0, r1", "sub! 1, r1, r2"]) {
+        let spent = step["gas_before"].as_u64().unwrap() - step["gas_after"].as_u64().unwrap();
+        assert_eq!(spent, cost_of(instruction) as u64);
+    }
+    assert_eq!(steps[1]["gas_before"], steps[0]["gas_after"]);
+    // the return ends the execution, so there is no state after it
+    assert_eq!(steps[2]["gas_after"], Value::Null);
+
+    // registers are r1 to r15 as they were before the step
+    for step in &steps {
+        assert_eq!(step["registers"].as_array().unwrap().len(), 15);
+        assert_eq!(step["depth"], json(1));
+        assert_eq!(step["contract_address"], json(user_address()));
+    }
+    let r1 = |step: &Value| step["registers"][0]["value"].clone();
+    assert_eq!(r1(&steps[1]), json(U256::from(5)));
+    assert_eq!(steps[1]["registers"][0]["is_pointer"], json(false));
+    assert_eq!(
+        steps[2]["registers"][1]["value"],
+        json(U256::one().overflowing_sub(U256::from(5)).0)
+    );
+
+    // 1 - 5 underflows
+    assert_eq!(steps[1]["flags"]["lt_of"], json(false));
+    assert_eq!(steps[2]["flags"]["lt_of"], json(true));
+    let pcs: Vec<_> = steps
+        .iter()
+        .map(|step| step["pc"].as_u64().unwrap())
+        .collect();
+    assert_eq!(pcs, [0, 1, 2]);
+    assert!(steps[0]["instruction"].as_str().unwrap().starts_with("add"));
+}
+
+fn callee_address() -> H160 {
+    H160::from_low_u64_be(0x100000)
+}
+
+fn inner_callee_address() -> H160 {
+    H160::from_low_u64_be(0x100001)
+}
+
+// Calls `callee_address() + {offset}` passing 10000 ergs, panicking if the call fails
+const CALLER: &str = "
+        add 10000, r0, r1
+        shl.s 192, r1, r1
+        add 1, r0, r2
+        shl.s 20, r2, r2
+        add {offset}, r2, r2
+        far_call r1, r2, @handler
+        ret.ok r0
+    handler:
+        ret.panic
+";
+
+fn call_tree(callee: &str, inner_callee: &str) -> Vec<Call> {
+    let (mut vm, mut storage) = Fixture::new(&CALLER.replace("{offset}", "0"))
+        .with_contract(callee_address(), callee)
+        .with_contract(inner_callee_address(), inner_callee)
+        .build();
+    let mut tracer = CallTracer::new();
+    vm.run(&mut tracer, EncodingMode::Production, &mut storage)
+        .unwrap();
+    tracer.calls().to_vec()
+}
+
+fn word(value: u64) -> Vec<u8> {
+    let mut bytes = [0; 32];
+    U256::from(value).to_big_endian(&mut bytes);
+    bytes.to_vec()
+}
+
+// Returns a word holding 42 from its heap
+const RETURNING_42: &str = "
+        add 42, r0, r2
+        st.1 0, r2
+        add 32, r0, r1
+        shl.s 96, r1, r1
+        ret.ok r1
+";
+
+#[test]
+fn call_tracer_nests_far_calls() {
+    let calls = call_tree(&CALLER.replace("{offset}", "1"), RETURNING_42);
+    assert_eq!(calls.len(), 1);
+    let outer = &calls[0];
+    assert_eq!(outer.call_type, CallType::Normal);
+    assert_eq!(outer.from, user_address());
+    assert_eq!(outer.to, callee_address());
+    assert_eq!(outer.outcome, CallOutcome::Ok);
+    assert!(outer.output.is_empty());
+
+    assert_eq!(outer.calls.len(), 1);
+    let inner = &outer.calls[0];
+    assert_eq!(inner.call_type, CallType::Normal);
+    assert_eq!(inner.from, callee_address());
+    assert_eq!(inner.to, inner_callee_address());
+    assert_eq!(inner.outcome, CallOutcome::Ok);
+    assert_eq!(inner.output, word(42));
+    assert!(inner.calls.is_empty());
+    // what the inner call spent is part of the outer call
+    assert!(0 < inner.gas_used && inner.gas_used < outer.gas_used);
+    assert!(outer.gas_used <= outer.gas);
+}
+
+#[test]
+fn call_tracer_records_reverts_with_their_data() {
+    let calls = call_tree(&RETURNING_42.replace("ret.ok", "ret.revert"), "ret.ok r0");
+    assert_eq!(calls.len(), 1);
+    assert_eq!(calls[0].outcome, CallOutcome::Revert);
+    assert_eq!(calls[0].output, word(42));
+    assert!(calls[0].calls.is_empty());
+}
+
+#[test]
+fn call_tracer_closes_panicked_frames() {
+    let calls = call_tree(
+        "
+        near_call r0, @inner, @handler
+        ret.ok r0
+    inner:
+        ret.panic
+    handler:
+        ret.panic
+        ",
+        "ret.ok r0",
+    );
+    assert_eq!(calls.len(), 1);
+    let far = &calls[0];
+    assert_eq!(far.to, callee_address());
+    assert_eq!(far.outcome, CallOutcome::Panic);
+    assert!(far.output.is_empty());
+
+    assert_eq!(far.calls.len(), 1);
+    assert_eq!(far.calls[0].call_type, CallType::Near);
+    assert_eq!(far.calls[0].to, callee_address());
+    assert_eq!(far.calls[0].outcome, CallOutcome::Panic);
+}
+
+#[test]
+fn call_tracer_unwinds_recursive_near_calls() {
+    // recursing until the gas runs out panics every frame on the way back
+    let calls = call_tree(
+        "
+        near_call r0, @inner, @handler
+        ret.ok r0
+    inner:
+        near_call r0, @inner, @handler
+    handler:
+        ret.panic
+        ",
+        "ret.ok r0",
+    );
+    assert_eq!(calls.len(), 1);
+    let far = &calls[0];
+    assert_eq!(far.outcome, CallOutcome::Panic);
+    // every near frame was closed inside the one that made it
+    let mut nested = 0;
+    let mut calls = &far.calls;
+    while let Some(near) = calls.first() {
+        assert_eq!(calls.len(), 1);
+        assert_eq!(near.call_type, CallType::Near);
+        assert_eq!(near.outcome, CallOutcome::Panic);
+        nested += 1;
+        calls = &near.calls;
+    }
+    assert!(nested > 1);
+}
+
+// Grows its heap, makes a near call and then a far call to `RETURNING_42`
+fn profiled_program() -> Fixture {
+    Fixture::new(
+        "
+        add 7, r0, r1
+        st.1 1000, r1
+        near_call r0, @inner, @handler
+        add 10000, r0, r1
+        shl.s 192, r1, r1
+        add 1, r0, r2
+        shl.s 20, r2, r2
+        far_call r1, r2, @handler
+        ret.ok r0
+    inner:
+        add 1, r0, r4
+        ret.ok r0
+    handler:
+        ret.panic
+        ",
+    )
+    .with_contract(callee_address(), RETURNING_42)
+}
+
+#[test]
+fn gas_profiler_accounts_for_the_ergs_spent() {
+    let (mut vm, mut storage) = profiled_program().build();
+    let mut profiler = GasProfilerTracer::new();
+    let output = vm
+        .run(&mut profiler, EncodingMode::Production, &mut storage)
+        .unwrap();
+    assert_eq!(output, ExecutionOutput::Ok(vec![]));
+
+    // the final return ends the execution before it can be attributed
+    let spent = profiled_program().run().gas_used() as u64;
+    assert_eq!(profiler.total.refunds, 0);
+    assert_eq!(profiler.total.ergs, spent);
+
+    let by_location: u64 = profiler.by_location.values().map(|usage| usage.ergs).sum();
+    let by_opcode: u64 = profiler.by_opcode.values().map(|usage| usage.ergs).sum();
+    assert_eq!(by_location, spent);
+    assert_eq!(by_opcode, spent);
+    let far_call_opcode = format!("{:?}", Variant::FarCall(FarCallOpcode::Normal));
+    assert_eq!(profiler.by_opcode[&far_call_opcode].count, 1);
+
+    // growing the heap is attributed to the store that did it
+    let store = profiler.by_location[&(user_address(), 1)];
+    assert!(store.memory_growth > 0);
+    assert_eq!(
+        store.ergs,
+        store.memory_growth + cost_of("st.1 1000, r1") as u64
+    );
+
+    // as is loading the callee's code, the gas passed to it is not
+    let far_call = profiler.by_location[&(user_address(), 7)];
+    let code_words = assemble(
+        RETURNING_42,
+        EncodingMode::Production,
+        ProtocolVersion::default(),
+    )
+    .unwrap()
+    .len() as u64;
+    assert_eq!(
+        far_call.decommit,
+        code_words * ERGS_PER_CODE_WORD_DECOMMITTMENT as u64
+    );
+    assert_eq!(
+        far_call.ergs,
+        far_call.decommit + cost_of("far_call r1, r2, 0") as u64
+    );
+    let near_call = profiler.by_location[&(user_address(), 2)];
+    assert_eq!(near_call.ergs, cost_of("near_call r0, 0, 0") as u64);
+}
+
+#[test]
+fn gas_profiler_folds_stacks_by_code_address() {
+    let (mut vm, mut storage) = profiled_program().build();
+    let mut profiler = GasProfilerTracer::new();
+    vm.run(&mut profiler, EncodingMode::Production, &mut storage)
+        .unwrap();
+
+    let mut folded = vec![];
+    profiler.write_folded_stacks(&mut folded).unwrap();
+    let stacks: Vec<(String, u64)> = String::from_utf8(folded)
+        .unwrap()
+        .lines()
+        .map(|line| {
+            let (stack, ergs) = line.rsplit_once(' ').unwrap();
+            (stack.to_string(), ergs.parse().unwrap())
+        })
+        .collect();
+    assert_eq!(
+        stacks.iter().map(|(_, ergs)| ergs).sum::<u64>(),
+        profiler.total.ergs
+    );
+
+    // the callee's instructions are nested under the caller
+    let callee_stack = format!("{:?};{:?};", user_address(), callee_address());
+    let in_callee: u64 = stacks
+        .iter()
+        .filter(|(stack, _)| stack.starts_with(&callee_stack))
+        .map(|(_, ergs)| ergs)
+        .sum();
+    let at_callee: u64 = profiler
+        .by_location
+        .iter()
+        .filter(|((address, _), _)| *address == callee_address())
+        .map(|(_, usage)| usage.ergs)
+        .sum();
+    assert!(in_callee > 0);
+    assert_eq!(in_callee, at_callee);
+    assert!(stacks
+        .iter()
+        .all(|(stack, _)| stack.starts_with(&format!("{:?};", user_address()))));
+}
@@ -0,0 +1,168 @@
+//! Differential fuzzing of the VM against the reference model in `reference.rs`.
+//!
+//! Random straight-line programs are run from random registers, flags, heap and calldata,
+//! one instruction at a time in both, and the first step after which registers, flags,
+//! gas, heaps or transient storage differ is reported together with the program.
+//! Far calls and storage are out of the model's scope.
+//!
+//! More cases can be run with e.g. `PROPTEST_CASES=100000 cargo test --test differential`.
+
+mod program;
+mod reference;
+
+use std::collections::{BTreeMap, HashMap};
+
+use era_vm::{
+    assembler::assemble,
+    config::ProtocolVersion,
+    debugger::{DebugStop, Debugger},
+    execution::Heap,
+    store::InitialStorageMemory,
+    vm::{EncodingMode, ExecutionOutput},
+    EraVM, Execution,
+};
+use proptest::prelude::*;
+use u256::{H160, U256};
+
+use program::{initial_state, program, InitialState, Program};
+use reference::{Reference, HEAP_PAGE};
+
+/// Forward near calls can still revisit code exponentially often, runs are cut off here
+const MAX_STEPS: usize = 5_000;
+
+fn build_vm(program: &Program, initial: &InitialState) -> EraVM {
+    let code = assemble(
+        &program.to_assembly(),
+        EncodingMode::Production,
+        ProtocolVersion::default(),
+    )
+    .unwrap_or_else(|err| panic!("{err}\n{program}"));
+    let mut execution = Execution::new(
+        code,
+        initial.calldata.clone(),
+        H160::from_low_u64_be(0x10000),
+        H160::from_low_u64_be(0x20000),
+        0,
+        [0; 32],
+        [0; 32],
+        0,
+        false,
+        initial.gas,
+    );
+    for (index, value) in initial.registers.iter().enumerate() {
+        execution.set_register(index as u8 + 1, *value);
+    }
+    (execution.flag_lt_of, execution.flag_gt, execution.flag_eq) = initial.flags;
+    execution
+        .heaps
+        .get_mut(HEAP_PAGE)
+        .unwrap()
+        .replace(Heap::new(initial.heap.clone(), initial.heap.len() as u32));
+    EraVM::new(execution)
+}
+
+/// Describes the first difference between the two after a step, if any.
+fn compare(
+    reference: &Reference,
+    vm: &EraVM,
+    output: &Option<ExecutionOutput>,
+) -> Result<(), String> {
+    fn check<T: PartialEq + std::fmt::Debug>(
+        what: &str,
+        expected: T,
+        found: T,
+    ) -> Result<(), String> {
+        if expected == found {
+            Ok(())
+        } else {
+            Err(format!(
+                "{what}: reference has {expected:?}, vm has {found:?}"
+            ))
+        }
+    }
+
+    check("output", &reference.output, output)?;
+    if output.is_some() {
+        return Ok(());
+    }
+
+    let execution = &vm.execution;
+    let frame = execution.current_frame().map_err(|err| err.to_string())?;
+    check(
+        "frame depth",
+        reference.frames.len(),
+        execution.frame_depth(),
+    )?;
+    check("pc", reference.frame().pc as u64, frame.pc)?;
+    check("gas", reference.frame().gas, frame.gas_left.0)?;
+    for index in 1..16 {
+        check(
+            &format!("r{index}"),
+            reference.registers[index as usize],
+            execution.get_register(index),
+        )?;
+    }
+    check(
+        "flags (lt_of, gt, eq)",
+        (reference.lt_of, reference.gt, reference.eq),
+        (execution.flag_lt_of, execution.flag_gt, execution.flag_eq),
+    )?;
+
+    for (page, expected) in reference.pages.iter().enumerate() {
+        let heap = execution.heaps.get(page as u32).unwrap();
+        check(
+            &format!("bound of page {page}"),
+            expected.bound as usize,
+            heap.len(),
+        )?;
+    }
+    for &(page, address) in &reference.written {
+        let expected = reference.pages[page as usize].bytes[address as usize..][..32].to_vec();
+        let found: Vec<u8> = (address..address + 32)
+            .map(|at| execution.heaps.get(page).unwrap().read_byte(at))
+            .collect();
+        check(&format!("page {page} at {address}"), expected, found)?;
+    }
+
+    let transient_storage: BTreeMap<U256, U256> = vm
+        .state
+        .transient_storage()
+        .iter()
+        .map(|(key, value)| (key.key, *value))
+        .collect();
+    check(
+        "transient storage",
+        &reference.transient_storage,
+        &transient_storage,
+    )
+}
+
+fn differential(program: Program, initial: InitialState) -> Result<(), String> {
+    let mut storage = InitialStorageMemory::new(HashMap::new(), HashMap::new());
+    let mut debugger = Debugger::new(build_vm(&program, &initial), EncodingMode::Production);
+    let mut reference = Reference::new(program.clone(), &initial);
+
+    for step in 0..MAX_STEPS {
+        let Some(instruction) = reference.next_instruction().cloned() else {
+            break;
+        };
+        let pc = reference.frame().pc;
+        reference.step();
+        let output = match debugger.step(&mut storage) {
+            Ok(DebugStop::Output(output)) => Some(output),
+            Ok(_) => None,
+            Err(_) => Some(ExecutionOutput::Panic),
+        };
+        compare(&reference, debugger.vm(), &output).map_err(|difference| {
+            format!("after step {step}, `{instruction}` at pc {pc}: {difference}\n\n{program}")
+        })?;
+    }
+    Ok(())
+}
+
+proptest! {
+    #[test]
+    fn vm_matches_reference(program in program(), initial in initial_state()) {
+        differential(program, initial).map_err(TestCaseError::fail)?;
+    }
+}
@@ -0,0 +1,400 @@
+use std::fmt;
+
+use era_vm::value::{FatPointer, TaggedValue};
+use proptest::prelude::*;
+use u256::U256;
+
+/// Longest generated program, not counting the final `ret.ok r0`
+const MAX_INSTRUCTIONS: usize = 48;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Condition {
+    Always,
+    Gt,
+    Lt,
+    Eq,
+    Ge,
+    Le,
+    Ne,
+    GtOrLt,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Shl,
+    Shr,
+    Rol,
+    Ror,
+    And,
+    Or,
+    Xor,
+    PtrAdd,
+    PtrSub,
+    PtrShrink,
+    PtrPack,
+    HeapRead,
+    HeapWrite,
+    AuxHeapRead,
+    AuxHeapWrite,
+    FatPointerRead,
+    ErgsLeft,
+    TransientRead,
+    TransientWrite,
+    NearCall { target: usize, handler: usize },
+    RetOk,
+    RetRevert,
+    RetPanic,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Src {
+    Reg(u8),
+    Imm(u16),
+}
+
+/// An instruction in a form both the reference model and the assembler understand.
+/// Operands an instruction doesn't use are ignored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instruction {
+    pub op: Op,
+    pub condition: Condition,
+    /// Sets the flags for arithmetic, `.inc` for heap accesses
+    pub flag0: bool,
+    /// Swaps the two sources
+    pub swap: bool,
+    pub src0: Src,
+    pub src1: u8,
+    pub dst0: u8,
+    pub dst1: u8,
+}
+
+#[derive(Debug, Clone)]
+pub struct Program {
+    pub instructions: Vec<Instruction>,
+}
+
+/// The state the VM is put in before running the program.
+#[derive(Debug, Clone)]
+pub struct InitialState {
+    /// r1 to r15
+    pub registers: Vec<TaggedValue>,
+    /// `(lt_of, gt, eq)`
+    pub flags: (bool, bool, bool),
+    pub heap: Vec<u8>,
+    pub calldata: Vec<u8>,
+    pub gas: u32,
+}
+
+impl Op {
+    pub fn is_arithmetic(self) -> bool {
+        matches!(
+            self,
+            Op::Add
+                | Op::Sub
+                | Op::Mul
+                | Op::Div
+                | Op::Shl
+                | Op::Shr
+                | Op::Rol
+                | Op::Ror
+                | Op::And
+                | Op::Or
+                | Op::Xor
+        )
+    }
+
+    pub fn is_ptr(self) -> bool {
+        matches!(self, Op::PtrAdd | Op::PtrSub | Op::PtrShrink | Op::PtrPack)
+    }
+
+    pub fn is_heap_access(self) -> bool {
+        matches!(
+            self,
+            Op::HeapRead | Op::HeapWrite | Op::AuxHeapRead | Op::AuxHeapWrite | Op::FatPointerRead
+        )
+    }
+
+    fn mnemonic(self) -> &'static str {
+        match self {
+            Op::Add => "add",
+            Op::Sub => "sub",
+            Op::Mul => "mul",
+            Op::Div => "div",
+            Op::Shl => "shl",
+            Op::Shr => "shr",
+            Op::Rol => "rol",
+            Op::Ror => "ror",
+            Op::And => "and",
+            Op::Or => "or",
+            Op::Xor => "xor",
+            Op::PtrAdd => "ptr.add",
+            Op::PtrSub => "ptr.sub",
+            Op::PtrShrink => "ptr.shrink",
+            Op::PtrPack => "ptr.pack",
+            Op::HeapRead => "ld.1",
+            Op::HeapWrite => "st.1",
+            Op::AuxHeapRead => "ld.2",
+            Op::AuxHeapWrite => "st.2",
+            Op::FatPointerRead => "ld",
+            Op::ErgsLeft => "context.ergs_left",
+            Op::TransientRead => "tload",
+            Op::TransientWrite => "tstore",
+            Op::NearCall { .. } => "near_call",
+            Op::RetOk => "ret.ok",
+            Op::RetRevert => "ret.revert",
+            Op::RetPanic => "ret.panic",
+        }
+    }
+}
+
+impl Condition {
+    fn suffix(self) -> &'static str {
+        match self {
+            Condition::Always => "",
+            Condition::Gt => ".gt",
+            Condition::Lt => ".lt",
+            Condition::Eq => ".eq",
+            Condition::Ge => ".ge",
+            Condition::Le => ".le",
+            Condition::Ne => ".ne",
+            Condition::GtOrLt => ".gtlt",
+        }
+    }
+}
+
+impl fmt::Display for Src {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Src::Reg(index) => write!(f, "r{index}"),
+            Src::Imm(value) => write!(f, "{value}"),
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = self.op;
+        write!(f, "{}", op.mnemonic())?;
+        if self.swap && (op.is_arithmetic() || op.is_ptr()) {
+            write!(f, ".s")?;
+        }
+        if self.flag0 && op.is_heap_access() {
+            write!(f, ".inc")?;
+        }
+        write!(f, "{}", self.condition.suffix())?;
+        if self.flag0 && op.is_arithmetic() {
+            write!(f, "!")?;
+        }
+
+        let (src0, src1, dst0, dst1) = (self.src0, self.src1, self.dst0, self.dst1);
+        match op {
+            Op::Mul | Op::Div => write!(f, " {src0}, r{src1}, r{dst0}, r{dst1}"),
+            _ if op.is_arithmetic() || op.is_ptr() => write!(f, " {src0}, r{src1}, r{dst0}"),
+            Op::HeapRead | Op::AuxHeapRead | Op::FatPointerRead if self.flag0 => {
+                write!(f, " {src0}, r{dst0}, r{dst1}")
+            }
+            Op::HeapRead | Op::AuxHeapRead | Op::FatPointerRead => write!(f, " {src0}, r{dst0}"),
+            Op::HeapWrite | Op::AuxHeapWrite if self.flag0 => {
+                write!(f, " {src0}, r{src1}, r{dst0}")
+            }
+            Op::HeapWrite | Op::AuxHeapWrite => write!(f, " {src0}, r{src1}"),
+            Op::ErgsLeft => write!(f, " r{dst0}"),
+            Op::TransientRead => write!(f, " {src0}, r{dst0}"),
+            Op::TransientWrite => write!(f, " {src0}, r{src1}"),
+            Op::NearCall { target, handler } => write!(f, " {src0}, @.L{target}, @.L{handler}"),
+            Op::RetOk | Op::RetRevert => write!(f, " r0"),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Program {
+    /// The program in assembly, every instruction labelled with its pc.
+    pub fn to_assembly(&self) -> String {
+        self.instructions
+            .iter()
+            .enumerate()
+            .map(|(pc, instruction)| format!(".L{pc}:\n\t{instruction}\n"))
+            .collect()
+    }
+}
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (pc, instruction) in self.instructions.iter().enumerate() {
+            writeln!(f, "{pc:>4}: {instruction}")?;
+        }
+        Ok(())
+    }
+}
+
+fn register() -> impl Strategy<Value = u8> {
+    0..16u8
+}
+
+fn immediate() -> impl Strategy<Value = u16> {
+    prop_oneof![0..64u16, any::<u16>()]
+}
+
+fn condition() -> impl Strategy<Value = Condition> {
+    prop_oneof![
+        8 => Just(Condition::Always),
+        1 => Just(Condition::Gt),
+        1 => Just(Condition::Lt),
+        1 => Just(Condition::Eq),
+        1 => Just(Condition::Ge),
+        1 => Just(Condition::Le),
+        1 => Just(Condition::Ne),
+        1 => Just(Condition::GtOrLt),
+    ]
+}
+
+// Near call targets are filled in once the length of the program is known
+fn op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        4 => Just(Op::Add),
+        2 => Just(Op::Sub),
+        2 => Just(Op::Mul),
+        2 => Just(Op::Div),
+        1 => Just(Op::Shl),
+        1 => Just(Op::Shr),
+        1 => Just(Op::Rol),
+        1 => Just(Op::Ror),
+        1 => Just(Op::And),
+        1 => Just(Op::Or),
+        1 => Just(Op::Xor),
+        2 => Just(Op::PtrAdd),
+        2 => Just(Op::PtrSub),
+        2 => Just(Op::PtrShrink),
+        2 => Just(Op::PtrPack),
+        2 => Just(Op::HeapRead),
+        2 => Just(Op::HeapWrite),
+        1 => Just(Op::AuxHeapRead),
+        1 => Just(Op::AuxHeapWrite),
+        2 => Just(Op::FatPointerRead),
+        1 => Just(Op::ErgsLeft),
+        1 => Just(Op::TransientRead),
+        1 => Just(Op::TransientWrite),
+        2 => Just(Op::NearCall { target: 0, handler: 0 }),
+        1 => Just(Op::RetOk),
+        1 => Just(Op::RetRevert),
+        1 => Just(Op::RetPanic),
+    ]
+}
+
+fn instruction() -> impl Strategy<Value = (Instruction, usize, usize)> {
+    let operands = (
+        prop_oneof![3 => register().prop_map(Src::Reg), 1 => immediate().prop_map(Src::Imm)],
+        register(),
+        register(),
+        register(),
+    );
+    (
+        op(),
+        condition(),
+        any::<bool>(),
+        any::<bool>(),
+        operands,
+        any::<usize>(),
+        any::<usize>(),
+    )
+        .prop_map(
+            |(op, condition, flag0, swap, (src0, src1, dst0, dst1), target, handler)| {
+                // these only take registers as their first source
+                let src0 = match (op, src0) {
+                    (
+                        Op::TransientRead | Op::TransientWrite | Op::NearCall { .. },
+                        Src::Imm(value),
+                    ) => Src::Reg((value % 16) as u8),
+                    _ => src0,
+                };
+                let instruction = Instruction {
+                    op,
+                    condition,
+                    flag0,
+                    swap,
+                    src0,
+                    src1,
+                    dst0,
+                    dst1,
+                };
+                (instruction, target, handler)
+            },
+        )
+}
+
+/// Straight-line programs ending in `ret.ok r0`. Near calls only go forward, so every run terminates.
+pub fn program() -> impl Strategy<Value = Program> {
+    prop::collection::vec(instruction(), 1..MAX_INSTRUCTIONS).prop_map(|generated| {
+        let end = generated.len();
+        let mut instructions: Vec<_> = generated
+            .into_iter()
+            .enumerate()
+            .map(|(pc, (mut instruction, target, handler))| {
+                if let Op::NearCall { .. } = instruction.op {
+                    let forward = end - pc;
+                    instruction.op = Op::NearCall {
+                        target: pc + 1 + target % forward,
+                        handler: pc + 1 + handler % forward,
+                    };
+                }
+                instruction
+            })
+            .collect();
+        instructions.push(Instruction {
+            op: Op::RetOk,
+            condition: Condition::Always,
+            flag0: false,
+            swap: false,
+            src0: Src::Reg(0),
+            src1: 0,
+            dst0: 0,
+            dst1: 0,
+        });
+        Program { instructions }
+    })
+}
+
+fn u256() -> impl Strategy<Value = U256> {
+    any::<[u64; 4]>().prop_map(U256)
+}
+
+fn value() -> impl Strategy<Value = TaggedValue> {
+    let pointer = (0..6u32, 0..512u32, 0..512u32, 0..600u32, any::<u128>()).prop_map(
+        |(page, start, len, offset, high)| {
+            let pointer = FatPointer {
+                offset,
+                page,
+                start,
+                len,
+            };
+            TaggedValue::new_pointer((U256::from(high) << 128) | pointer.encode())
+        },
+    );
+    prop_oneof![
+        3 => (0..1u64 << 16).prop_map(|value| TaggedValue::new_raw_integer(value.into())),
+        2 => u256().prop_map(TaggedValue::new_raw_integer),
+        2 => pointer,
+    ]
+}
+
+pub fn initial_state() -> impl Strategy<Value = InitialState> {
+    (
+        prop::collection::vec(value(), 15),
+        any::<(bool, bool, bool)>(),
+        prop::collection::vec(any::<u8>(), 0..256),
+        prop::collection::vec(any::<u8>(), 0..256),
+        prop_oneof![0..2_000u32, 2_000..1u32 << 20],
+    )
+        .prop_map(|(registers, flags, heap, calldata, gas)| InitialState {
+            registers,
+            flags,
+            heap,
+            calldata,
+            gas,
+        })
+}
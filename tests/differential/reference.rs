@@ -0,0 +1,448 @@
+//! A deliberately simple interpreter for the instructions in `program.rs`.
+//! It shares no code with the VM beyond the value types and the protocol constants.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use era_vm::{
+    value::{FatPointer, TaggedValue},
+    vm::ExecutionOutput,
+};
+use u256::{U256, U512};
+use zkevm_opcode_defs::{
+    AddOpcode, BinopOpcode, ContextOpcode, DivOpcode, LogOpcode, MulOpcode, NearCallOpcode,
+    Opcode as Variant, PtrOpcode, RetOpcode, ShiftOpcode, SubOpcode, UMAOpcode,
+    MAX_OFFSET_TO_DEREF_LOW_U32, MEMORY_GROWTH_ERGS_PER_BYTE,
+};
+
+use crate::program::{Condition, InitialState, Instruction, Op, Program, Src};
+
+pub const HEAP_PAGE: u32 = 2;
+pub const AUX_HEAP_PAGE: u32 = 3;
+
+#[derive(Debug, Clone, Default)]
+pub struct Page {
+    pub bytes: Vec<u8>,
+    /// Bytes paid for so far, reads and writes below it don't cost anything
+    pub bound: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub pc: usize,
+    pub gas: u32,
+    exception_handler: usize,
+    transient_storage: BTreeMap<U256, U256>,
+}
+
+/// Something the instruction did that makes the frame panic
+struct Panic;
+
+pub struct Reference {
+    pub registers: [TaggedValue; 16],
+    pub lt_of: bool,
+    pub gt: bool,
+    pub eq: bool,
+    /// The initial frame followed by the near call frames
+    pub frames: Vec<Frame>,
+    pub pages: Vec<Page>,
+    pub transient_storage: BTreeMap<U256, U256>,
+    /// Words that were written to a heap, as `(page, address)`
+    pub written: BTreeSet<(u32, u32)>,
+    pub output: Option<ExecutionOutput>,
+    program: Program,
+}
+
+impl Reference {
+    pub fn new(program: Program, initial: &InitialState) -> Self {
+        let mut registers = [TaggedValue::default(); 16];
+        registers[1..].copy_from_slice(&initial.registers);
+        let (lt_of, gt, eq) = initial.flags;
+        let pages = vec![
+            Page::default(),
+            Page {
+                bytes: initial.calldata.clone(),
+                bound: initial.calldata.len() as u32,
+            },
+            Page {
+                bytes: initial.heap.clone(),
+                bound: initial.heap.len() as u32,
+            },
+            Page::default(),
+            // static memory
+            Page::default(),
+        ];
+        Self {
+            registers,
+            lt_of,
+            gt,
+            eq,
+            frames: vec![Frame {
+                pc: 0,
+                gas: initial.gas,
+                exception_handler: 0,
+                transient_storage: BTreeMap::new(),
+            }],
+            pages,
+            transient_storage: BTreeMap::new(),
+            written: BTreeSet::new(),
+            output: None,
+            program,
+        }
+    }
+
+    pub fn frame(&self) -> &Frame {
+        self.frames.last().unwrap()
+    }
+
+    fn frame_mut(&mut self) -> &mut Frame {
+        self.frames.last_mut().unwrap()
+    }
+
+    pub fn next_instruction(&self) -> Option<&Instruction> {
+        if self.output.is_some() {
+            return None;
+        }
+        self.program.instructions.get(self.frame().pc)
+    }
+
+    pub fn step(&mut self) {
+        let Some(instruction) = self.next_instruction().cloned() else {
+            return;
+        };
+        let price = variant(&instruction.op).ergs_price();
+        if self.charge(price).is_err() {
+            return self.panic();
+        }
+        if !self.holds(instruction.condition) {
+            self.frame_mut().pc += 1;
+            return;
+        }
+        match self.execute(&instruction) {
+            Ok(()) => {}
+            Err(Panic) => self.panic(),
+        }
+    }
+
+    fn holds(&self, condition: Condition) -> bool {
+        match condition {
+            Condition::Always => true,
+            Condition::Gt => self.gt,
+            Condition::Lt => self.lt_of,
+            Condition::Eq => self.eq,
+            Condition::Ge => self.gt || self.eq,
+            Condition::Le => self.lt_of || self.eq,
+            Condition::Ne => !self.eq,
+            Condition::GtOrLt => self.gt || self.lt_of,
+        }
+    }
+
+    fn charge(&mut self, ergs: u32) -> Result<(), Panic> {
+        let frame = self.frame_mut();
+        if ergs > frame.gas {
+            frame.gas = 0;
+            return Err(Panic);
+        }
+        frame.gas -= ergs;
+        Ok(())
+    }
+
+    fn set_flags(&mut self, lt_of: bool, gt: bool, eq: bool) {
+        self.lt_of = lt_of;
+        self.gt = gt;
+        self.eq = eq;
+    }
+
+    fn read(&self, index: u8) -> TaggedValue {
+        self.registers[index as usize]
+    }
+
+    fn write(&mut self, index: u8, value: TaggedValue) {
+        if index != 0 {
+            self.registers[index as usize] = value;
+        }
+    }
+
+    fn sources(&self, instruction: &Instruction) -> (TaggedValue, TaggedValue) {
+        let src0 = match instruction.src0 {
+            Src::Reg(index) => self.read(index),
+            Src::Imm(value) => TaggedValue::new_raw_integer(value.into()),
+        };
+        let src1 = self.read(instruction.src1);
+        if instruction.swap && (instruction.op.is_arithmetic() || instruction.op.is_ptr()) {
+            (src1, src0)
+        } else {
+            (src0, src1)
+        }
+    }
+
+    fn execute(&mut self, instruction: &Instruction) -> Result<(), Panic> {
+        let (src0, src1) = self.sources(instruction);
+        let (a, b) = (src0.value, src1.value);
+        let set_flags = instruction.flag0;
+        let integer = TaggedValue::new_raw_integer;
+
+        match instruction.op {
+            Op::Add | Op::Sub => {
+                let (result, overflow) = if instruction.op == Op::Add {
+                    a.overflowing_add(b)
+                } else {
+                    a.overflowing_sub(b)
+                };
+                if set_flags {
+                    self.set_flags(overflow, !overflow && !result.is_zero(), result.is_zero());
+                }
+                self.write(instruction.dst0, integer(result));
+            }
+            Op::Mul => {
+                let product = a.full_mul(b);
+                let low = U256::try_from(product & U512::from(U256::MAX)).unwrap();
+                let high = U256::try_from(product >> 256).unwrap();
+                if set_flags {
+                    let overflow = !high.is_zero();
+                    self.set_flags(overflow, !overflow && !low.is_zero(), low.is_zero());
+                }
+                self.write(instruction.dst0, integer(low));
+                self.write(instruction.dst1, integer(high));
+            }
+            Op::Div => {
+                let (quotient, remainder) = if b.is_zero() {
+                    (U256::zero(), U256::zero())
+                } else {
+                    a.div_mod(b)
+                };
+                if set_flags {
+                    if b.is_zero() {
+                        self.set_flags(true, false, true);
+                    } else {
+                        self.set_flags(false, remainder.is_zero(), quotient.is_zero());
+                    }
+                }
+                self.write(instruction.dst0, integer(quotient));
+                self.write(instruction.dst1, integer(remainder));
+            }
+            Op::Shl | Op::Shr | Op::Rol | Op::Ror | Op::And | Op::Or | Op::Xor => {
+                let shift = (b % 256).as_usize();
+                let result = match instruction.op {
+                    Op::Shl => a << shift,
+                    Op::Shr => a >> shift,
+                    Op::Rol if shift == 0 => a,
+                    Op::Rol => (a << shift) | (a >> (256 - shift)),
+                    Op::Ror if shift == 0 => a,
+                    Op::Ror => (a >> shift) | (a << (256 - shift)),
+                    Op::And => a & b,
+                    Op::Or => a | b,
+                    _ => a ^ b,
+                };
+                if set_flags {
+                    self.set_flags(false, false, result.is_zero());
+                }
+                self.write(instruction.dst0, integer(result));
+            }
+            Op::PtrAdd | Op::PtrSub | Op::PtrShrink => {
+                if !src0.is_pointer || src1.is_pointer || b > U256::from(u32::MAX) {
+                    return Err(Panic);
+                }
+                let diff = b.low_u32();
+                let mut pointer = FatPointer::decode(a);
+                let changed = match instruction.op {
+                    Op::PtrAdd => pointer.offset.checked_add(diff).map(|o| pointer.offset = o),
+                    Op::PtrSub => pointer.offset.checked_sub(diff).map(|o| pointer.offset = o),
+                    _ => pointer.len.checked_sub(diff).map(|len| pointer.len = len),
+                };
+                changed.ok_or(Panic)?;
+                let high = (a >> 128) << 128;
+                self.write(
+                    instruction.dst0,
+                    TaggedValue::new_pointer(high | pointer.encode()),
+                );
+            }
+            Op::PtrPack => {
+                let low_mask = U256::from(u128::MAX);
+                if !src0.is_pointer || src1.is_pointer || !(b & low_mask).is_zero() {
+                    return Err(Panic);
+                }
+                self.write(
+                    instruction.dst0,
+                    TaggedValue::new_pointer((a & low_mask) | b),
+                );
+            }
+            Op::HeapRead | Op::AuxHeapRead | Op::HeapWrite | Op::AuxHeapWrite => {
+                if src0.is_pointer || a > U256::from(MAX_OFFSET_TO_DEREF_LOW_U32) {
+                    return Err(Panic);
+                }
+                let address = a.low_u32();
+                let page = match instruction.op {
+                    Op::HeapRead | Op::HeapWrite => HEAP_PAGE,
+                    _ => AUX_HEAP_PAGE,
+                };
+                self.grow(page, address + 32)?;
+                if matches!(instruction.op, Op::HeapWrite | Op::AuxHeapWrite) {
+                    self.store(page, address, b);
+                    if instruction.flag0 {
+                        self.write(instruction.dst0, integer((address + 32).into()));
+                    }
+                } else {
+                    let value = self.load(page, address);
+                    self.write(instruction.dst0, integer(value));
+                    if instruction.flag0 {
+                        self.write(instruction.dst1, integer((address + 32).into()));
+                    }
+                }
+            }
+            Op::FatPointerRead => {
+                if !src0.is_pointer {
+                    return Err(Panic);
+                }
+                let mut pointer = FatPointer::decode(a);
+                if pointer.offset > u32::MAX - 32 {
+                    self.frame_mut().gas = 0;
+                    return Err(Panic);
+                }
+                let value = if pointer.offset < pointer.len {
+                    if pointer.page as usize >= self.pages.len() {
+                        return Err(Panic);
+                    }
+                    let address = pointer.start + pointer.offset;
+                    self.grow(pointer.page, address + 32)?;
+                    self.load(pointer.page, address)
+                } else {
+                    U256::zero()
+                };
+                self.write(instruction.dst0, integer(value));
+                if instruction.flag0 {
+                    pointer.offset += 32;
+                    self.write(instruction.dst1, TaggedValue::new_pointer(pointer.encode()));
+                }
+            }
+            Op::ErgsLeft => {
+                let gas = self.frame().gas;
+                self.write(instruction.dst0, integer(gas.into()));
+            }
+            Op::TransientRead => {
+                let value = self.transient_storage.get(&a).copied().unwrap_or_default();
+                self.write(instruction.dst0, integer(value));
+            }
+            Op::TransientWrite => {
+                self.transient_storage.insert(a, b);
+            }
+            Op::NearCall { target, handler } => {
+                let available = self.frame().gas;
+                let requested = a.low_u32();
+                let passed = if requested == 0 || requested > available {
+                    available
+                } else {
+                    requested
+                };
+                self.frame_mut().gas -= passed;
+                self.set_flags(false, false, false);
+                self.frames.push(Frame {
+                    pc: target,
+                    gas: passed,
+                    exception_handler: handler,
+                    transient_storage: self.transient_storage.clone(),
+                });
+                return Ok(());
+            }
+            Op::RetOk | Op::RetRevert | Op::RetPanic => {
+                let failed = instruction.op != Op::RetOk;
+                self.set_flags(instruction.op == Op::RetPanic, false, false);
+                self.ret(
+                    failed,
+                    match instruction.op {
+                        Op::RetOk => ExecutionOutput::Ok(vec![]),
+                        Op::RetRevert => ExecutionOutput::Revert(vec![]),
+                        _ => ExecutionOutput::Panic,
+                    },
+                );
+                return Ok(());
+            }
+        }
+        self.frame_mut().pc += 1;
+        Ok(())
+    }
+
+    // Like the VM, the bound is raised before the growth is paid for
+    fn grow(&mut self, page: u32, end: u32) -> Result<(), Panic> {
+        let page = &mut self.pages[page as usize];
+        if end <= page.bound {
+            return Ok(());
+        }
+        let cost = MEMORY_GROWTH_ERGS_PER_BYTE * (end - page.bound);
+        page.bound = end;
+        self.charge(cost)
+    }
+
+    fn store(&mut self, page: u32, address: u32, value: U256) {
+        let bytes = &mut self.pages[page as usize].bytes;
+        let (start, end) = (address as usize, address as usize + 32);
+        if bytes.len() < end {
+            bytes.resize(end, 0);
+        }
+        value.to_big_endian(&mut bytes[start..end]);
+        self.written.insert((page, address));
+    }
+
+    fn load(&self, page: u32, address: u32) -> U256 {
+        let bytes = &self.pages[page as usize].bytes;
+        let mut word = [0; 32];
+        for (i, byte) in word.iter_mut().enumerate() {
+            *byte = bytes.get(address as usize + i).copied().unwrap_or(0);
+        }
+        U256::from_big_endian(&word)
+    }
+
+    fn ret(&mut self, failed: bool, output: ExecutionOutput) {
+        if self.frames.len() == 1 {
+            self.output = Some(output);
+            return;
+        }
+        let callee = self.frames.pop().unwrap();
+        if failed {
+            self.transient_storage = callee.transient_storage;
+        }
+        let caller = self.frame_mut();
+        caller.gas += callee.gas;
+        if failed {
+            caller.pc = callee.exception_handler;
+        } else {
+            caller.pc += 1;
+        }
+    }
+
+    fn panic(&mut self) {
+        self.set_flags(true, false, false);
+        self.ret(true, ExecutionOutput::Panic);
+    }
+}
+
+fn variant(op: &Op) -> Variant {
+    match op {
+        Op::Add => Variant::Add(AddOpcode::Add),
+        Op::Sub => Variant::Sub(SubOpcode::Sub),
+        Op::Mul => Variant::Mul(MulOpcode::Mul),
+        Op::Div => Variant::Div(DivOpcode::Div),
+        Op::Shl => Variant::Shift(ShiftOpcode::Shl),
+        Op::Shr => Variant::Shift(ShiftOpcode::Shr),
+        Op::Rol => Variant::Shift(ShiftOpcode::Rol),
+        Op::Ror => Variant::Shift(ShiftOpcode::Ror),
+        Op::And => Variant::Binop(BinopOpcode::And),
+        Op::Or => Variant::Binop(BinopOpcode::Or),
+        Op::Xor => Variant::Binop(BinopOpcode::Xor),
+        Op::PtrAdd => Variant::Ptr(PtrOpcode::Add),
+        Op::PtrSub => Variant::Ptr(PtrOpcode::Sub),
+        Op::PtrShrink => Variant::Ptr(PtrOpcode::Shrink),
+        Op::PtrPack => Variant::Ptr(PtrOpcode::Pack),
+        Op::HeapRead => Variant::UMA(UMAOpcode::HeapRead),
+        Op::HeapWrite => Variant::UMA(UMAOpcode::HeapWrite),
+        Op::AuxHeapRead => Variant::UMA(UMAOpcode::AuxHeapRead),
+        Op::AuxHeapWrite => Variant::UMA(UMAOpcode::AuxHeapWrite),
+        Op::FatPointerRead => Variant::UMA(UMAOpcode::FatPointerRead),
+        Op::ErgsLeft => Variant::Context(ContextOpcode::ErgsLeft),
+        Op::TransientRead => Variant::Log(LogOpcode::TransientStorageRead),
+        Op::TransientWrite => Variant::Log(LogOpcode::TransientStorageWrite),
+        Op::NearCall { .. } => Variant::NearCall(NearCallOpcode::Normal),
+        Op::RetOk => Variant::Ret(RetOpcode::Ok),
+        Op::RetRevert => Variant::Ret(RetOpcode::Revert),
+        Op::RetPanic => Variant::Ret(RetOpcode::Panic),
+    }
+}